@@ -0,0 +1,11 @@
+CREATE TABLE escrows (
+    id SERIAL PRIMARY KEY,
+    loan_amount BIGINT NOT NULL,
+    loan_term VARCHAR NOT NULL,
+    purpose_of_loan TEXT NOT NULL,
+    monthly_income BIGINT NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'PENDING',
+    sender_address VARCHAR NOT NULL,
+    recipient_address VARCHAR NOT NULL,
+    locked_funds BIGINT NOT NULL DEFAULT 0
+);
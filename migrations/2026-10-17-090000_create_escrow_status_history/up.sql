@@ -0,0 +1,11 @@
+CREATE TABLE escrow_status_history (
+    id SERIAL PRIMARY KEY,
+    escrow_id INTEGER NOT NULL REFERENCES escrows (id) ON DELETE CASCADE,
+    from_status VARCHAR,
+    to_status VARCHAR NOT NULL,
+    actor VARCHAR NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX escrow_status_history_escrow_id_idx ON escrow_status_history (escrow_id, created_at);
@@ -0,0 +1,9 @@
+ALTER TABLE escrow_status_history
+    ALTER COLUMN from_status TYPE VARCHAR USING from_status::text,
+    ALTER COLUMN to_status TYPE VARCHAR USING to_status::text;
+
+ALTER TABLE escrows ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE escrows ALTER COLUMN status TYPE VARCHAR USING status::text;
+ALTER TABLE escrows ALTER COLUMN status SET DEFAULT 'PENDING';
+
+DROP TYPE escrow_status;
@@ -0,0 +1,10 @@
+CREATE TYPE escrow_status AS ENUM ('PENDING', 'FUNDED', 'RELEASED', 'CANCELLED');
+
+ALTER TABLE escrows ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE escrows
+    ALTER COLUMN status TYPE escrow_status USING UPPER(status)::escrow_status;
+ALTER TABLE escrows ALTER COLUMN status SET DEFAULT 'PENDING';
+
+ALTER TABLE escrow_status_history
+    ALTER COLUMN from_status TYPE escrow_status USING UPPER(from_status)::escrow_status,
+    ALTER COLUMN to_status TYPE escrow_status USING UPPER(to_status)::escrow_status;
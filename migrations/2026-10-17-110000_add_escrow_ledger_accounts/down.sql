@@ -0,0 +1,3 @@
+ALTER TABLE escrows
+    DROP COLUMN funding_tx_hash,
+    DROP COLUMN escrow_account_id;
@@ -0,0 +1,5 @@
+ALTER TABLE escrows
+    ADD COLUMN escrow_account_id VARCHAR,
+    ADD COLUMN funding_tx_hash VARCHAR;
+
+CREATE UNIQUE INDEX escrows_escrow_account_id_idx ON escrows (escrow_account_id);
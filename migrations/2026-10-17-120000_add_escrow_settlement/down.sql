@@ -0,0 +1,7 @@
+-- Postgres cannot drop a value from an enum, so SETTLING stays in the type.
+-- Escrows caught mid-settlement go back to FUNDED so they can be settled again.
+UPDATE escrows SET status = 'FUNDED' WHERE status = 'SETTLING';
+
+ALTER TABLE escrows
+    DROP COLUMN settlement_tx_hash,
+    DROP COLUMN settlement_target;
@@ -0,0 +1,5 @@
+ALTER TYPE escrow_status ADD VALUE IF NOT EXISTS 'SETTLING' AFTER 'FUNDED';
+
+ALTER TABLE escrows
+    ADD COLUMN settlement_target escrow_status,
+    ADD COLUMN settlement_tx_hash VARCHAR;
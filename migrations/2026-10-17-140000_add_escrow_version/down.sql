@@ -0,0 +1 @@
+ALTER TABLE escrows DROP COLUMN version;
@@ -0,0 +1 @@
+ALTER TABLE escrows ADD COLUMN version INT4 NOT NULL DEFAULT 1;
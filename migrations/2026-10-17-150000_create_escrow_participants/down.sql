@@ -0,0 +1,2 @@
+DROP TABLE escrow_participants;
+DROP TYPE participant_role;
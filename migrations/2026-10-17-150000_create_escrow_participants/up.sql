@@ -0,0 +1,14 @@
+CREATE TYPE participant_role AS ENUM ('SENDER', 'RECIPIENT', 'ARBITER');
+
+-- Binds Firebase users to an escrow. Escrows created before this table have
+-- no participants and are only visible to admins.
+CREATE TABLE escrow_participants (
+    escrow_id INT4 NOT NULL REFERENCES escrows (id) ON DELETE CASCADE,
+    role participant_role NOT NULL,
+    user_id VARCHAR(128) NOT NULL,
+    release_approved_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (escrow_id, role)
+);
+
+CREATE INDEX escrow_participants_user_id_idx ON escrow_participants (user_id);
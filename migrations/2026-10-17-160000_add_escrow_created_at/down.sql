@@ -0,0 +1,4 @@
+DROP INDEX escrows_status_idx;
+DROP INDEX escrows_loan_amount_idx;
+DROP INDEX escrows_created_at_idx;
+ALTER TABLE escrows DROP COLUMN created_at;
@@ -0,0 +1,16 @@
+ALTER TABLE escrows ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+-- Existing escrows were created when their first history entry was written
+UPDATE escrows
+SET created_at = history.first_entry
+FROM (
+    SELECT escrow_id, MIN(created_at) AS first_entry
+    FROM escrow_status_history
+    GROUP BY escrow_id
+) AS history
+WHERE history.escrow_id = escrows.id;
+
+-- Keyset pagination orders by (sort column, id)
+CREATE INDEX escrows_created_at_idx ON escrows (created_at, id);
+CREATE INDEX escrows_loan_amount_idx ON escrows (loan_amount, id);
+CREATE INDEX escrows_status_idx ON escrows (status);
@@ -0,0 +1,7 @@
+DROP TRIGGER set_updated_at ON escrows;
+
+ALTER TABLE escrows
+    DROP COLUMN cancelled_at,
+    DROP COLUMN released_at,
+    DROP COLUMN funded_at,
+    DROP COLUMN updated_at;
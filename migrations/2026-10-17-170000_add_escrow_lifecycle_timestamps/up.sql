@@ -0,0 +1,26 @@
+ALTER TABLE escrows
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN funded_at TIMESTAMPTZ,
+    ADD COLUMN released_at TIMESTAMPTZ,
+    ADD COLUMN cancelled_at TIMESTAMPTZ;
+
+-- Recover the dates of existing escrows from their status history
+UPDATE escrows
+SET updated_at = COALESCE(
+        (SELECT MAX(h.created_at) FROM escrow_status_history h WHERE h.escrow_id = escrows.id),
+        escrows.created_at
+    ),
+    funded_at = (
+        SELECT MIN(h.created_at) FROM escrow_status_history h
+        WHERE h.escrow_id = escrows.id AND h.to_status = 'FUNDED'
+    ),
+    released_at = (
+        SELECT MIN(h.created_at) FROM escrow_status_history h
+        WHERE h.escrow_id = escrows.id AND h.to_status = 'RELEASED'
+    ),
+    cancelled_at = (
+        SELECT MIN(h.created_at) FROM escrow_status_history h
+        WHERE h.escrow_id = escrows.id AND h.to_status = 'CANCELLED'
+    );
+
+SELECT diesel_manage_updated_at('escrows');
@@ -0,0 +1,11 @@
+-- Postgres cannot drop a value from an enum, so EXPIRED stays in the type.
+-- Expired escrows were never funded, which makes them cancelled ones.
+UPDATE escrows SET status = 'CANCELLED', cancelled_at = expired_at WHERE status = 'EXPIRED';
+
+DROP INDEX escrows_release_deadline_idx;
+DROP INDEX escrows_funding_deadline_idx;
+
+ALTER TABLE escrows
+    DROP COLUMN expired_at,
+    DROP COLUMN release_deadline,
+    DROP COLUMN funding_deadline;
@@ -0,0 +1,12 @@
+ALTER TYPE escrow_status ADD VALUE IF NOT EXISTS 'EXPIRED' AFTER 'CANCELLED';
+
+ALTER TABLE escrows
+    ADD COLUMN funding_deadline TIMESTAMPTZ,
+    ADD COLUMN release_deadline TIMESTAMPTZ,
+    ADD COLUMN expired_at TIMESTAMPTZ;
+
+-- What the deadline scheduler scans for on every run
+CREATE INDEX escrows_funding_deadline_idx ON escrows (funding_deadline)
+    WHERE status = 'PENDING' AND funding_deadline IS NOT NULL;
+CREATE INDEX escrows_release_deadline_idx ON escrows (release_deadline)
+    WHERE status = 'FUNDED' AND release_deadline IS NOT NULL;
@@ -0,0 +1,5 @@
+DROP TABLE escrow_milestones;
+
+-- Postgres cannot drop a value from an enum, so PARTIALLY_RELEASED stays in
+-- the type. Partially released escrows still hold funds, like funded ones.
+UPDATE escrows SET status = 'FUNDED' WHERE status = 'PARTIALLY_RELEASED';
@@ -0,0 +1,16 @@
+ALTER TYPE escrow_status ADD VALUE IF NOT EXISTS 'PARTIALLY_RELEASED' AFTER 'FUNDED';
+
+-- Tranches an escrow is paid out in, in order. Their amounts add up to the
+-- loan amount. Escrows without milestones are released in one go.
+CREATE TABLE escrow_milestones (
+    escrow_id INT4 NOT NULL REFERENCES escrows (id) ON DELETE CASCADE,
+    position INT4 NOT NULL CHECK (position > 0),
+    description TEXT NOT NULL,
+    amount INT8 NOT NULL CHECK (amount > 0),
+    sender_approved_at TIMESTAMPTZ,
+    recipient_approved_at TIMESTAMPTZ,
+    released_at TIMESTAMPTZ,
+    release_tx_hash VARCHAR,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (escrow_id, position)
+);
@@ -0,0 +1,9 @@
+ALTER TABLE escrows DROP COLUMN settlement_recipient_amount;
+
+DROP TABLE escrow_dispute_evidence;
+DROP TABLE escrow_disputes;
+DROP TYPE dispute_outcome;
+
+-- Postgres cannot drop a value from an enum, so DISPUTED stays in the type.
+-- Disputed escrows still hold their funds, like funded ones.
+UPDATE escrows SET status = 'FUNDED' WHERE status = 'DISPUTED';
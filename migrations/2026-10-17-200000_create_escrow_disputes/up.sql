@@ -0,0 +1,35 @@
+ALTER TYPE escrow_status ADD VALUE IF NOT EXISTS 'DISPUTED' AFTER 'PARTIALLY_RELEASED';
+
+CREATE TYPE dispute_outcome AS ENUM ('RELEASE', 'REFUND', 'SPLIT');
+
+-- A disagreement raised by the sender or recipient. The escrow's funds are
+-- frozen while it is open, until the arbiter resolves it.
+CREATE TABLE escrow_disputes (
+    id SERIAL PRIMARY KEY,
+    escrow_id INT4 NOT NULL REFERENCES escrows (id) ON DELETE CASCADE,
+    opened_by VARCHAR(128) NOT NULL,
+    reason TEXT NOT NULL,
+    opened_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    outcome dispute_outcome,
+    recipient_amount INT8,
+    sender_amount INT8,
+    justification TEXT,
+    resolved_by VARCHAR(128),
+    resolved_at TIMESTAMPTZ
+);
+
+-- At most one open dispute per escrow
+CREATE UNIQUE INDEX escrow_disputes_open_idx ON escrow_disputes (escrow_id)
+    WHERE resolved_at IS NULL;
+
+CREATE TABLE escrow_dispute_evidence (
+    dispute_id INT4 NOT NULL REFERENCES escrow_disputes (id) ON DELETE CASCADE,
+    position INT4 NOT NULL,
+    name TEXT NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY (dispute_id, position)
+);
+
+-- While settling a split: the part of the locked funds paid to the
+-- recipient, the rest going back to the sender
+ALTER TABLE escrows ADD COLUMN settlement_recipient_amount INT8;
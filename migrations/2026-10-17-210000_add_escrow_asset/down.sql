@@ -0,0 +1,3 @@
+ALTER TABLE escrows
+    DROP COLUMN asset_issuer,
+    DROP COLUMN asset_code;
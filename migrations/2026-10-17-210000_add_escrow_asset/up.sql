@@ -0,0 +1,5 @@
+-- The Stellar asset an escrow is denominated in. Escrows created before this
+-- were all in XLM, which has no issuer.
+ALTER TABLE escrows
+    ADD COLUMN asset_code VARCHAR(12) NOT NULL DEFAULT 'XLM',
+    ADD COLUMN asset_issuer VARCHAR(56);
@@ -0,0 +1,4 @@
+DROP TABLE ledger_discrepancies;
+DROP TYPE discrepancy_kind;
+ALTER TABLE escrows DROP COLUMN deposit_tx_hash;
+DROP TABLE ledger_cursors;
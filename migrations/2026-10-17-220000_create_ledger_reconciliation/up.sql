@@ -0,0 +1,37 @@
+-- Where reconciliation stopped reading each account's payments on the
+-- ledger: a Horizon paging token.
+CREATE TABLE ledger_cursors (
+    account_id VARCHAR(56) PRIMARY KEY,
+    cursor VARCHAR(64) NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- The deposit to the treasury that funded the escrow, when it was funded by
+-- paying the treasury directly
+ALTER TABLE escrows ADD COLUMN deposit_tx_hash VARCHAR(64);
+
+CREATE TYPE discrepancy_kind AS ENUM ('BALANCE_MISMATCH', 'UNMATCHED_DEPOSIT');
+
+-- Disagreements between an escrow and the ledger, for an operator to look
+-- into. Amounts are in units of the escrow's asset.
+CREATE TABLE ledger_discrepancies (
+    id SERIAL PRIMARY KEY,
+    escrow_id INT4 NOT NULL REFERENCES escrows (id) ON DELETE CASCADE,
+    kind discrepancy_kind NOT NULL,
+    expected_amount INT8 NOT NULL,
+    actual_amount INT8 NOT NULL,
+    transaction_hash VARCHAR(64),
+    detail TEXT NOT NULL,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX ledger_discrepancies_escrow_idx ON ledger_discrepancies (escrow_id);
+
+-- A deposit is flagged once, however often its payments are read
+CREATE UNIQUE INDEX ledger_discrepancies_deposit_idx ON ledger_discrepancies (transaction_hash)
+    WHERE kind = 'UNMATCHED_DEPOSIT';
+
+-- At most one open balance mismatch per escrow
+CREATE UNIQUE INDEX ledger_discrepancies_balance_idx ON ledger_discrepancies (escrow_id)
+    WHERE kind = 'BALANCE_MISMATCH' AND resolved_at IS NULL;
@@ -0,0 +1,2 @@
+DROP TABLE escrow_deposits;
+ALTER TABLE escrows DROP COLUMN deposit_memo_id;
@@ -0,0 +1,32 @@
+-- Memo ID a deposit to the treasury must carry to credit the escrow. Random
+-- rather than sequential, so a mistyped memo is unlikely to name another
+-- escrow; kept below 2^53 so JavaScript clients read it exactly.
+ALTER TABLE escrows
+    ADD COLUMN deposit_memo_id INT8 NOT NULL
+    DEFAULT (1 + floor(random() * 9007199254740990))::INT8;
+ALTER TABLE escrows ADD CONSTRAINT escrows_deposit_memo_id_key UNIQUE (deposit_memo_id);
+
+-- Payments to the treasury carrying an escrow's memo ID. Whatever does not
+-- fund the escrow (all of an underpayment, the excess of an overpayment) is
+-- returned to the payer.
+CREATE TABLE escrow_deposits (
+    id SERIAL PRIMARY KEY,
+    escrow_id INT4 NOT NULL REFERENCES escrows (id) ON DELETE CASCADE,
+    transaction_hash VARCHAR(64) NOT NULL,
+    paging_token VARCHAR(64) NOT NULL,
+    from_address VARCHAR(56) NOT NULL,
+    amount INT8 NOT NULL,
+    credited_amount INT8 NOT NULL,
+    refund_amount INT8 NOT NULL,
+    refund_reason TEXT,
+    refund_tx_hash VARCHAR(64),
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    refunded_at TIMESTAMPTZ,
+    UNIQUE (transaction_hash, paging_token)
+);
+
+CREATE INDEX escrow_deposits_escrow_idx ON escrow_deposits (escrow_id);
+
+-- Refunds still to be paid
+CREATE INDEX escrow_deposits_refund_idx ON escrow_deposits (id)
+    WHERE refund_amount > 0 AND refunded_at IS NULL;
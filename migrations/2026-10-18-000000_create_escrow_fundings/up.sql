@@ -0,0 +1,11 @@
+-- Escrow account creation last submitted for a pending escrow. Written in
+-- its own transaction before submitting, so a retry after a lost response
+-- finds the earlier attempt instead of funding a second account. No foreign
+-- key: checking it would wait on the escrow row the funding holds locked.
+CREATE TABLE escrow_fundings (
+    escrow_id INT4 PRIMARY KEY,
+    escrow_account_id VARCHAR(56) NOT NULL,
+    amount INT8 NOT NULL,
+    tx_hash VARCHAR(64) NOT NULL,
+    submitted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,15 @@
+-- Postgres cannot drop an enum value, so the type is rebuilt without it
+DELETE FROM ledger_discrepancies WHERE kind = 'FAILED_DEPOSIT';
+DROP INDEX ledger_discrepancies_deposit_idx;
+DROP INDEX ledger_discrepancies_balance_idx;
+
+ALTER TYPE discrepancy_kind RENAME TO discrepancy_kind_old;
+CREATE TYPE discrepancy_kind AS ENUM ('BALANCE_MISMATCH', 'UNMATCHED_DEPOSIT');
+ALTER TABLE ledger_discrepancies
+    ALTER COLUMN kind TYPE discrepancy_kind USING kind::TEXT::discrepancy_kind;
+DROP TYPE discrepancy_kind_old;
+
+CREATE UNIQUE INDEX ledger_discrepancies_deposit_idx ON ledger_discrepancies (transaction_hash)
+    WHERE kind = 'UNMATCHED_DEPOSIT';
+CREATE UNIQUE INDEX ledger_discrepancies_balance_idx ON ledger_discrepancies (escrow_id)
+    WHERE kind = 'BALANCE_MISMATCH' AND resolved_at IS NULL;
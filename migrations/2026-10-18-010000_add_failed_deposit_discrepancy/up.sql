@@ -0,0 +1,3 @@
+-- A deposit reconciliation could not apply. It is skipped so later payments
+-- are still read, and left for an operator.
+ALTER TYPE discrepancy_kind ADD VALUE 'FAILED_DEPOSIT';
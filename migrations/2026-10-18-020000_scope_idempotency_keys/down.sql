@@ -0,0 +1,9 @@
+DROP INDEX idempotency_keys_created_at_idx;
+
+-- Keep one row per key, as the old primary key requires
+DELETE FROM idempotency_keys a
+    USING idempotency_keys b
+    WHERE a.idempotency_key = b.idempotency_key AND a.caller_uid > b.caller_uid;
+ALTER TABLE idempotency_keys DROP CONSTRAINT idempotency_keys_pkey;
+ALTER TABLE idempotency_keys DROP COLUMN caller_uid;
+ALTER TABLE idempotency_keys ADD PRIMARY KEY (idempotency_key);
@@ -0,0 +1,9 @@
+-- Keys are per caller, so two users picking the same key do not collide.
+-- Keys from before this change belong to no caller.
+ALTER TABLE idempotency_keys ADD COLUMN caller_uid VARCHAR(128) NOT NULL DEFAULT '';
+ALTER TABLE idempotency_keys ALTER COLUMN caller_uid DROP DEFAULT;
+ALTER TABLE idempotency_keys DROP CONSTRAINT idempotency_keys_pkey;
+ALTER TABLE idempotency_keys ADD PRIMARY KEY (caller_uid, idempotency_key);
+
+-- Expired keys are purged by age
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,3 @@
+-- Response headers replayed with the body, such as `ETag`: a JSON array of
+-- [name, value] pairs
+ALTER TABLE idempotency_keys ADD COLUMN response_headers TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX escrows_release_deadline_idx;
+CREATE INDEX escrows_release_deadline_idx ON escrows (release_deadline)
+    WHERE status = 'FUNDED' AND release_deadline IS NOT NULL;
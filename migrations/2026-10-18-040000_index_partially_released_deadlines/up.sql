@@ -0,0 +1,5 @@
+-- The deadline scheduler also refunds partially released escrows past their
+-- release deadline
+DROP INDEX escrows_release_deadline_idx;
+CREATE INDEX escrows_release_deadline_idx ON escrows (release_deadline)
+    WHERE status IN ('FUNDED', 'PARTIALLY_RELEASED') AND release_deadline IS NOT NULL;
@@ -1,36 +1,430 @@
-use dotenvy::dotenv;
-use serde::{Deserialize, Serialize};
-use std::env;
+use crate::errors::config::{ConfigError, ConfigProblem};
+use crate::services::signing::RequestVerifier;
+use crate::services::stellar::SupportedAsset;
+use crate::telemetry::LogFormat;
+use figment::providers::{Env, Format, Serialized, Toml};
+use figment::{Figment, Provider};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use stellar_base::KeyPair;
 
-#[derive(Serialize, Deserialize)]
+/// Looked for in the working directory and its parents when no
+/// `--config` file is given.
+pub const DEFAULT_CONFIG_FILE: &str = "trustbridge.toml";
+
+/// Every setting, named as in the config file. Each can also be set by the
+/// upper-case environment variable of the same name, e.g. `DATABASE_URL`.
+pub const CONFIG_KEYS: &[&str] = &[
+    "listen_addr",
+    "log_format",
+    "database_url",
+    "database_max_connections",
+    "run_migrations_on_startup",
+    "deadline_scheduler_enabled",
+    "deadline_check_interval_secs",
+    "ledger_reconciler_enabled",
+    "reconcile_interval_secs",
+    "firebase_auth_enabled",
+    "firebase_project_id",
+    "firebase_private_key",
+    "firebase_client_email",
+    "api_secret_key",
+    "api_key_roles",
+    "stellar_network",
+    "stellar_horizon_url",
+    "stellar_escrow_public_key",
+    "stellar_escrow_secret_key",
+    "stellar_supported_assets",
+];
+
+/// Settings that are masked by `config check`.
+const SECRET_KEYS: &[&str] = &[
+    "firebase_private_key",
+    "api_secret_key",
+    "stellar_escrow_secret_key",
+];
+
+const MASK: &str = "********";
+
+/// The service configuration.
+///
+/// Built from, in increasing precedence: built-in defaults, the TOML config
+/// file, environment variables and command-line flags. Its `Debug` output
+/// masks secrets like `masked` does.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub firebase_project_id: String,
-    pub firebase_private_key: String,
-    pub firebase_client_email: String,
-    pub api_secret_key: String,
+    pub listen_addr: SocketAddr,
+    pub log_format: LogFormat,
+    pub database_url: String,
+    pub database_max_connections: u32,
+    pub run_migrations_on_startup: bool,
+    /// Expire and refund escrows whose deadlines pass. Turn off on servers
+    /// that should only answer requests.
+    pub deadline_scheduler_enabled: bool,
+    /// How often the deadline scheduler runs.
+    pub deadline_check_interval_secs: u64,
+    /// Fund escrows from deposits to the treasury and check escrow account
+    /// balances against the ledger.
+    pub ledger_reconciler_enabled: bool,
+    /// How often the reconciler reads the ledger.
+    pub reconcile_interval_secs: u64,
+    /// Accept Firebase ID tokens. When off, only signed service requests
+    /// are authenticated and the Firebase settings are not needed.
+    pub firebase_auth_enabled: bool,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub firebase_project_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub firebase_private_key: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub firebase_client_email: Option<String>,
+    /// Keys for signed service requests; see `RequestVerifier::from_config`.
+    /// Service requests are refused when unset.
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub api_secret_key: Option<String>,
+    /// Roles of the service request keys, as `key_id:role` pairs. Keys left
+    /// out get the least privileged role.
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub api_key_roles: Option<String>,
+    /// `testnet` or `mainnet`.
     pub stellar_network: String,
     pub stellar_horizon_url: String,
-    pub stellar_escrow_public_key: String,
+    /// Checked against the secret key when set.
+    pub stellar_escrow_public_key: Option<String>,
+    pub stellar_escrow_secret_key: String,
+    /// Assets escrows may be denominated in, comma-separated: `XLM` or
+    /// `CODE:ISSUER[:DECIMALS]`.
+    pub stellar_supported_assets: String,
 }
 
-impl Config {
-    pub fn from_env() -> Self {
-        dotenv().ok();
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Destructured so that a new setting cannot be left out unnoticed
+        let Config {
+            listen_addr,
+            log_format,
+            database_url,
+            database_max_connections,
+            run_migrations_on_startup,
+            deadline_scheduler_enabled,
+            deadline_check_interval_secs,
+            ledger_reconciler_enabled,
+            reconcile_interval_secs,
+            firebase_auth_enabled,
+            firebase_project_id,
+            firebase_private_key,
+            firebase_client_email,
+            api_secret_key,
+            api_key_roles,
+            stellar_network,
+            stellar_horizon_url,
+            stellar_escrow_public_key,
+            stellar_escrow_secret_key: _,
+            stellar_supported_assets,
+        } = self;
+        let masked = |secret: &Option<String>| secret.as_ref().map(|_| MASK);
+
+        f.debug_struct("Config")
+            .field("listen_addr", listen_addr)
+            .field("log_format", log_format)
+            .field("database_url", &mask_password(database_url))
+            .field("database_max_connections", database_max_connections)
+            .field("run_migrations_on_startup", run_migrations_on_startup)
+            .field("deadline_scheduler_enabled", deadline_scheduler_enabled)
+            .field("deadline_check_interval_secs", deadline_check_interval_secs)
+            .field("ledger_reconciler_enabled", ledger_reconciler_enabled)
+            .field("reconcile_interval_secs", reconcile_interval_secs)
+            .field("firebase_auth_enabled", firebase_auth_enabled)
+            .field("firebase_project_id", firebase_project_id)
+            .field("firebase_private_key", &masked(firebase_private_key))
+            .field("firebase_client_email", firebase_client_email)
+            .field("api_secret_key", &masked(api_secret_key))
+            .field("api_key_roles", api_key_roles)
+            .field("stellar_network", stellar_network)
+            .field("stellar_horizon_url", stellar_horizon_url)
+            .field("stellar_escrow_public_key", stellar_escrow_public_key)
+            .field("stellar_escrow_secret_key", &MASK)
+            .field("stellar_supported_assets", stellar_supported_assets)
+            .finish()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
         Self {
-            firebase_project_id: env::var("FIREBASE_PROJECT_ID")
-                .expect("Missing FIREBASE_PROJECT_ID"),
-            firebase_private_key: env::var("FIREBASE_PRIVATE_KEY")
-                .expect("Missing FIREBASE_PRIVATE_KEY"),
-            firebase_client_email: env::var("FIREBASE_CLIENT_EMAIL")
-                .expect("Missing FIREBASE_CLIENT_EMAIL"),
-            api_secret_key: env::var("API_SECRET_KEY")
-                .expect("Missing API_SECRET_KEY"),
-            stellar_network: env::var("STELLAR_NETWORK")
-                .unwrap_or_else(|_| "testnet".to_string()),
-            stellar_horizon_url: env::var("STELLAR_HORIZON_URL")
-                .unwrap_or_else(|_| "https://horizon-testnet.stellar.org".to_string()),
-            stellar_escrow_public_key: env::var("STELLAR_ESCROW_PUBLIC_KEY")
-                .expect("Missing STELLAR_ESCROW_PUBLIC_KEY"),
-        }
-    }
-}
\ No newline at end of file
+            listen_addr: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            log_format: LogFormat::Json,
+            database_url: String::new(),
+            database_max_connections: 10,
+            run_migrations_on_startup: true,
+            deadline_scheduler_enabled: true,
+            deadline_check_interval_secs: 60,
+            ledger_reconciler_enabled: true,
+            reconcile_interval_secs: 30,
+            firebase_auth_enabled: true,
+            firebase_project_id: None,
+            firebase_private_key: None,
+            firebase_client_email: None,
+            api_secret_key: None,
+            api_key_roles: None,
+            stellar_network: "testnet".to_string(),
+            stellar_horizon_url: "https://horizon-testnet.stellar.org".to_string(),
+            stellar_escrow_public_key: None,
+            stellar_escrow_secret_key: String::new(),
+            stellar_supported_assets: "XLM".to_string(),
+        }
+    }
+}
+
+/// Settings that may be given on the command line. Secrets are left out on
+/// purpose: flags show up in process listings.
+#[derive(Debug, Default, Serialize, clap::Args)]
+pub struct ConfigOverrides {
+    /// Address to listen on, e.g. 127.0.0.1:3000
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen_addr: Option<SocketAddr>,
+
+    #[arg(long, global = true, value_enum)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_format: Option<LogFormat>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database_max_connections: Option<u32>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_migrations_on_startup: Option<bool>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline_scheduler_enabled: Option<bool>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ledger_reconciler_enabled: Option<bool>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firebase_auth_enabled: Option<bool>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stellar_network: Option<String>,
+
+    #[arg(long, global = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stellar_horizon_url: Option<String>,
+}
+
+impl Config {
+    /// Loads `.env`, then layers the config file, the environment and
+    /// `overrides` over the defaults. A `file` that was asked for must exist.
+    pub fn load(file: Option<&Path>, overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        dotenvy::dotenv().ok();
+
+        let file = match file {
+            Some(path) if !path.is_file() => {
+                return Err(ConfigError {
+                    problems: vec![ConfigProblem::new(
+                        "config",
+                        format!("Config file {} does not exist", path.display()),
+                    )],
+                })
+            }
+            Some(path) => Toml::file_exact(path),
+            None => Toml::file(DEFAULT_CONFIG_FILE),
+        };
+
+        Self::layered(file, Env::raw().only(CONFIG_KEYS), overrides)
+    }
+
+    /// Merges the layers over the defaults and validates the result.
+    pub fn layered(
+        file: impl Provider,
+        env: impl Provider,
+        overrides: &ConfigOverrides,
+    ) -> Result<Self, ConfigError> {
+        let config: Config = Figment::from(Serialized::defaults(Config::default()))
+            .merge(file)
+            .merge(env)
+            .merge(Serialized::defaults(overrides))
+            .extract()?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks every setting and reports all problems together.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut require = |key: &str, value: Option<&str>| {
+            if value.is_none_or(|value| value.trim().is_empty()) {
+                problems.push(ConfigProblem::new(key, "must be set"));
+            }
+        };
+
+        require("database_url", Some(&self.database_url));
+        require(
+            "stellar_escrow_secret_key",
+            Some(&self.stellar_escrow_secret_key),
+        );
+        if self.firebase_auth_enabled {
+            require("firebase_project_id", self.firebase_project_id.as_deref());
+        }
+
+        if !self.database_url.is_empty()
+            && !self.database_url.starts_with("postgres://")
+            && !self.database_url.starts_with("postgresql://")
+        {
+            problems.push(ConfigProblem::new(
+                "database_url",
+                "must be a postgres:// connection string",
+            ));
+        }
+        if self.database_max_connections == 0 {
+            problems.push(ConfigProblem::new(
+                "database_max_connections",
+                "must be at least 1",
+            ));
+        }
+        if self.deadline_check_interval_secs == 0 {
+            problems.push(ConfigProblem::new(
+                "deadline_check_interval_secs",
+                "must be at least 1",
+            ));
+        }
+        if self.reconcile_interval_secs == 0 {
+            problems.push(ConfigProblem::new(
+                "reconcile_interval_secs",
+                "must be at least 1",
+            ));
+        }
+
+        match &self.api_secret_key {
+            Some(api_secret_key) => {
+                if let Err(err) =
+                    RequestVerifier::from_config(api_secret_key, self.api_key_roles.as_deref())
+                {
+                    let key = if err.starts_with("API_KEY_ROLES") {
+                        "api_key_roles"
+                    } else {
+                        "api_secret_key"
+                    };
+                    problems.push(ConfigProblem::new(key, err));
+                }
+            }
+            None if self.api_key_roles.is_some() => problems.push(ConfigProblem::new(
+                "api_key_roles",
+                "names keys, but api_secret_key is not set",
+            )),
+            None => {}
+        }
+
+        if !matches!(self.stellar_network.as_str(), "testnet" | "mainnet") {
+            problems.push(ConfigProblem::new(
+                "stellar_network",
+                format!(
+                    "must be 'testnet' or 'mainnet', not '{}'",
+                    self.stellar_network
+                ),
+            ));
+        }
+        if !self.stellar_horizon_url.starts_with("http://")
+            && !self.stellar_horizon_url.starts_with("https://")
+        {
+            problems.push(ConfigProblem::new(
+                "stellar_horizon_url",
+                "must be an http(s) URL",
+            ));
+        }
+
+        if !self.stellar_escrow_secret_key.is_empty() {
+            match KeyPair::from_secret_seed(&self.stellar_escrow_secret_key) {
+                Err(_) => problems.push(ConfigProblem::new(
+                    "stellar_escrow_secret_key",
+                    "is not a valid Stellar secret seed",
+                )),
+                Ok(keypair) => {
+                    let derived = keypair.public_key().account_id();
+                    if let Some(public_key) = &self.stellar_escrow_public_key {
+                        if *public_key != derived {
+                            problems.push(ConfigProblem::new(
+                                "stellar_escrow_public_key",
+                                format!("does not match the secret key, which is for {}", derived),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = SupportedAsset::parse_list(&self.stellar_supported_assets) {
+            problems.push(ConfigProblem::new("stellar_supported_assets", err));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    /// The configuration with secrets and the database password masked, for
+    /// display.
+    pub fn masked(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("Config serializes to JSON");
+        let settings = value.as_object_mut().expect("Config is a JSON object");
+
+        for key in SECRET_KEYS {
+            if let Some(setting) = settings.get_mut(*key).filter(|setting| !setting.is_null()) {
+                *setting = serde_json::Value::from(MASK);
+            }
+        }
+        settings.insert(
+            "database_url".to_string(),
+            mask_password(&self.database_url).into(),
+        );
+
+        value
+    }
+}
+
+/// Environment values that look like numbers or booleans arrive parsed;
+/// free-form settings such as secrets take them back as text.
+fn lenient_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        Text(String),
+        Integer(i64),
+        Unsigned(u64),
+        Float(f64),
+        Bool(bool),
+    }
+
+    Ok(
+        Option::<Scalar>::deserialize(deserializer)?.map(|scalar| match scalar {
+            Scalar::Text(text) => text,
+            Scalar::Integer(number) => number.to_string(),
+            Scalar::Unsigned(number) => number.to_string(),
+            Scalar::Float(number) => number.to_string(),
+            Scalar::Bool(flag) => flag.to_string(),
+        }),
+    )
+}
+
+fn mask_password(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let Some((credentials, host)) = rest.rsplit_once('@') else {
+        return url.to_string();
+    };
+    match credentials.split_once(':') {
+        Some((user, _)) => format!("{}://{}:{}@{}", scheme, user, MASK, host),
+        None => url.to_string(),
+    }
+}
@@ -0,0 +1,36 @@
+use diesel::pg::Pg;
+use diesel::r2d2::{self, ConnectionManager};
+use diesel::PgConnection;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Failure raised by the migration harness.
+pub type MigrationError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The SQL migrations under `migrations/`, compiled into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+pub fn init_pool(database_url: &str, max_connections: u32) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    r2d2::Pool::builder()
+        .max_size(max_connections)
+        .build(manager)
+        .expect("Failed to create pool.")
+}
+
+/// Applies every migration that has not run yet and returns their versions.
+pub fn run_pending_migrations(
+    conn: &mut impl MigrationHarness<Pg>,
+) -> Result<Vec<String>, MigrationError> {
+    let applied = conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(applied.iter().map(ToString::to_string).collect())
+}
+
+/// Reverts the most recently applied migration and returns its version.
+pub fn revert_last_migration(
+    conn: &mut impl MigrationHarness<Pg>,
+) -> Result<String, MigrationError> {
+    let reverted = conn.revert_last_migration(MIGRATIONS)?;
+    Ok(reverted.to_string())
+}
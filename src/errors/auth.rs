@@ -0,0 +1,79 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::errors::escrow::{ErrorBody, ErrorDetail};
+
+/// Why a request could not be authenticated.
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Missing bearer token")]
+    MissingToken,
+
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    #[error("Invalid request signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Request body is larger than {0} bytes")]
+    PayloadTooLarge(usize),
+
+    #[error("Signing keys unavailable: {0}")]
+    KeysUnavailable(String),
+}
+
+impl AuthError {
+    pub fn invalid(reason: impl std::fmt::Display) -> Self {
+        AuthError::InvalidToken(reason.to_string())
+    }
+
+    pub fn invalid_signature(reason: impl std::fmt::Display) -> Self {
+        AuthError::InvalidSignature(reason.to_string())
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingToken
+            | AuthError::InvalidToken(_)
+            | AuthError::InvalidSignature(_) => "unauthenticated",
+            AuthError::PayloadTooLarge(_) => "payload_too_large",
+            AuthError::KeysUnavailable(_) => "auth_unavailable",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::MissingToken
+            | AuthError::InvalidToken(_)
+            | AuthError::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            AuthError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AuthError::KeysUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+
+        let message = if status.is_server_error() {
+            tracing::error!(error = %self, "Authentication failed");
+            "Authentication is temporarily unavailable".to_string()
+        } else {
+            self.to_string()
+        };
+
+        let body = ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message,
+                field: None,
+            },
+        };
+
+        (status, [(header::WWW_AUTHENTICATE, "Bearer")], Json(body)).into_response()
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+
+/// One thing wrong with the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// The setting at fault, as named in the config file.
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Every problem found while loading the configuration, so they can all be
+/// fixed in one go.
+#[derive(Debug, thiserror::Error)]
+pub struct ConfigError {
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}: {}", problem.key, problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<figment::Error> for ConfigError {
+    fn from(err: figment::Error) -> Self {
+        let problems = err
+            .into_iter()
+            .map(|err| {
+                let key = if err.path.is_empty() {
+                    "config".to_string()
+                } else {
+                    err.path.join(".")
+                };
+                ConfigProblem::new(key, err.kind.to_string())
+            })
+            .collect();
+        ConfigError { problems }
+    }
+}
@@ -0,0 +1,174 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::models::escrow::EscrowStatus;
+use crate::models::money::AmountError;
+
+/// Every way an escrow operation can fail, grouped by who has to act on it:
+/// the client (4xx) or the operators (5xx).
+#[derive(Debug, thiserror::Error)]
+pub enum EscrowError {
+    #[error("Escrow {0} not found")]
+    NotFound(i32),
+
+    #[error("Escrow {escrow_id} has no milestone {position}")]
+    MilestoneNotFound { escrow_id: i32, position: i32 },
+
+    #[error("{message}")]
+    Validation {
+        field: &'static str,
+        message: String,
+    },
+
+    #[error("Cannot move escrow from {from} to {to}")]
+    InvalidTransition {
+        from: EscrowStatus,
+        to: EscrowStatus,
+    },
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("Request body is larger than {0} bytes")]
+    PayloadTooLarge(usize),
+
+    #[error("Escrow is at version {actual}, not {expected}")]
+    VersionMismatch { expected: i32, actual: i32 },
+
+    #[error("Ledger operation failed: {0}")]
+    Ledger(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] diesel::result::Error),
+
+    #[error("Database unavailable: {0}")]
+    Pool(#[from] r2d2::Error),
+}
+
+/// Amounts in the wrong asset or out of range are the client's to fix.
+impl From<AmountError> for EscrowError {
+    fn from(err: AmountError) -> Self {
+        let field = match err {
+            AmountError::AssetMismatch { .. } => "asset_code",
+            AmountError::Overflow => "amount",
+        };
+        EscrowError::validation(field, err.to_string())
+    }
+}
+
+impl EscrowError {
+    pub fn validation(field: &'static str, message: impl Into<String>) -> Self {
+        EscrowError::Validation {
+            field,
+            message: message.into(),
+        }
+    }
+
+    pub fn ledger(err: impl std::fmt::Debug) -> Self {
+        EscrowError::Ledger(format!("{:?}", err))
+    }
+
+    /// Whether the failure lies outside the request and may clear by
+    /// itself, as when the ledger or the database cannot be reached, so the
+    /// same work is worth trying again later.
+    pub fn is_transient(&self) -> bool {
+        use diesel::result::{DatabaseErrorKind, Error};
+
+        match self {
+            EscrowError::Ledger(_) | EscrowError::Pool(_) => true,
+            EscrowError::Database(Error::DatabaseError(kind, _)) => matches!(
+                kind,
+                DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::SerializationFailure
+            ),
+            _ => false,
+        }
+    }
+
+    /// Stable, machine-readable identifier for the error kind. Clients branch
+    /// on this value, so existing codes must never change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EscrowError::NotFound(_) => "escrow_not_found",
+            EscrowError::MilestoneNotFound { .. } => "milestone_not_found",
+            EscrowError::Validation { .. } => "validation_failed",
+            EscrowError::InvalidTransition { .. } => "invalid_state_transition",
+            EscrowError::Forbidden(_) => "forbidden",
+            EscrowError::Conflict(_) => "conflict",
+            EscrowError::PayloadTooLarge(_) => "payload_too_large",
+            EscrowError::VersionMismatch { .. } => "precondition_failed",
+            EscrowError::Ledger(_) => "ledger_error",
+            EscrowError::Database(_) => "database_error",
+            EscrowError::Pool(_) => "database_unavailable",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            EscrowError::NotFound(_) | EscrowError::MilestoneNotFound { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            EscrowError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            EscrowError::Forbidden(_) => StatusCode::FORBIDDEN,
+            EscrowError::InvalidTransition { .. } | EscrowError::Conflict(_) => {
+                StatusCode::CONFLICT
+            }
+            EscrowError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            EscrowError::VersionMismatch { .. } => StatusCode::PRECONDITION_FAILED,
+            EscrowError::Ledger(_) => StatusCode::BAD_GATEWAY,
+            EscrowError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            EscrowError::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorDetail {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<&'static str>,
+}
+
+impl IntoResponse for EscrowError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let field = match &self {
+            EscrowError::Validation { field, .. } => Some(*field),
+            _ => None,
+        };
+
+        // Infrastructure failures are logged in full but never leaked to clients.
+        let message = if status.is_server_error() {
+            tracing::error!(error = %self, "Escrow request failed");
+            match self {
+                EscrowError::Ledger(_) => "The ledger rejected or failed to process the request",
+                _ => "Internal server error",
+            }
+            .to_string()
+        } else {
+            self.to_string()
+        };
+
+        let body = ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message,
+                field,
+            },
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
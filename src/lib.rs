@@ -0,0 +1,11 @@
+pub mod config;
+pub mod db;
+pub mod errors;
+pub mod models;
+pub mod routes;
+pub mod schema;
+pub mod services;
+pub mod state;
+pub mod telemetry;
+#[cfg(test)]
+mod tests;
@@ -1,43 +1,155 @@
-use crate::config::Config;
-use axum::{routing::get, Router};
-use dotenvy::dotenv;
-use std::{env, net::SocketAddr};
-
-mod config;
-mod routes;
-mod services;
-mod models;
-mod schema;
-mod tests;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use trustbridge_backend::config::{Config, ConfigOverrides};
+use trustbridge_backend::db::{self, DbPool};
+use trustbridge_backend::routes;
+use trustbridge_backend::services::scheduler;
+use trustbridge_backend::state::AppState;
+use trustbridge_backend::telemetry::{self, Redactor};
+
+/// How often expired idempotency keys are deleted.
+const IDEMPOTENCY_KEY_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Parser)]
+#[command(about = "TrustBridge escrow backend")]
+struct Cli {
+    /// TOML config file. Defaults to trustbridge.toml, if there is one.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP server (the default).
+    Serve,
+    /// Apply all pending migrations, or revert the last one.
+    Migrate {
+        #[arg(value_enum, default_value_t = Direction::Up)]
+        direction: Direction,
+    },
+    /// Inspect the configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate the configuration and print it with secrets masked.
+    Check,
+}
 
 fn load_env() {
     dotenvy::from_filename(".env.local").ok();
+}
 
-    println!("=== Variables de entorno cargadas ===");
-    for (key, value) in env::vars() {
-        println!("{} = {}", key, value);
-    }
+fn migrate(pool: &DbPool, direction: Direction) {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let result = match direction {
+        Direction::Up => db::run_pending_migrations(&mut conn).map(|applied| {
+            if applied.is_empty() {
+                tracing::info!("No pending migrations");
+            }
+            for version in applied {
+                tracing::info!(version, "Applied migration");
+            }
+        }),
+        Direction::Down => db::revert_last_migration(&mut conn)
+            .map(|version| tracing::info!(version, "Reverted migration")),
+    };
 
-    let project_id = env::var("FIREBASE_PROJECT_ID").expect("FIREBASE_PROJECT_ID not set");
-    println!("Firebase Project ID: {}", project_id);
+    if let Err(e) = result {
+        tracing::error!(error = %e, "Migration failed");
+        process::exit(1);
+    }
 }
 
 #[tokio::main]
 async fn main() {
     load_env();
+    let cli = Cli::parse();
+
+    let config = match Config::load(cli.config.as_deref(), &cli.overrides) {
+        Ok(config) => config,
+        Err(err) => {
+            // Logging is configured by the config, so report on stderr
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&config.masked()).expect("Config serializes to JSON")
+        );
+        return;
+    }
 
-    let config = Config::from_env();
+    telemetry::init(config.log_format, Redactor::from_config(&config));
+    let pool = db::init_pool(&config.database_url, config.database_max_connections);
 
-    println!("Firebase Project ID: {}", config.firebase_project_id);
-    println!("Firebase Client Email: {}", config.firebase_client_email);
+    if let Some(Command::Migrate { direction }) = cli.command {
+        migrate(&pool, direction);
+        return;
+    }
+
+    if config.run_migrations_on_startup {
+        migrate(&pool, Direction::Up);
+    }
 
-    let app = Router::new().route("/health", get(routes::health::health_check));
+    tracing::info!(
+        stellar_network = %config.stellar_network,
+        firebase_auth_enabled = config.firebase_auth_enabled,
+        signed_requests_enabled = config.api_secret_key.is_some(),
+        "Configuration loaded"
+    );
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    println!("Running on http://{}", addr);
+    let addr = config.listen_addr;
+    let state = AppState::new(config, pool);
 
+    if state.config.deadline_scheduler_enabled {
+        scheduler::spawn_deadline_scheduler(
+            state.escrow_service.clone(),
+            Duration::from_secs(state.config.deadline_check_interval_secs),
+        );
+    }
+    if state.config.ledger_reconciler_enabled {
+        scheduler::spawn_ledger_reconciler(
+            state.escrow_service.clone(),
+            Duration::from_secs(state.config.reconcile_interval_secs),
+        );
+    }
+
+    scheduler::spawn_idempotency_key_purge(
+        state.idempotency_service.clone(),
+        IDEMPOTENCY_KEY_PURGE_INTERVAL,
+    );
+
+    let app = routes::app(state);
+
+    tracing::info!(%addr, "Listening");
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
         .await
         .unwrap();
-}
\ No newline at end of file
+}
@@ -1,47 +1,266 @@
-use crate::schema::escrows;
+use crate::errors::escrow::EscrowError;
+use crate::models::money::{AssetAmount, AssetId, Money};
+use crate::models::stellar_address::StellarAddress;
+use crate::schema::{escrows, sql_types};
+use crate::services::stellar;
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Stored as the native `escrow_status` Postgres enum.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = sql_types::EscrowStatus)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum EscrowStatus {
+    #[default]
     Pending,
     Funded,
+    /// Some milestones have been paid out; the rest are still locked.
+    PartiallyReleased,
+    /// A party disputes the escrow; its funds are frozen until the arbiter
+    /// resolves it.
+    Disputed,
+    /// A release or refund has been submitted to the ledger but not confirmed.
+    Settling,
     Released,
     Cancelled,
+    /// Never funded before its funding deadline.
+    Expired,
+}
+
+impl fmt::Display for EscrowStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl EscrowStatus {
-    pub fn to_string(&self) -> String {
+    /// The label used by the `escrow_status` database enum and the API.
+    pub fn as_str(&self) -> &'static str {
         match self {
-            EscrowStatus::Pending => "PENDING".to_string(),
-            EscrowStatus::Funded => "FUNDED".to_string(),
-            EscrowStatus::Released => "RELEASED".to_string(),
-            EscrowStatus::Cancelled => "CANCELLED".to_string(),
+            EscrowStatus::Pending => "PENDING",
+            EscrowStatus::Funded => "FUNDED",
+            EscrowStatus::PartiallyReleased => "PARTIALLY_RELEASED",
+            EscrowStatus::Disputed => "DISPUTED",
+            EscrowStatus::Settling => "SETTLING",
+            EscrowStatus::Released => "RELEASED",
+            EscrowStatus::Cancelled => "CANCELLED",
+            EscrowStatus::Expired => "EXPIRED",
         }
     }
 
-    pub fn from_string(status: &str) -> Result<Self, String> {
+    pub fn from_string(status: &str) -> Result<Self, EscrowError> {
         match status.to_uppercase().as_str() {
             "PENDING" => Ok(EscrowStatus::Pending),
             "FUNDED" => Ok(EscrowStatus::Funded),
+            "PARTIALLY_RELEASED" => Ok(EscrowStatus::PartiallyReleased),
+            "DISPUTED" => Ok(EscrowStatus::Disputed),
+            "SETTLING" => Ok(EscrowStatus::Settling),
             "RELEASED" => Ok(EscrowStatus::Released),
             "CANCELLED" => Ok(EscrowStatus::Cancelled),
-            _ => Err("Invalid status".to_string()),
+            "EXPIRED" => Ok(EscrowStatus::Expired),
+            _ => Err(EscrowError::validation(
+                "status",
+                format!("Invalid status '{}'", status),
+            )),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable)]
-#[diesel(table_name = escrows)]
+impl ToSql<sql_types::EscrowStatus, Pg> for EscrowStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::EscrowStatus, Pg> for EscrowStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"PENDING" => Ok(EscrowStatus::Pending),
+            b"FUNDED" => Ok(EscrowStatus::Funded),
+            b"PARTIALLY_RELEASED" => Ok(EscrowStatus::PartiallyReleased),
+            b"DISPUTED" => Ok(EscrowStatus::Disputed),
+            b"SETTLING" => Ok(EscrowStatus::Settling),
+            b"RELEASED" => Ok(EscrowStatus::Released),
+            b"CANCELLED" => Ok(EscrowStatus::Cancelled),
+            b"EXPIRED" => Ok(EscrowStatus::Expired),
+            other => Err(format!(
+                "Unrecognized escrow_status variant: {}",
+                String::from_utf8_lossy(other)
+            )
+            .into()),
+        }
+    }
+}
+
+/// An escrow as stored and returned by the API. Timestamps are UTC and
+/// serialize as RFC 3339.
+#[derive(Debug, Serialize, Deserialize, Queryable)]
 pub struct Escrow {
-    #[diesel(skip_insertion)]
     pub id: i32,
-    pub loan_amount: i64,
+    pub loan_amount: Money,
     pub loan_term: String,
     pub purpose_of_loan: String,
-    pub monthly_income: i64,
-    pub status: String,
-    pub sender_address: String,
-    pub recipient_address: String,
-    pub locked_funds: i64,
+    pub monthly_income: Money,
+    pub status: EscrowStatus,
+    pub sender_address: StellarAddress,
+    pub recipient_address: StellarAddress,
+    pub locked_funds: Money,
+    /// Stellar account holding the locked funds once the escrow is funded.
+    pub escrow_account_id: Option<String>,
+    pub funding_tx_hash: Option<String>,
+    /// While `Settling`: the status the escrow moves to once the ledger confirms.
+    pub settlement_target: Option<EscrowStatus>,
+    /// Last payout transaction submitted for this escrow.
+    pub settlement_tx_hash: Option<String>,
+    /// Bumped on every write; exposed to clients as the `ETag`.
+    pub version: i32,
+    pub created_at: DateTime<Utc>,
+    /// Set by the database on every write.
+    pub updated_at: DateTime<Utc>,
+    pub funded_at: Option<DateTime<Utc>>,
+    pub released_at: Option<DateTime<Utc>>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    /// A pending escrow expires if it is not funded by then.
+    pub funding_deadline: Option<DateTime<Utc>>,
+    /// A funded escrow is refunded to the sender if not released by then.
+    pub release_deadline: Option<DateTime<Utc>>,
+    pub expired_at: Option<DateTime<Utc>>,
+    /// While settling a split dispute resolution: the part of the locked
+    /// funds paid to the recipient. The rest goes back to the sender.
+    pub settlement_recipient_amount: Option<Money>,
+    /// Stellar asset of every amount on the escrow; `XLM` has no issuer.
+    pub asset_code: String,
+    pub asset_issuer: Option<String>,
+    /// The payment to the treasury that funded the escrow, if the sender
+    /// deposited directly instead of calling the lock endpoint.
+    pub deposit_tx_hash: Option<String>,
+    /// Memo ID identifying deposits to the treasury for this escrow.
+    pub deposit_memo_id: i64,
+}
+
+impl Escrow {
+    /// The asset every amount on the escrow is in.
+    pub fn asset(&self) -> AssetId {
+        AssetId::new(&self.asset_code, self.asset_issuer.as_deref())
+    }
+
+    /// `amount` of the escrow's asset.
+    pub fn amount(&self, amount: Money) -> AssetAmount {
+        AssetAmount::new(amount, self.asset())
+    }
+
+    pub fn loan(&self) -> AssetAmount {
+        self.amount(self.loan_amount)
+    }
+
+    pub fn locked(&self) -> AssetAmount {
+        self.amount(self.locked_funds)
+    }
+}
+
+/// The client-supplied part of an escrow. Status, locked funds and ledger
+/// references are always set by `EscrowService`.
+#[derive(Debug, Deserialize, Insertable)]
+#[diesel(table_name = escrows)]
+pub struct NewEscrow {
+    pub loan_amount: Money,
+    pub loan_term: String,
+    pub purpose_of_loan: String,
+    pub monthly_income: Money,
+    pub sender_address: StellarAddress,
+    pub recipient_address: StellarAddress,
+    #[serde(default)]
+    pub funding_deadline: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub release_deadline: Option<DateTime<Utc>>,
+    /// Defaults to XLM.
+    #[serde(default = "native_asset_code")]
+    pub asset_code: String,
+    #[serde(default)]
+    pub asset_issuer: Option<String>,
+}
+
+impl NewEscrow {
+    pub fn asset(&self) -> AssetId {
+        AssetId::new(&self.asset_code, self.asset_issuer.as_deref())
+    }
+}
+
+fn native_asset_code() -> String {
+    stellar::NATIVE_ASSET_CODE.to_string()
+}
+
+/// Body of `POST /escrows/:id/lock`. The asset must be the escrow's.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LockFunds {
+    pub amount: Money,
+    pub asset_code: String,
+    /// Issuing account; omitted for XLM.
+    #[serde(default)]
+    pub asset_issuer: Option<String>,
+}
+
+impl LockFunds {
+    pub fn asset_amount(&self) -> AssetAmount {
+        AssetAmount::new(
+            self.amount,
+            AssetId::new(&self.asset_code, self.asset_issuer.as_deref()),
+        )
+    }
+}
+
+/// Order of an escrow listing. Ties are broken by ID in the same direction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EscrowSort {
+    #[serde(rename = "created_at")]
+    CreatedAtAsc,
+    #[default]
+    #[serde(rename = "-created_at")]
+    CreatedAtDesc,
+    #[serde(rename = "loan_amount")]
+    LoanAmountAsc,
+    #[serde(rename = "-loan_amount")]
+    LoanAmountDesc,
+}
+
+/// Filters and paging for listing escrows, as given in the query string.
+#[derive(Debug, Default, Deserialize)]
+pub struct EscrowQuery {
+    /// Comma-separated statuses, e.g. `FUNDED,SETTLING`.
+    pub status: Option<String>,
+    /// Sender Stellar address.
+    pub sender: Option<StellarAddress>,
+    /// Recipient Stellar address.
+    pub recipient: Option<StellarAddress>,
+    pub asset_code: Option<String>,
+    pub asset_issuer: Option<String>,
+    /// Amount filters are in units of one asset, so they need `asset_code`
+    /// (and `asset_issuer` for an issued asset).
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sort: EscrowSort,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// One page of an escrow listing.
+#[derive(Debug, Serialize)]
+pub struct EscrowPage {
+    pub items: Vec<Escrow>,
+    /// Pass as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
 }
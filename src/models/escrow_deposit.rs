@@ -0,0 +1,61 @@
+use crate::models::money::Money;
+use crate::schema::escrow_deposits;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// A payment to the treasury carrying an escrow's memo ID. The escrow is
+/// funded from `credited_amount`; the rest goes back to the payer.
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct EscrowDeposit {
+    pub id: i32,
+    pub escrow_id: i32,
+    pub transaction_hash: String,
+    /// Horizon paging token of the payment, telling apart several payments
+    /// in one transaction.
+    #[serde(skip)]
+    pub paging_token: String,
+    /// Account that paid, and receives any refund.
+    pub from_address: String,
+    pub amount: Money,
+    /// Zero unless the deposit funded the escrow.
+    pub credited_amount: Money,
+    pub refund_amount: Money,
+    /// Why some or all of the deposit is returned.
+    pub refund_reason: Option<String>,
+    /// Last refund transaction submitted.
+    pub refund_tx_hash: Option<String>,
+    pub received_at: DateTime<Utc>,
+    /// Set once the refund is confirmed on the ledger.
+    pub refunded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = escrow_deposits)]
+pub struct NewEscrowDeposit<'a> {
+    pub escrow_id: i32,
+    pub transaction_hash: &'a str,
+    pub paging_token: &'a str,
+    pub from_address: &'a str,
+    pub amount: Money,
+    pub credited_amount: Money,
+    pub refund_amount: Money,
+    pub refund_reason: Option<&'a str>,
+}
+
+/// How to fund a pending escrow by paying the treasury from any wallet.
+#[derive(Debug, Clone, Serialize)]
+pub struct DepositInstructions {
+    pub escrow_id: i32,
+    /// Treasury account to pay.
+    pub destination: String,
+    pub asset_code: String,
+    pub asset_issuer: Option<String>,
+    /// Exactly what to pay. Less is returned in full, and anything more than
+    /// this is returned once the escrow is funded.
+    pub amount: Money,
+    /// Always `id`.
+    pub memo_type: &'static str,
+    /// Decimal memo ID the payment must carry.
+    pub memo: String,
+}
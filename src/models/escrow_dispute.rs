@@ -0,0 +1,115 @@
+use crate::models::money::Money;
+use crate::schema::{escrow_dispute_evidence, sql_types};
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// How the arbiter settles a dispute. Stored as the native `dispute_outcome`
+/// Postgres enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::DisputeOutcome)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DisputeOutcome {
+    /// Everything locked goes to the recipient.
+    Release,
+    /// Everything locked goes back to the sender.
+    Refund,
+    /// The recipient gets `recipient_amount`, the sender the rest.
+    Split,
+}
+
+impl DisputeOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisputeOutcome::Release => "RELEASE",
+            DisputeOutcome::Refund => "REFUND",
+            DisputeOutcome::Split => "SPLIT",
+        }
+    }
+}
+
+impl ToSql<sql_types::DisputeOutcome, Pg> for DisputeOutcome {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::DisputeOutcome, Pg> for DisputeOutcome {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"RELEASE" => Ok(DisputeOutcome::Release),
+            b"REFUND" => Ok(DisputeOutcome::Refund),
+            b"SPLIT" => Ok(DisputeOutcome::Split),
+            other => Err(format!(
+                "Unrecognized dispute_outcome variant: {}",
+                String::from_utf8_lossy(other)
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct EscrowDispute {
+    pub id: i32,
+    pub escrow_id: i32,
+    /// Firebase user ID of the party that opened it.
+    pub opened_by: String,
+    pub reason: String,
+    pub opened_at: DateTime<Utc>,
+    /// Set, with the amounts and justification, once resolved.
+    pub outcome: Option<DisputeOutcome>,
+    pub recipient_amount: Option<Money>,
+    pub sender_amount: Option<Money>,
+    pub justification: Option<String>,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// A document backing a dispute, stored elsewhere and linked by URL.
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct DisputeEvidence {
+    #[serde(skip)]
+    pub dispute_id: i32,
+    pub position: i32,
+    pub name: String,
+    pub url: String,
+}
+
+/// A dispute with its evidence, as returned by the API.
+#[derive(Debug, Clone, Serialize)]
+pub struct Dispute {
+    #[serde(flatten)]
+    pub dispute: EscrowDispute,
+    pub evidence: Vec<DisputeEvidence>,
+}
+
+#[derive(Debug, Clone, Deserialize, Insertable)]
+#[diesel(table_name = escrow_dispute_evidence)]
+pub struct NewEvidence {
+    pub name: String,
+    pub url: String,
+}
+
+/// What a party gives when opening a dispute.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenDispute {
+    pub reason: String,
+    #[serde(default)]
+    pub evidence: Vec<NewEvidence>,
+}
+
+/// The arbiter's decision on an open dispute.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DisputeResolution {
+    pub outcome: DisputeOutcome,
+    /// Required for, and only allowed with, a `Split`.
+    pub recipient_amount: Option<Money>,
+    pub justification: String,
+}
@@ -0,0 +1,24 @@
+use crate::models::money::Money;
+use crate::schema::escrow_fundings;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// The last transaction submitted to create a pending escrow's account.
+#[derive(Debug, Clone, Queryable)]
+pub struct EscrowFunding {
+    pub escrow_id: i32,
+    /// Derived from the escrow, so every attempt creates the same account.
+    pub escrow_account_id: String,
+    pub amount: Money,
+    pub tx_hash: String,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = escrow_fundings)]
+pub struct NewEscrowFunding<'a> {
+    pub escrow_id: i32,
+    pub escrow_account_id: &'a str,
+    pub amount: Money,
+    pub tx_hash: &'a str,
+}
@@ -0,0 +1,30 @@
+use crate::models::money::Money;
+use crate::schema::escrow_milestones;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One tranche of an escrow's funds, paid out to the recipient on its own.
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct EscrowMilestone {
+    pub escrow_id: i32,
+    /// 1-based order within the escrow; identifies the milestone in URLs.
+    pub position: i32,
+    pub description: String,
+    pub amount: Money,
+    pub sender_approved_at: Option<DateTime<Utc>>,
+    pub recipient_approved_at: Option<DateTime<Utc>>,
+    pub released_at: Option<DateTime<Utc>>,
+    /// Ledger transaction that paid this milestone out. Saved when the payout
+    /// is submitted, so it is only final once `released_at` is set.
+    pub release_tx_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A milestone as given when creating an escrow.
+#[derive(Debug, Clone, Deserialize, Insertable)]
+#[diesel(table_name = escrow_milestones)]
+pub struct NewMilestone {
+    pub description: String,
+    pub amount: Money,
+}
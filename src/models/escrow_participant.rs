@@ -0,0 +1,95 @@
+use crate::schema::{escrow_participants, sql_types};
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// The part a user plays in one escrow. Stored as the native
+/// `participant_role` Postgres enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::ParticipantRole)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ParticipantRole {
+    /// Puts the money in.
+    Sender,
+    /// Receives the money on release.
+    Recipient,
+    /// Decides on release or refund when the parties do not.
+    Arbiter,
+}
+
+impl ParticipantRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParticipantRole::Sender => "SENDER",
+            ParticipantRole::Recipient => "RECIPIENT",
+            ParticipantRole::Arbiter => "ARBITER",
+        }
+    }
+}
+
+impl ToSql<sql_types::ParticipantRole, Pg> for ParticipantRole {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::ParticipantRole, Pg> for ParticipantRole {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"SENDER" => Ok(ParticipantRole::Sender),
+            b"RECIPIENT" => Ok(ParticipantRole::Recipient),
+            b"ARBITER" => Ok(ParticipantRole::Arbiter),
+            other => Err(format!(
+                "Unrecognized participant_role variant: {}",
+                String::from_utf8_lossy(other)
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct EscrowParticipant {
+    pub escrow_id: i32,
+    pub role: ParticipantRole,
+    /// Firebase user ID.
+    pub user_id: String,
+    /// When this party agreed to release the funds, for sender and recipient.
+    pub release_approved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = escrow_participants)]
+pub struct NewEscrowParticipant<'a> {
+    pub escrow_id: i32,
+    pub role: ParticipantRole,
+    pub user_id: &'a str,
+}
+
+/// The users bound to a new escrow, by Firebase user ID.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Participants {
+    pub sender: String,
+    pub recipient: String,
+    pub arbiter: Option<String>,
+}
+
+impl Participants {
+    pub fn bindings(&self) -> Vec<(ParticipantRole, &str)> {
+        let mut bindings = vec![
+            (ParticipantRole::Sender, self.sender.as_str()),
+            (ParticipantRole::Recipient, self.recipient.as_str()),
+        ];
+        if let Some(arbiter) = &self.arbiter {
+            bindings.push((ParticipantRole::Arbiter, arbiter.as_str()));
+        }
+        bindings
+    }
+}
@@ -0,0 +1,26 @@
+use crate::models::escrow::EscrowStatus;
+use crate::schema::escrow_status_history;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Queryable)]
+pub struct EscrowStatusHistory {
+    pub id: i32,
+    pub escrow_id: i32,
+    pub from_status: Option<EscrowStatus>,
+    pub to_status: EscrowStatus,
+    pub actor: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = escrow_status_history)]
+pub struct NewEscrowStatusHistory<'a> {
+    pub escrow_id: i32,
+    pub from_status: Option<EscrowStatus>,
+    pub to_status: EscrowStatus,
+    pub actor: &'a str,
+    pub reason: Option<&'a str>,
+}
@@ -0,0 +1,28 @@
+use crate::schema::idempotency_keys;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// A client-supplied `Idempotency-Key` and the response it produced.
+/// `response_status` stays empty while the first request is still running.
+/// Keys are scoped to the caller that sent them.
+#[derive(Debug, Queryable)]
+pub struct IdempotencyKey {
+    pub idempotency_key: String,
+    pub request_hash: String,
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Empty for unauthenticated requests.
+    pub caller_uid: String,
+    /// JSON array of the `[name, value]` pairs replayed with the response.
+    pub response_headers: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = idempotency_keys)]
+pub struct NewIdempotencyKey<'a> {
+    pub caller_uid: &'a str,
+    pub idempotency_key: &'a str,
+    pub request_hash: &'a str,
+}
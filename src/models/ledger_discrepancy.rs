@@ -0,0 +1,88 @@
+use crate::models::money::Money;
+use crate::schema::{ledger_discrepancies, sql_types};
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// What reconciliation found wrong. Stored as the native `discrepancy_kind`
+/// Postgres enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::DiscrepancyKind)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DiscrepancyKind {
+    /// The escrow account holds more or less than the escrow's locked funds.
+    BalanceMismatch,
+    /// A payment to the treasury carried the escrow's memo ID, but in an
+    /// asset other than the escrow's, so it is neither credited nor refunded.
+    UnmatchedDeposit,
+    /// A deposit naming the escrow could not be applied for a reason retrying
+    /// would not fix. Reconciliation refunds it and moves past it, so an
+    /// operator should find out why.
+    FailedDeposit,
+}
+
+impl DiscrepancyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscrepancyKind::BalanceMismatch => "BALANCE_MISMATCH",
+            DiscrepancyKind::UnmatchedDeposit => "UNMATCHED_DEPOSIT",
+            DiscrepancyKind::FailedDeposit => "FAILED_DEPOSIT",
+        }
+    }
+}
+
+impl ToSql<sql_types::DiscrepancyKind, Pg> for DiscrepancyKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::DiscrepancyKind, Pg> for DiscrepancyKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"BALANCE_MISMATCH" => Ok(DiscrepancyKind::BalanceMismatch),
+            b"UNMATCHED_DEPOSIT" => Ok(DiscrepancyKind::UnmatchedDeposit),
+            b"FAILED_DEPOSIT" => Ok(DiscrepancyKind::FailedDeposit),
+            other => Err(format!(
+                "Unrecognized discrepancy_kind variant: {}",
+                String::from_utf8_lossy(other)
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct LedgerDiscrepancy {
+    pub id: i32,
+    pub escrow_id: i32,
+    pub kind: DiscrepancyKind,
+    /// What the escrow says: its locked funds, or the amount it expected to
+    /// be funded with.
+    pub expected_amount: Money,
+    /// What the ledger shows.
+    pub actual_amount: Money,
+    /// The payment that revealed it, if any.
+    pub transaction_hash: Option<String>,
+    pub detail: String,
+    pub detected_at: DateTime<Utc>,
+    /// Set once a later check finds the escrow and the ledger agree again.
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = ledger_discrepancies)]
+pub struct NewLedgerDiscrepancy<'a> {
+    pub escrow_id: i32,
+    pub kind: DiscrepancyKind,
+    pub expected_amount: Money,
+    pub actual_amount: Money,
+    pub transaction_hash: Option<&'a str>,
+    pub detail: &'a str,
+}
@@ -1 +1,11 @@
-pub mod escrow;
\ No newline at end of file
+pub mod escrow;
+pub mod escrow_deposit;
+pub mod escrow_dispute;
+pub mod escrow_funding;
+pub mod escrow_milestone;
+pub mod escrow_participant;
+pub mod escrow_status_history;
+pub mod idempotency_key;
+pub mod ledger_discrepancy;
+pub mod money;
+pub mod stellar_address;
@@ -0,0 +1,241 @@
+use crate::services::stellar;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::BigInt;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use stellar_base::amount::Stroops;
+
+/// An exact quantity of some Stellar asset, in units of 10^-7: the precision
+/// of every amount on the ledger, called stroops for XLM. Which asset is given
+/// by the escrow the amount belongs to (`asset_code` and `asset_issuer`).
+///
+/// On its own it neither orders nor adds up, since that would mix assets:
+/// pair it with its asset as an `AssetAmount` first.
+///
+/// Stored as `INT8` and serialized as the integer number of units; `Display`
+/// and `FromStr` use Stellar's decimal amount strings, e.g. `12.5000000`.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(transparent)]
+#[diesel(sql_type = BigInt)]
+pub struct Money(i64);
+
+impl Money {
+    /// Decimal places of a unit.
+    pub const DECIMALS: u32 = 7;
+    pub const ZERO: Money = Money(0);
+    pub const MAX: Money = Money(i64::MAX);
+
+    const UNITS_PER_WHOLE: i64 = 10_i64.pow(Self::DECIMALS);
+
+    pub const fn from_units(units: i64) -> Self {
+        Money(units)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+}
+
+/// A Stellar asset: its code, and its issuing account unless it is XLM.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetId {
+    pub code: String,
+    pub issuer: Option<String>,
+}
+
+impl AssetId {
+    pub fn new(code: &str, issuer: Option<&str>) -> Self {
+        AssetId {
+            code: code.to_string(),
+            issuer: issuer.map(str::to_string),
+        }
+    }
+
+    pub fn native() -> Self {
+        AssetId::new(stellar::NATIVE_ASSET_CODE, None)
+    }
+}
+
+impl fmt::Display for AssetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.issuer {
+            Some(issuer) => write!(f, "{}:{}", self.code, issuer),
+            None => f.write_str(&self.code),
+        }
+    }
+}
+
+/// Why two amounts could not be compared or combined.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AmountError {
+    #[error("Amount is in {actual}, not {expected}")]
+    AssetMismatch { expected: AssetId, actual: AssetId },
+    #[error("Amount is out of range")]
+    Overflow,
+}
+
+/// An amount together with the asset it is in: the only form in which
+/// amounts are compared, added or subtracted. Every operation checks both
+/// sides are in the same asset and fails with `AmountError::AssetMismatch`
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetAmount {
+    pub amount: Money,
+    pub asset: AssetId,
+}
+
+impl AssetAmount {
+    pub fn new(amount: Money, asset: AssetId) -> Self {
+        AssetAmount { amount, asset }
+    }
+
+    pub fn zero(asset: AssetId) -> Self {
+        AssetAmount::new(Money::ZERO, asset)
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.amount.is_positive()
+    }
+
+    /// `other`'s units, if it is in the same asset.
+    fn units_of(&self, other: &AssetAmount) -> Result<i64, AmountError> {
+        if other.asset != self.asset {
+            return Err(AmountError::AssetMismatch {
+                expected: self.asset.clone(),
+                actual: other.asset.clone(),
+            });
+        }
+        Ok(other.amount.0)
+    }
+
+    pub fn compare(&self, other: &AssetAmount) -> Result<Ordering, AmountError> {
+        Ok(self.amount.0.cmp(&self.units_of(other)?))
+    }
+
+    pub fn checked_add(&self, other: &AssetAmount) -> Result<AssetAmount, AmountError> {
+        let units = self
+            .amount
+            .0
+            .checked_add(self.units_of(other)?)
+            .ok_or(AmountError::Overflow)?;
+        Ok(AssetAmount::new(Money(units), self.asset.clone()))
+    }
+
+    pub fn checked_sub(&self, other: &AssetAmount) -> Result<AssetAmount, AmountError> {
+        let units = self
+            .amount
+            .0
+            .checked_sub(self.units_of(other)?)
+            .ok_or(AmountError::Overflow)?;
+        Ok(AssetAmount::new(Money(units), self.asset.clone()))
+    }
+
+    /// Adds up `amounts`, all in `asset`.
+    pub fn checked_sum<'a>(
+        asset: &AssetId,
+        amounts: impl IntoIterator<Item = &'a AssetAmount>,
+    ) -> Result<AssetAmount, AmountError> {
+        amounts
+            .into_iter()
+            .try_fold(AssetAmount::zero(asset.clone()), |total, amount| {
+                total.checked_add(amount)
+            })
+    }
+}
+
+impl fmt::Display for AssetAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.asset.code)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let per_whole = Self::UNITS_PER_WHOLE as u64;
+        write!(f, "{}{}.{:07}", sign, units / per_whole, units % per_whole)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("'{0}' is not an amount with at most 7 decimal places")]
+pub struct ParseMoneyError(String);
+
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    /// Parses a decimal amount such as `12.5`, exactly.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseMoneyError(s.to_string());
+
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty()
+            || fraction.len() > Self::DECIMALS as usize
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| invalid())?;
+        let fraction: i64 = format!("{:0<7}", fraction).parse().map_err(|_| invalid())?;
+        let units = whole
+            .checked_mul(Self::UNITS_PER_WHOLE)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or_else(invalid)?;
+
+        Ok(Money(if negative { -units } else { units }))
+    }
+}
+
+impl From<Money> for Stroops {
+    fn from(money: Money) -> Stroops {
+        Stroops::new(money.0)
+    }
+}
+
+impl From<Stroops> for Money {
+    fn from(stroops: Stroops) -> Money {
+        Money(stroops.to_i64())
+    }
+}
+
+impl ToSql<BigInt, Pg> for Money {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i64 as ToSql<BigInt, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<BigInt, Pg> for Money {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        i64::from_sql(bytes).map(Money)
+    }
+}
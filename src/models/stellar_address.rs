@@ -0,0 +1,102 @@
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use stellar_base::crypto::{MuxedAccount, MuxedEd25519PublicKey};
+use stellar_base::PublicKey;
+
+/// Where a party of an escrow sends and receives funds: a Stellar account ID
+/// (`G...`) or a muxed account (`M...`), which adds a 64-bit ID to tell apart
+/// the customers of a shared account such as an exchange's.
+///
+/// Only valid StrKeys, checksum included, in their canonical upper-case form
+/// are accepted from clients, so the same account is always stored the same
+/// way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(try_from = "String", into = "String")]
+#[diesel(sql_type = Text)]
+pub struct StellarAddress(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("'{0}' is not a Stellar account (G...) or muxed account (M...) address")]
+pub struct InvalidAddress(String);
+
+impl StellarAddress {
+    pub fn parse(address: &str) -> Result<Self, InvalidAddress> {
+        let canonical = decode(address)
+            .map(|account| account.account_id())
+            .map_err(|_| InvalidAddress(address.to_string()))?;
+        if canonical != address {
+            return Err(InvalidAddress(address.to_string()));
+        }
+        Ok(StellarAddress(canonical))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_muxed(&self) -> bool {
+        self.0.starts_with('M')
+    }
+
+    /// The address as a payment destination. Fails only for addresses stored
+    /// before they were validated.
+    pub fn muxed_account(&self) -> Result<MuxedAccount, InvalidAddress> {
+        decode(&self.0).map_err(|_| InvalidAddress(self.0.clone()))
+    }
+
+    /// The `G...` account that holds the funds, which is the address itself
+    /// unless it is muxed. Balances and trustlines belong to this account.
+    pub fn account_id(&self) -> Result<String, InvalidAddress> {
+        Ok(match self.muxed_account()? {
+            MuxedAccount::Ed25519(account) => account.account_id(),
+            MuxedAccount::MuxedEd25519(muxed) => muxed.public_key().account_id(),
+        })
+    }
+}
+
+fn decode(address: &str) -> stellar_base::error::Result<MuxedAccount> {
+    if address.starts_with('M') {
+        MuxedEd25519PublicKey::from_account_id(address).map(MuxedAccount::from)
+    } else {
+        PublicKey::from_account_id(address).map(MuxedAccount::from)
+    }
+}
+
+impl TryFrom<String> for StellarAddress {
+    type Error = InvalidAddress;
+
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        StellarAddress::parse(&address)
+    }
+}
+
+impl From<StellarAddress> for String {
+    fn from(address: StellarAddress) -> String {
+        address.0
+    }
+}
+
+impl fmt::Display for StellarAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ToSql<Text, Pg> for StellarAddress {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+/// Rows are trusted as stored: addresses saved before validation existed
+/// still load, and only fail once used on the ledger.
+impl FromSql<Text, Pg> for StellarAddress {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        <String as FromSql<Text, Pg>>::from_sql(bytes).map(StellarAddress)
+    }
+}
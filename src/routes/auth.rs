@@ -0,0 +1,99 @@
+use crate::errors::auth::AuthError;
+use crate::routes::body::{read_limited, BodyError, MAX_BODY_BYTES};
+use crate::services::auth::{AuthUser, FirebaseAuth};
+use crate::services::signing::{
+    RequestVerifier, SignedRequest, KEY_ID_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+/// The ways a caller can prove who they are: a Firebase ID token for users,
+/// or an HMAC signature for internal services and partners.
+#[derive(Clone)]
+pub struct Authenticators {
+    /// `None` when Firebase authentication is turned off.
+    pub firebase: Option<Arc<FirebaseAuth>>,
+    pub services: Arc<RequestVerifier>,
+}
+
+/// Route layer that only lets authenticated requests through, making their
+/// `AuthUser` available to handlers.
+///
+/// Requests carrying `X-TrustBridge-Signature` must be validly signed;
+/// everything else needs a Firebase bearer token.
+pub async fn authenticate(
+    State(auth): State<Authenticators>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, AuthError> {
+    let (mut parts, body) = request.into_parts();
+
+    let (user, body) = if parts.headers.contains_key(SIGNATURE_HEADER) {
+        let body = read_limited(body).await.map_err(|err| match err {
+            BodyError::TooLarge => AuthError::PayloadTooLarge(MAX_BODY_BYTES),
+            BodyError::Unreadable => AuthError::invalid_signature("Request body could not be read"),
+        })?;
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or_else(|| parts.uri.path());
+        let signed = SignedRequest {
+            key_id: header_str(&parts.headers, KEY_ID_HEADER)?,
+            timestamp: header_str(&parts.headers, TIMESTAMP_HEADER)?,
+            nonce: header_str(&parts.headers, NONCE_HEADER)?,
+            signature: header_str(&parts.headers, SIGNATURE_HEADER)?,
+            method: parts.method.as_str(),
+            path,
+            body: &body,
+        };
+        let user = auth
+            .services
+            .verify(&signed, chrono::Utc::now().timestamp())?;
+        (user, Body::from(body))
+    } else {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or(AuthError::MissingToken)?;
+        let firebase = auth
+            .firebase
+            .as_ref()
+            .ok_or_else(|| AuthError::invalid("Firebase authentication is disabled"))?;
+        (firebase.verify(token).await?, body)
+    };
+
+    parts.extensions.insert(user);
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, AuthError> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| AuthError::invalid_signature(format!("Missing {} header", name)))
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or(AuthError::MissingToken)
+    }
+}
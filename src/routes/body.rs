@@ -0,0 +1,32 @@
+use axum::body::Bytes;
+use http_body::{Body as HttpBody, LengthLimitError, Limited};
+
+/// Largest request body the server reads, in bytes. Anything longer is
+/// refused with 413.
+pub const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Why a body could not be read into memory.
+#[derive(Debug, thiserror::Error)]
+pub enum BodyError {
+    #[error("Body is larger than {MAX_BODY_BYTES} bytes")]
+    TooLarge,
+    #[error("Body could not be read")]
+    Unreadable,
+}
+
+/// Reads `body` into memory, giving up once it passes `MAX_BODY_BYTES`.
+pub async fn read_limited<B>(body: B) -> Result<Bytes, BodyError>
+where
+    B: HttpBody,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    hyper::body::to_bytes(Limited::new(body, MAX_BODY_BYTES))
+        .await
+        .map_err(|err| {
+            if err.downcast_ref::<LengthLimitError>().is_some() {
+                BodyError::TooLarge
+            } else {
+                BodyError::Unreadable
+            }
+        })
+}
@@ -1,124 +1,366 @@
-use crate::models::escrow::{Escrow, EscrowStatus};
-use crate::services::escrow::EscrowService;
-use sqlx::PgPool;
+use crate::errors::escrow::EscrowError;
+use crate::models::escrow::{Escrow, EscrowPage, EscrowQuery, EscrowStatus, LockFunds, NewEscrow};
+use crate::models::escrow_deposit::{DepositInstructions, EscrowDeposit};
+use crate::models::escrow_dispute::{Dispute, DisputeResolution, OpenDispute};
+use crate::models::escrow_milestone::{EscrowMilestone, NewMilestone};
+use crate::models::escrow_participant::{EscrowParticipant, Participants};
+use crate::models::escrow_status_history::EscrowStatusHistory;
+use crate::models::ledger_discrepancy::LedgerDiscrepancy;
+use crate::routes::extract::{self, Json, Path, Query};
+use crate::routes::idempotency::idempotency;
+use crate::services::auth::AuthUser;
+use crate::state::AppState;
 use axum::{
-    extract::{Path, State},
+    async_trait,
+    body::Body,
+    extract::{FromRequest, FromRequestParts, State},
+    http::{header, request::Parts, Request},
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post, put},
-    Json, Router,
+    Router,
 };
-use std::sync::Arc;
-use tracing::error;
+use serde::{de::DeserializeOwned, Deserialize};
 
-pub struct AppState {
-    escrow_service: Arc<EscrowService>,
-    db_pool: Arc<PgPool>,
-}
-
-pub fn escrow_routes(escrow_service: EscrowService, db_pool: PgPool) -> Router {
-    let shared_state = Arc::new(AppState {
-        escrow_service: Arc::new(escrow_service),
-        db_pool: Arc::new(db_pool),
-    });
-
-    Router::new()
+pub fn escrow_routes(state: &AppState) -> Router<AppState> {
+    // Routes that move money, change an escrow or create records honour
+    // Idempotency-Key
+    let idempotent = Router::new()
         .route("/escrows", post(create_escrow))
-        .route("/escrows/:id", get(get_escrow))
         .route("/escrows/:id/status", put(update_status))
         .route("/escrows/:id/cancel", post(cancel_and_refund))
         .route("/escrows/:id/release", post(release_funds))
         .route("/escrows/:id/lock", post(lock_funds))
-        .with_state(shared_state)
+        .route(
+            "/escrows/:id/milestones/:position/release",
+            post(release_milestone),
+        )
+        .route("/escrows/:id/disputes", post(open_dispute))
+        .route("/escrows/:id/disputes/resolve", post(resolve_dispute))
+        .route_layer(middleware::from_fn_with_state(
+            state.idempotency_service.clone(),
+            idempotency,
+        ));
+
+    Router::new()
+        .merge(idempotent)
+        .route("/escrows", get(list_escrows))
+        .route("/escrows/:id", get(get_escrow))
+        .route("/escrows/:id/history", get(get_status_history))
+        .route("/escrows/:id/participants", get(get_participants))
+        .route("/escrows/:id/milestones", get(get_milestones))
+        .route("/escrows/:id/disputes", get(get_disputes))
+        .route(
+            "/escrows/:id/deposit-instructions",
+            get(get_deposit_instructions),
+        )
+        .route("/escrows/:id/deposits", get(get_deposits))
+        .route("/escrows/:id/discrepancies", get(get_discrepancies))
 }
 
-async fn create_escrow(
-    State(state): State<Arc<AppState>>,
-    Json(escrow): Json<Escrow>,
-) -> Result<Json<Escrow>, String> {
-    let mut tx = state.db_pool.begin().await.map_err(|e| e.to_string())?;
-    let result = state.escrow_service.create_escrow(&mut tx, escrow).await;
-    match result {
-        Ok(escrow) => {
-            tx.commit().await.map_err(|e| e.to_string())?;
-            Ok(Json(escrow))
+/// An escrow response carrying the escrow's version as a strong `ETag`.
+pub struct VersionedEscrow(pub Escrow);
+
+impl IntoResponse for VersionedEscrow {
+    fn into_response(self) -> Response {
+        let etag = format!("\"{}\"", self.0.version);
+        ([(header::ETAG, etag)], Json(self.0)).into_response()
+    }
+}
+
+/// The version named by an `If-Match` header, if the client sent one.
+/// `If-Match: *` matches any version.
+pub struct IfMatch(pub Option<i32>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = EscrowError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(header::IF_MATCH) else {
+            return Ok(IfMatch(None));
+        };
+
+        let value = value.to_str().unwrap_or_default().trim();
+        if value == "*" {
+            return Ok(IfMatch(None));
         }
-        Err(e) => {
-            let _ = tx.rollback().await;
-            Err(e)
+
+        value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .and_then(|version| version.parse().ok())
+            .map(|version| IfMatch(Some(version)))
+            .ok_or_else(|| {
+                EscrowError::validation("If-Match", "Must be a single ETag returned by this API")
+            })
+    }
+}
+
+/// A JSON body the client may leave out, in which case `T::default()` is
+/// used. Unlike `Option<Json<T>>`, a body that is sent but does not parse is
+/// rejected like any other `Json` body rather than silently ignored.
+pub struct OptionalJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S, Body> for OptionalJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Default,
+{
+    type Rejection = EscrowError;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let headers = request.headers().clone();
+        let body = extract::bytes(request, state).await?;
+        if body.is_empty() {
+            return Ok(OptionalJson(T::default()));
         }
+
+        let mut request = Request::new(Body::from(body));
+        *request.headers_mut() = headers;
+        let Json(value) = Json::from_request(request, state).await?;
+        Ok(OptionalJson(value))
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateEscrowRequest {
+    #[serde(flatten)]
+    pub escrow: NewEscrow,
+    pub participants: Participants,
+    /// Omit to release the escrow in one go.
+    #[serde(default)]
+    pub milestones: Vec<NewMilestone>,
+}
+
+async fn create_escrow(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(request): Json<CreateEscrowRequest>,
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .create_escrow(
+            request.escrow,
+            request.participants,
+            request.milestones,
+            &user,
+        )
+        .await
+        .map(VersionedEscrow)
+}
+
+async fn list_escrows(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<EscrowQuery>,
+) -> Result<Json<EscrowPage>, EscrowError> {
+    state
+        .escrow_service
+        .list_escrows(query, &user)
+        .await
+        .map(Json)
+}
+
 async fn get_escrow(
-    State(state): State<Arc<AppState>>,
+    State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<i32>,
-) -> Result<Json<Escrow>, String> {
-    state.escrow_service.get_escrow(id).await.map(Json)
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .get_escrow(id, &user)
+        .await
+        .map(VersionedEscrow)
 }
 
-async fn update_status(
-    State(state): State<Arc<AppState>>,
+async fn get_status_history(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<EscrowStatusHistory>>, EscrowError> {
+    state
+        .escrow_service
+        .status_history(id, &user)
+        .await
+        .map(Json)
+}
+
+async fn get_participants(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<EscrowParticipant>>, EscrowError> {
+    state.escrow_service.participants(id, &user).await.map(Json)
+}
+
+async fn get_milestones(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<EscrowMilestone>>, EscrowError> {
+    state.escrow_service.milestones(id, &user).await.map(Json)
+}
+
+async fn get_disputes(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<Dispute>>, EscrowError> {
+    state.escrow_service.disputes(id, &user).await.map(Json)
+}
+
+async fn get_deposit_instructions(
+    State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<i32>,
-    Json(status): Json<String>,
-) -> Result<Json<Escrow>, String> {
-    let new_status = EscrowStatus::from_string(&status)?;
+) -> Result<Json<DepositInstructions>, EscrowError> {
     state
         .escrow_service
-        .update_status(id, new_status)
+        .deposit_instructions(id, &user)
         .await
         .map(Json)
 }
 
+async fn get_deposits(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<EscrowDeposit>>, EscrowError> {
+    state.escrow_service.deposits(id, &user).await.map(Json)
+}
+
+async fn get_discrepancies(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<LedgerDiscrepancy>>, EscrowError> {
+    state
+        .escrow_service
+        .discrepancies(id, &user)
+        .await
+        .map(Json)
+}
+
+async fn open_dispute(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+    IfMatch(expected_version): IfMatch,
+    Json(request): Json<OpenDispute>,
+) -> Result<Json<Dispute>, EscrowError> {
+    state
+        .escrow_service
+        .open_dispute(id, request, &user, expected_version)
+        .await
+        .map(Json)
+}
+
+async fn resolve_dispute(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+    IfMatch(expected_version): IfMatch,
+    Json(resolution): Json<DisputeResolution>,
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .resolve_dispute(id, resolution, &user, expected_version)
+        .await
+        .map(VersionedEscrow)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateStatusRequest {
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+async fn update_status(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+    IfMatch(expected_version): IfMatch,
+    Json(request): Json<UpdateStatusRequest>,
+) -> Result<VersionedEscrow, EscrowError> {
+    let new_status = EscrowStatus::from_string(&request.status)?;
+    state
+        .escrow_service
+        .update_status(
+            id,
+            new_status,
+            &user,
+            request.reason.as_deref(),
+            expected_version,
+        )
+        .await
+        .map(VersionedEscrow)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TransitionRequest {
+    pub reason: Option<String>,
+}
+
 async fn cancel_and_refund(
-    State(state): State<Arc<AppState>>,
+    State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<i32>,
-) -> Result<Json<Escrow>, String> {
-    let mut tx = state.db_pool.begin().await.map_err(|e| e.to_string())?;
-    let result = state.escrow_service.cancel_and_refund(&mut tx, id).await;
-    match result {
-        Ok(escrow) => {
-            tx.commit().await.map_err(|e| e.to_string())?;
-            Ok(Json(escrow))
-        }
-        Err(e) => {
-            let _ = tx.rollback().await;
-            Err(e)
-        }
-    }
+    IfMatch(expected_version): IfMatch,
+    OptionalJson(request): OptionalJson<TransitionRequest>,
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .cancel_and_refund(id, &user, request.reason.as_deref(), expected_version)
+        .await
+        .map(VersionedEscrow)
 }
 
 async fn release_funds(
-    State(state): State<Arc<AppState>>,
+    State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<i32>,
-) -> Result<Json<Escrow>, String> {
-    let mut tx = state.db_pool.begin().await.map_err(|e| e.to_string())?;
-    let result = state.escrow_service.release_funds(&mut tx, id).await;
-    match result {
-        Ok(escrow) => {
-            tx.commit().await.map_err(|e| e.to_string())?;
-            Ok(Json(escrow))
-        }
-        Err(e) => {
-            let _ = tx.rollback().await;
-            Err(e)
-        }
-    }
+    IfMatch(expected_version): IfMatch,
+    OptionalJson(request): OptionalJson<TransitionRequest>,
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .release_funds(id, &user, request.reason.as_deref(), expected_version)
+        .await
+        .map(VersionedEscrow)
+}
+
+async fn release_milestone(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, position)): Path<(i32, i32)>,
+    IfMatch(expected_version): IfMatch,
+    OptionalJson(request): OptionalJson<TransitionRequest>,
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .release_milestone(
+            id,
+            position,
+            &user,
+            request.reason.as_deref(),
+            expected_version,
+        )
+        .await
+        .map(VersionedEscrow)
 }
 
 async fn lock_funds(
-    State(state): State<Arc<AppState>>,
+    State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<i32>,
-    Json(amount): Json<i64>,
-) -> Result<Json<Escrow>, String> {
-    let mut tx = state.db_pool.begin().await.map_err(|e| e.to_string())?;
-    let result = state.escrow_service.lock_funds(&mut tx, id, amount).await;
-    match result {
-        Ok(escrow) => {
-            tx.commit().await.map_err(|e| e.to_string())?;
-            Ok(Json(escrow))
-        }
-        Err(e) => {
-            let _ = tx.rollback().await;
-            Err(e)
-        }
-    }
+    IfMatch(expected_version): IfMatch,
+    Json(funds): Json<LockFunds>,
+) -> Result<VersionedEscrow, EscrowError> {
+    state
+        .escrow_service
+        .lock_funds(id, funds, &user, expected_version)
+        .await
+        .map(VersionedEscrow)
 }
@@ -0,0 +1,101 @@
+use crate::errors::escrow::EscrowError;
+use crate::routes::body::MAX_BODY_BYTES;
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{rejection::BytesRejection, FromRequest, FromRequestParts},
+    http::{request::Parts, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+// Axum's own extractors answer a bad request with a plain-text body. These
+// wrap them so the client gets the same `ErrorBody` JSON as any other
+// escrow error.
+
+/// Turns an axum rejection into an `EscrowError` on `field`.
+fn rejected(field: &'static str, status: StatusCode, message: String) -> EscrowError {
+    if status == StatusCode::PAYLOAD_TOO_LARGE {
+        EscrowError::PayloadTooLarge(MAX_BODY_BYTES)
+    } else {
+        EscrowError::validation(field, message)
+    }
+}
+
+/// `axum::Json`, rejecting a body that does not parse as a validation error
+/// on `body`.
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S, Body> for Json<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = EscrowError;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        axum::Json::from_request(request, state)
+            .await
+            .map(|axum::Json(value)| Json(value))
+            .map_err(|rejection| rejected("body", rejection.status(), rejection.body_text()))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// `axum::extract::Path`, rejecting parameters that do not parse as a
+/// validation error on `path`.
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Path<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = EscrowError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Path::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Path(value)| Path(value))
+            .map_err(|rejection| rejected("path", rejection.status(), rejection.body_text()))
+    }
+}
+
+/// `axum::extract::Query`, rejecting a query string that does not parse as a
+/// validation error on `query`.
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Query<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = EscrowError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Query(value)| Query(value))
+            .map_err(|rejection| rejected("query", rejection.status(), rejection.body_text()))
+    }
+}
+
+/// The raw body, for extractors that look at it before parsing.
+pub(crate) async fn bytes<S: Send + Sync>(
+    request: Request<Body>,
+    state: &S,
+) -> Result<Bytes, EscrowError> {
+    Bytes::from_request(request, state)
+        .await
+        .map_err(|rejection: BytesRejection| {
+            rejected("body", rejection.status(), rejection.body_text())
+        })
+}
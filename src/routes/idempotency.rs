@@ -0,0 +1,171 @@
+use crate::errors::escrow::EscrowError;
+use crate::routes::body::{read_limited, BodyError, MAX_BODY_BYTES};
+use crate::services::auth::AuthUser;
+use crate::services::idempotency::{IdempotencyOutcome, IdempotencyService};
+use axum::{
+    body::{self, Body, Bytes, Full},
+    extract::State,
+    http::{header, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::Body as HttpBody;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Set on responses that were replayed from an earlier request.
+pub const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Response headers stored with the body and replayed with it.
+const REPLAYED_HEADERS: [HeaderName; 3] = [header::CONTENT_TYPE, header::ETAG, header::LOCATION];
+
+/// Route layer honouring the `Idempotency-Key` header.
+///
+/// The first response for a caller's key is stored, with its
+/// `REPLAYED_HEADERS`, and replayed for retries carrying the same method,
+/// path and body within `KEY_TTL_HOURS`. Server errors and responses over
+/// `MAX_BODY_BYTES` are not stored, so a retry after one runs the request
+/// again. Requests without the header pass through.
+pub async fn idempotency(
+    State(service): State<Arc<IdempotencyService>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, EscrowError> {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| {
+            EscrowError::validation(
+                "Idempotency-Key",
+                format!("Must be 1 to {} visible ASCII characters", MAX_KEY_LENGTH),
+            )
+        })?
+        .to_string();
+
+    let (parts, request_body) = request.into_parts();
+    let request_body = read_limited(request_body).await.map_err(|err| match err {
+        BodyError::TooLarge => EscrowError::PayloadTooLarge(MAX_BODY_BYTES),
+        BodyError::Unreadable => EscrowError::validation("body", "Request body could not be read"),
+    })?;
+    // Keys are scoped to the caller, so one user's key can never replay
+    // another user's response, nor collide with it
+    let caller = parts
+        .extensions
+        .get::<AuthUser>()
+        .map(|user| user.uid.clone())
+        .unwrap_or_default();
+    let hash = request_hash(
+        &caller,
+        parts.method.as_str(),
+        parts.uri.path(),
+        &request_body,
+    );
+
+    if let IdempotencyOutcome::Replay {
+        status,
+        headers,
+        body,
+    } = service.begin(&caller, &key, &hash).await?
+    {
+        return Ok(replay(status, &headers, body));
+    }
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(request_body)))
+        .await;
+
+    if response.status().is_server_error() {
+        if let Err(err) = service.abandon(&caller, &key).await {
+            tracing::error!(key, error = %err, "Failed to release idempotency key");
+        }
+        return Ok(response);
+    }
+
+    // Only responses known to fit are stored; anything else goes out as is
+    // and a retry runs the request again
+    let fits = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|len| len <= MAX_BODY_BYTES as u64);
+    if !fits {
+        service.abandon(&caller, &key).await?;
+        return Ok(response);
+    }
+
+    let (parts, response_body) = response.into_parts();
+    let response_body = match read_limited(response_body).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::error!(key, error = %err, "Failed to buffer idempotent response");
+            service.abandon(&caller, &key).await?;
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    let stored = String::from_utf8_lossy(&response_body);
+    let headers: Vec<(String, String)> = REPLAYED_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = parts.headers.get(name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    if let Err(err) = service
+        .complete(&caller, &key, parts.status.as_u16(), &headers, &stored)
+        .await
+    {
+        tracing::error!(key, error = %err, "Failed to store idempotent response");
+    }
+
+    Ok(Response::from_parts(
+        parts,
+        body::boxed(Full::from(response_body)),
+    ))
+}
+
+/// Fingerprint of a request, so a reused key can be told apart from a retry.
+fn request_hash(caller: &str, method: &str, path: &str, body: &Bytes) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(caller.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(method.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn replay(status: u16, stored_headers: &[(String, String)], body: String) -> Response {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    // Responses stored before their headers were are all JSON
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    for (name, value) in stored_headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAY_HEADER, HeaderValue::from_static("true"));
+    response
+}
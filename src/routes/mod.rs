@@ -1,2 +1,26 @@
+pub mod auth;
+pub mod body;
+pub mod escrow;
+pub mod extract;
 pub mod health;
-pub mod escrow;
\ No newline at end of file
+pub mod idempotency;
+
+use crate::state::AppState;
+use crate::telemetry;
+use axum::{extract::DefaultBodyLimit, middleware, routing::get, Router};
+
+pub fn app(state: AppState) -> Router {
+    let router = Router::new()
+        .route("/health", get(health::health_check))
+        .merge(
+            escrow::escrow_routes(&state).route_layer(middleware::from_fn_with_state(
+                state.auth.clone(),
+                auth::authenticate,
+            )),
+        )
+        // Extractors share the cap the auth and idempotency layers apply
+        .layer(DefaultBodyLimit::max(body::MAX_BODY_BYTES))
+        .with_state(state);
+
+    telemetry::with_request_tracing(router)
+}
@@ -1,15 +1,230 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "discrepancy_kind"))]
+    pub struct DiscrepancyKind;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "dispute_outcome"))]
+    pub struct DisputeOutcome;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "escrow_status"))]
+    pub struct EscrowStatus;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "participant_role"))]
+    pub struct ParticipantRole;
+}
+
+diesel::table! {
+    escrow_deposits (id) {
+        id -> Int4,
+        escrow_id -> Int4,
+        #[max_length = 64]
+        transaction_hash -> Varchar,
+        #[max_length = 64]
+        paging_token -> Varchar,
+        #[max_length = 56]
+        from_address -> Varchar,
+        amount -> Int8,
+        credited_amount -> Int8,
+        refund_amount -> Int8,
+        refund_reason -> Nullable<Text>,
+        #[max_length = 64]
+        refund_tx_hash -> Nullable<Varchar>,
+        received_at -> Timestamptz,
+        refunded_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    escrow_fundings (escrow_id) {
+        escrow_id -> Int4,
+        #[max_length = 56]
+        escrow_account_id -> Varchar,
+        amount -> Int8,
+        #[max_length = 64]
+        tx_hash -> Varchar,
+        submitted_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    escrow_dispute_evidence (dispute_id, position) {
+        dispute_id -> Int4,
+        position -> Int4,
+        name -> Text,
+        url -> Text,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DisputeOutcome;
+
+    escrow_disputes (id) {
+        id -> Int4,
+        escrow_id -> Int4,
+        #[max_length = 128]
+        opened_by -> Varchar,
+        reason -> Text,
+        opened_at -> Timestamptz,
+        outcome -> Nullable<DisputeOutcome>,
+        recipient_amount -> Nullable<Int8>,
+        sender_amount -> Nullable<Int8>,
+        justification -> Nullable<Text>,
+        #[max_length = 128]
+        resolved_by -> Nullable<Varchar>,
+        resolved_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    escrow_milestones (escrow_id, position) {
+        escrow_id -> Int4,
+        position -> Int4,
+        description -> Text,
+        amount -> Int8,
+        sender_approved_at -> Nullable<Timestamptz>,
+        recipient_approved_at -> Nullable<Timestamptz>,
+        released_at -> Nullable<Timestamptz>,
+        release_tx_hash -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ParticipantRole;
+
+    escrow_participants (escrow_id, role) {
+        escrow_id -> Int4,
+        role -> ParticipantRole,
+        #[max_length = 128]
+        user_id -> Varchar,
+        release_approved_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::EscrowStatus;
+
+    escrow_status_history (id) {
+        id -> Int4,
+        escrow_id -> Int4,
+        from_status -> Nullable<EscrowStatus>,
+        to_status -> EscrowStatus,
+        actor -> Varchar,
+        reason -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::EscrowStatus;
+
     escrows (id) {
         id -> Int4,
         loan_amount -> Int8,
         loan_term -> Varchar,
         purpose_of_loan -> Text,
         monthly_income -> Int8,
-        status -> Varchar,
+        status -> EscrowStatus,
         sender_address -> Varchar,
         recipient_address -> Varchar,
         locked_funds -> Int8,
+        escrow_account_id -> Nullable<Varchar>,
+        funding_tx_hash -> Nullable<Varchar>,
+        settlement_target -> Nullable<EscrowStatus>,
+        settlement_tx_hash -> Nullable<Varchar>,
+        version -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        funded_at -> Nullable<Timestamptz>,
+        released_at -> Nullable<Timestamptz>,
+        cancelled_at -> Nullable<Timestamptz>,
+        funding_deadline -> Nullable<Timestamptz>,
+        release_deadline -> Nullable<Timestamptz>,
+        expired_at -> Nullable<Timestamptz>,
+        settlement_recipient_amount -> Nullable<Int8>,
+        #[max_length = 12]
+        asset_code -> Varchar,
+        #[max_length = 56]
+        asset_issuer -> Nullable<Varchar>,
+        #[max_length = 64]
+        deposit_tx_hash -> Nullable<Varchar>,
+        deposit_memo_id -> Int8,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (caller_uid, idempotency_key) {
+        #[max_length = 255]
+        idempotency_key -> Varchar,
+        #[max_length = 64]
+        request_hash -> Varchar,
+        response_status -> Nullable<Int4>,
+        response_body -> Nullable<Text>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        #[max_length = 128]
+        caller_uid -> Varchar,
+        response_headers -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    ledger_cursors (account_id) {
+        #[max_length = 56]
+        account_id -> Varchar,
+        #[max_length = 64]
+        cursor -> Varchar,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DiscrepancyKind;
+
+    ledger_discrepancies (id) {
+        id -> Int4,
+        escrow_id -> Int4,
+        kind -> DiscrepancyKind,
+        expected_amount -> Int8,
+        actual_amount -> Int8,
+        #[max_length = 64]
+        transaction_hash -> Nullable<Varchar>,
+        detail -> Text,
+        detected_at -> Timestamptz,
+        resolved_at -> Nullable<Timestamptz>,
     }
 }
+
+diesel::joinable!(escrow_deposits -> escrows (escrow_id));
+diesel::joinable!(escrow_dispute_evidence -> escrow_disputes (dispute_id));
+diesel::joinable!(escrow_disputes -> escrows (escrow_id));
+diesel::joinable!(escrow_fundings -> escrows (escrow_id));
+diesel::joinable!(escrow_milestones -> escrows (escrow_id));
+diesel::joinable!(escrow_participants -> escrows (escrow_id));
+diesel::joinable!(escrow_status_history -> escrows (escrow_id));
+diesel::joinable!(ledger_discrepancies -> escrows (escrow_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    escrow_deposits,
+    escrow_dispute_evidence,
+    escrow_disputes,
+    escrow_fundings,
+    escrow_milestones,
+    escrow_participants,
+    escrow_status_history,
+    escrows,
+    idempotency_keys,
+    ledger_cursors,
+    ledger_discrepancies,
+);
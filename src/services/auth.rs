@@ -0,0 +1,221 @@
+use crate::errors::auth::AuthError;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Where Google publishes the keys that sign Firebase ID tokens.
+pub const GOOGLE_JWKS_URL: &str =
+    "https://www.googleapis.com/service_accounts/v1/jwk/securetoken@system.gserviceaccount.com";
+
+/// Used when the key response does not say how long it may be cached.
+const DEFAULT_KEYS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A token signed with an unknown key refetches the keys at most this often.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a user may do across the platform, set as the `role` custom claim
+/// on their Firebase account. Users without the claim are borrowers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    #[default]
+    Borrower,
+    Lender,
+    Arbiter,
+    Admin,
+}
+
+impl Role {
+    pub fn from_claim(claim: &str) -> Option<Self> {
+        match claim {
+            "borrower" => Some(Role::Borrower),
+            "lender" => Some(Role::Lender),
+            "arbiter" => Some(Role::Arbiter),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Recorded as the actor of status changes the server makes on its own,
+/// such as deadline expiries and refunds.
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// The user a verified Firebase ID token belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthUser {
+    /// Firebase user ID (the token's `sub`).
+    pub uid: String,
+    pub email: Option<String>,
+    pub role: Role,
+}
+
+/// A JWKS and how long it may be cached.
+pub struct PublicKeys {
+    pub keys: JwkSet,
+    pub max_age: Duration,
+}
+
+/// Supplies the public keys ID tokens are verified against.
+pub trait KeySource: Send + Sync {
+    /// Fetches the current key set. May block.
+    fn fetch(&self) -> Result<PublicKeys, AuthError>;
+}
+
+/// Fetches Google's published Firebase signing keys over HTTPS.
+pub struct GoogleKeySource {
+    url: String,
+}
+
+impl GoogleKeySource {
+    pub fn new() -> Self {
+        Self {
+            url: GOOGLE_JWKS_URL.to_string(),
+        }
+    }
+}
+
+impl Default for GoogleKeySource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeySource for GoogleKeySource {
+    fn fetch(&self) -> Result<PublicKeys, AuthError> {
+        let response = ureq::get(&self.url)
+            .call()
+            .map_err(|err| AuthError::KeysUnavailable(err.to_string()))?;
+
+        let max_age = response
+            .header("cache-control")
+            .and_then(parse_max_age)
+            .unwrap_or(DEFAULT_KEYS_MAX_AGE);
+        let body = response
+            .into_string()
+            .map_err(|err| AuthError::KeysUnavailable(err.to_string()))?;
+        let keys = serde_json::from_str(&body)
+            .map_err(|err| AuthError::KeysUnavailable(err.to_string()))?;
+
+        Ok(PublicKeys { keys, max_age })
+    }
+}
+
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+    cache_control
+        .split(',')
+        .filter_map(|directive| directive.trim().strip_prefix("max-age="))
+        .find_map(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs)
+}
+
+#[derive(Debug, Deserialize)]
+struct FirebaseClaims {
+    sub: String,
+    email: Option<String>,
+    role: Option<String>,
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+    expires_at: Instant,
+}
+
+/// Verifies Firebase ID tokens for one project.
+///
+/// Tokens must be RS256-signed by a key from `key_source`, issued by
+/// `https://securetoken.google.com/<project>` for the project as audience,
+/// and unexpired. Keys are cached for as long as their source allows.
+pub struct FirebaseAuth {
+    key_source: Arc<dyn KeySource>,
+    validation: Validation,
+    cache: RwLock<Option<CachedKeys>>,
+}
+
+impl FirebaseAuth {
+    pub fn new(project_id: &str, key_source: Arc<dyn KeySource>) -> Self {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[project_id]);
+        validation.set_issuer(&[format!("https://securetoken.google.com/{}", project_id)]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+
+        Self {
+            key_source,
+            validation,
+            cache: RwLock::new(None),
+        }
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<AuthUser, AuthError> {
+        let header = decode_header(token).map_err(AuthError::invalid)?;
+        if header.alg != Algorithm::RS256 {
+            return Err(AuthError::invalid("Token must be signed with RS256"));
+        }
+        let kid = header
+            .kid
+            .ok_or_else(|| AuthError::invalid("Token has no key ID"))?;
+
+        let key = match self.cached_key(&kid) {
+            Some(key) => key,
+            None => {
+                self.refresh().await?;
+                self.cached_key(&kid)
+                    .ok_or_else(|| AuthError::invalid("Token was signed with an unknown key"))?
+            }
+        };
+
+        let claims = decode::<FirebaseClaims>(token, &key, &self.validation)
+            .map_err(AuthError::invalid)?
+            .claims;
+        if claims.sub.is_empty() {
+            return Err(AuthError::invalid("Token has no subject"));
+        }
+
+        let role = match claims.role.as_deref() {
+            None => Role::default(),
+            Some(claim) => Role::from_claim(claim)
+                .ok_or_else(|| AuthError::invalid(format!("Unknown role '{}'", claim)))?,
+        };
+
+        Ok(AuthUser {
+            uid: claims.sub,
+            email: claims.email,
+            role,
+        })
+    }
+
+    fn cached_key(&self, kid: &str) -> Option<DecodingKey> {
+        let cache = self.cache.read().unwrap();
+        let cached = cache.as_ref().filter(|c| c.expires_at > Instant::now())?;
+        DecodingKey::from_jwk(cached.keys.find(kid)?).ok()
+    }
+
+    async fn refresh(&self) -> Result<(), AuthError> {
+        let recently_fetched = self
+            .cache
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|c| c.fetched_at.elapsed() < MIN_REFRESH_INTERVAL && c.expires_at > Instant::now())
+            .unwrap_or(false);
+        if recently_fetched {
+            return Ok(());
+        }
+
+        let key_source = self.key_source.clone();
+        let fetched = tokio::task::spawn_blocking(move || key_source.fetch())
+            .await
+            .map_err(|err| AuthError::KeysUnavailable(err.to_string()))??;
+
+        let now = Instant::now();
+        *self.cache.write().unwrap() = Some(CachedKeys {
+            keys: fetched.keys,
+            fetched_at: now,
+            expires_at: now + fetched.max_age,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use crate::errors::escrow::EscrowError;
+use crate::models::escrow_participant::{EscrowParticipant, ParticipantRole, Participants};
+use crate::services::auth::{AuthUser, Role};
+
+/// What one user may do with one escrow, from their platform role and the
+/// participant roles they hold on it. Admins may do everything.
+#[derive(Debug)]
+pub struct EscrowAccess {
+    admin: bool,
+    roles: Vec<ParticipantRole>,
+}
+
+impl EscrowAccess {
+    pub fn of(actor: &AuthUser, participants: &[EscrowParticipant]) -> Self {
+        EscrowAccess {
+            admin: actor.role == Role::Admin,
+            roles: participants
+                .iter()
+                .filter(|participant| participant.user_id == actor.uid)
+                .map(|participant| participant.role)
+                .collect(),
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    pub fn is(&self, role: ParticipantRole) -> bool {
+        self.roles.contains(&role)
+    }
+
+    /// Participants and admins can see an escrow and its history.
+    pub fn require_view(&self) -> Result<(), EscrowError> {
+        if self.admin || !self.roles.is_empty() {
+            Ok(())
+        } else {
+            Err(EscrowError::Forbidden(
+                "Only participants may access this escrow".to_string(),
+            ))
+        }
+    }
+
+    /// Passes for admins and for holders of any of `roles`.
+    pub fn require(&self, roles: &[ParticipantRole], action: &str) -> Result<(), EscrowError> {
+        if self.admin || roles.iter().any(|role| self.is(*role)) {
+            return Ok(());
+        }
+
+        let allowed: Vec<_> = roles
+            .iter()
+            .map(|role| role.as_str().to_lowercase())
+            .collect();
+        Err(EscrowError::Forbidden(format!(
+            "Only the {} may {} this escrow",
+            allowed.join(" or "),
+            action
+        )))
+    }
+}
+
+/// Borrowers and lenders open escrows they take part in as sender or
+/// recipient; admins may open any escrow.
+pub fn authorize_create(actor: &AuthUser, participants: &Participants) -> Result<(), EscrowError> {
+    if participants.sender == participants.recipient {
+        return Err(EscrowError::validation(
+            "participants",
+            "Sender and recipient must be different users",
+        ));
+    }
+
+    match actor.role {
+        Role::Admin => Ok(()),
+        Role::Borrower | Role::Lender
+            if actor.uid == participants.sender || actor.uid == participants.recipient =>
+        {
+            Ok(())
+        }
+        Role::Borrower | Role::Lender => Err(EscrowError::Forbidden(
+            "You must be the sender or recipient of escrows you create".to_string(),
+        )),
+        Role::Arbiter => Err(EscrowError::Forbidden(
+            "Arbiters cannot create escrows".to_string(),
+        )),
+    }
+}
@@ -1,246 +1,2480 @@
-use crate::models::escrow::{Escrow, EscrowStatus};
+use crate::db::DbPool;
+use crate::errors::escrow::EscrowError;
+use crate::models::escrow::{
+    Escrow, EscrowPage, EscrowQuery, EscrowSort, EscrowStatus, LockFunds, NewEscrow,
+};
+use crate::models::escrow_deposit::{DepositInstructions, EscrowDeposit, NewEscrowDeposit};
+use crate::models::escrow_dispute::{
+    Dispute, DisputeEvidence, DisputeOutcome, DisputeResolution, EscrowDispute, OpenDispute,
+};
+use crate::models::escrow_funding::{EscrowFunding, NewEscrowFunding};
+use crate::models::escrow_milestone::{EscrowMilestone, NewMilestone};
+use crate::models::escrow_participant::{
+    EscrowParticipant, NewEscrowParticipant, ParticipantRole, Participants,
+};
+use crate::models::escrow_status_history::{EscrowStatusHistory, NewEscrowStatusHistory};
+use crate::models::ledger_discrepancy::{DiscrepancyKind, LedgerDiscrepancy, NewLedgerDiscrepancy};
+use crate::models::money::{AssetAmount, AssetId, Money};
+use crate::models::stellar_address::StellarAddress;
+use crate::schema::{escrow_deposits, ledger_discrepancies};
+use crate::services::auth::{AuthUser, Role, SYSTEM_ACTOR};
+use crate::services::authorization::{self, EscrowAccess};
+use crate::services::ledger::{LedgerClient, LedgerPayment};
+use crate::services::state_machine::EscrowTransition;
+use crate::services::stellar::{self, StellarConfig, SupportedAsset};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use diesel::PgConnection;
-use stellar_sdk::{Client, Network, Keypair, TransactionBuilder};
-use std::str::FromStr;
-type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
-
-type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
-
-pub struct StellarConfig {
-    pub network: Network,
-    pub horizon_url: String,
-    pub escrow_keypair: Keypair,
-}
-
-impl StellarConfig {
-    pub fn from_env() -> Self {
-        dotenv::dotenv().ok();
-        
-        let network = match std::env::var("STELLAR_NETWORK")
-            .unwrap_or_else(|_| "testnet".to_string()).as_str() {
-            "mainnet" => Network::Public,
-            _ => Network::Testnet,
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard};
+use stellar_base::transaction::Transaction;
+use stellar_base::{Asset, KeyPair, Memo};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+/// Payments read from the ledger per request, Horizon's maximum.
+const PAYMENT_PAGE_SIZE: u8 = 200;
+/// Escrows whose account should hold their locked funds.
+const ACTIVE_STATUSES: [EscrowStatus; 3] = [
+    EscrowStatus::Funded,
+    EscrowStatus::PartiallyReleased,
+    EscrowStatus::Disputed,
+];
+/// Unique constraint on the random `deposit_memo_id` default.
+const DEPOSIT_MEMO_ID_CONSTRAINT: &str = "escrows_deposit_memo_id_key";
+/// Memo IDs drawn before creating an escrow gives up. A collision among 2^53
+/// values is rare, let alone several in a row.
+const MEMO_ID_ATTEMPTS: u32 = 5;
+
+#[derive(Clone)]
+pub struct EscrowService {
+    pool: DbPool,
+    stellar_config: Arc<StellarConfig>,
+    ledger: Arc<dyn LedgerClient>,
+    /// Held from reading the treasury's sequence number until the
+    /// transaction using it is submitted. Two treasury transactions built at
+    /// once would take the same number, and the ledger rejects the second.
+    treasury_lock: Arc<Mutex<()>>,
+}
+
+impl EscrowService {
+    pub fn new(
+        pool: DbPool,
+        stellar_config: Arc<StellarConfig>,
+        ledger: Arc<dyn LedgerClient>,
+    ) -> Self {
+        EscrowService {
+            pool,
+            stellar_config,
+            ledger,
+            treasury_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Runs `work` on tokio's blocking pool, in the current span. For
+    /// anything that waits on the ledger, above all while holding row locks,
+    /// which would otherwise stall a runtime worker for the whole round trip.
+    async fn blocking<T, F>(&self, work: F) -> Result<T, EscrowError>
+    where
+        T: Send + 'static,
+        F: FnOnce(EscrowService) -> Result<T, EscrowError> + Send + 'static,
+    {
+        let service = self.clone();
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || span.in_scope(|| work(service)))
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+    }
+
+    #[tracing::instrument(skip_all, fields(actor = %actor.uid))]
+    pub async fn create_escrow(
+        &self,
+        new_escrow: NewEscrow,
+        participants: Participants,
+        milestones: Vec<NewMilestone>,
+        actor: &AuthUser,
+    ) -> Result<Escrow, EscrowError> {
+        use crate::schema::escrows::dsl::*;
+
+        authorization::authorize_create(actor, &participants)?;
+
+        let mut conn = self.pool.get()?;
+
+        // Validate the escrow
+        if !new_escrow.loan_amount.is_positive() {
+            return Err(EscrowError::validation(
+                "loan_amount",
+                "Loan amount must be greater than 0",
+            ));
+        }
+
+        if new_escrow.loan_term.is_empty() {
+            return Err(EscrowError::validation(
+                "loan_term",
+                "Loan term must be provided",
+            ));
+        }
+
+        if new_escrow.purpose_of_loan.is_empty() {
+            return Err(EscrowError::validation(
+                "purpose_of_loan",
+                "Purpose of loan must be provided",
+            ));
+        }
+
+        if !new_escrow.monthly_income.is_positive() {
+            return Err(EscrowError::validation(
+                "monthly_income",
+                "Monthly income must be greater than 0",
+            ));
+        }
+
+        let asset = self
+            .stellar_config
+            .supported_asset(&new_escrow.asset_code, new_escrow.asset_issuer.as_deref())?;
+        asset.check_precision("loan_amount", new_escrow.loan_amount)?;
+        asset.check_precision("monthly_income", new_escrow.monthly_income)?;
+
+        let now = Utc::now();
+        for (field, deadline) in [
+            ("funding_deadline", new_escrow.funding_deadline),
+            ("release_deadline", new_escrow.release_deadline),
+        ] {
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                return Err(EscrowError::validation(
+                    field,
+                    "Deadline must be in the future",
+                ));
+            }
+        }
+        if let (Some(funding), Some(release)) =
+            (new_escrow.funding_deadline, new_escrow.release_deadline)
+        {
+            if release <= funding {
+                return Err(EscrowError::validation(
+                    "release_deadline",
+                    "Release deadline must be after the funding deadline",
+                ));
+            }
+        }
+
+        if !milestones.is_empty() {
+            for (index, milestone) in milestones.iter().enumerate() {
+                if !milestone.amount.is_positive() {
+                    return Err(EscrowError::validation(
+                        "milestones",
+                        format!("Milestone {} amount must be greater than 0", index + 1),
+                    ));
+                }
+                asset.check_precision("milestones", milestone.amount)?;
+                if milestone.description.trim().is_empty() {
+                    return Err(EscrowError::validation(
+                        "milestones",
+                        format!("Milestone {} description must be provided", index + 1),
+                    ));
+                }
+            }
+            let escrow_asset = new_escrow.asset();
+            let amounts: Vec<_> = milestones
+                .iter()
+                .map(|milestone| AssetAmount::new(milestone.amount, escrow_asset.clone()))
+                .collect();
+            let total = AssetAmount::checked_sum(&escrow_asset, &amounts).map_err(|_| {
+                EscrowError::validation("milestones", "Milestone amounts are too large")
+            })?;
+            let loan = AssetAmount::new(new_escrow.loan_amount, escrow_asset);
+            if total != loan {
+                return Err(EscrowError::validation(
+                    "milestones",
+                    format!(
+                        "Milestone amounts add up to {}, not the loan amount of {}",
+                        total, loan
+                    ),
+                ));
+            }
+        }
+
+        // Create the escrow; it enters the state machine as Pending with nothing locked
+        conn.transaction(|conn| {
+            // The deposit memo ID is drawn at random by the column default, so
+            // it can already be taken; draw again rather than fail the request.
+            // Each attempt runs in a savepoint so a collision leaves the
+            // transaction usable
+            let mut attempt = 1;
+            let created: Escrow = loop {
+                let inserted = conn.transaction(|conn| {
+                    diesel::insert_into(escrows)
+                        .values((
+                            &new_escrow,
+                            status.eq(EscrowStatus::Pending),
+                            locked_funds.eq(0),
+                        ))
+                        .get_result(conn)
+                });
+                match inserted {
+                    Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info))
+                        if info.constraint_name() == Some(DEPOSIT_MEMO_ID_CONSTRAINT)
+                            && attempt < MEMO_ID_ATTEMPTS =>
+                    {
+                        attempt += 1;
+                    }
+                    result => break result?,
+                }
+            };
+
+            let bindings: Vec<_> = participants
+                .bindings()
+                .into_iter()
+                .map(|(role, user_id)| NewEscrowParticipant {
+                    escrow_id: created.id,
+                    role,
+                    user_id,
+                })
+                .collect();
+            diesel::insert_into(crate::schema::escrow_participants::table)
+                .values(&bindings)
+                .execute(conn)?;
+
+            if !milestones.is_empty() {
+                use crate::schema::escrow_milestones::dsl as milestone;
+                let rows: Vec<_> = (1..)
+                    .zip(&milestones)
+                    .map(|(position, new_milestone)| {
+                        (
+                            milestone::escrow_id.eq(created.id),
+                            milestone::position.eq(position),
+                            new_milestone,
+                        )
+                    })
+                    .collect();
+                diesel::insert_into(milestone::escrow_milestones)
+                    .values(rows)
+                    .execute(conn)?;
+            }
+
+            record_transition(
+                conn,
+                created.id,
+                None,
+                EscrowStatus::Pending,
+                &actor.uid,
+                Some("Escrow created"),
+            )?;
+
+            Ok(created)
+        })
+    }
+
+    pub async fn get_escrow(&self, _id: i32, actor: &AuthUser) -> Result<Escrow, EscrowError> {
+        let mut conn = self.pool.get()?;
+
+        let escrow = find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(actor, &load_participants(&mut conn, _id)?).require_view()?;
+
+        Ok(escrow)
+    }
+
+    /// Lists the escrows matching `query`, a page at a time. Admins see every
+    /// escrow; everyone else only those they take part in.
+    pub async fn list_escrows(
+        &self,
+        query: EscrowQuery,
+        actor: &AuthUser,
+    ) -> Result<EscrowPage, EscrowError> {
+        use crate::schema::escrows::dsl::*;
+
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(EscrowError::validation(
+                "limit",
+                format!("Must be between 1 and {}", MAX_PAGE_SIZE),
+            ));
+        }
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(|cursor| PageCursor::decode(cursor, query.sort))
+            .transpose()?;
+
+        let mut sql = escrows.into_boxed();
+
+        if actor.role != Role::Admin {
+            use crate::schema::escrow_participants::dsl as participants;
+            sql = sql.filter(
+                id.eq_any(
+                    participants::escrow_participants
+                        .select(participants::escrow_id)
+                        .filter(participants::user_id.eq(&actor.uid)),
+                ),
+            );
+        }
+        if let Some(statuses) = &query.status {
+            let statuses = statuses
+                .split(',')
+                .map(|label| EscrowStatus::from_string(label.trim()))
+                .collect::<Result<Vec<_>, _>>()?;
+            sql = sql.filter(status.eq_any(statuses));
+        }
+        if let Some(sender) = &query.sender {
+            sql = sql.filter(sender_address.eq(sender));
+        }
+        if let Some(recipient) = &query.recipient {
+            sql = sql.filter(recipient_address.eq(recipient));
+        }
+        if let Some(code) = &query.asset_code {
+            sql = sql.filter(asset_code.eq(code));
+        }
+        if let Some(issuer) = &query.asset_issuer {
+            sql = sql.filter(asset_issuer.eq(issuer));
+        }
+        if query.min_amount.is_some() || query.max_amount.is_some() {
+            // Amounts only compare within one asset
+            let code = query.asset_code.as_deref().ok_or_else(|| {
+                EscrowError::validation("asset_code", "Required to filter by amount")
+            })?;
+            let asset = self
+                .stellar_config
+                .supported_asset(code, query.asset_issuer.as_deref())?;
+            if asset.issuer.is_none() {
+                sql = sql.filter(asset_issuer.is_null());
+            }
+        }
+        if let Some(min_amount) = query.min_amount {
+            sql = sql.filter(loan_amount.ge(min_amount));
+        }
+        if let Some(max_amount) = query.max_amount {
+            sql = sql.filter(loan_amount.le(max_amount));
+        }
+        if let Some(after) = query.created_after {
+            sql = sql.filter(created_at.ge(after));
+        }
+        if let Some(before) = query.created_before {
+            sql = sql.filter(created_at.lt(before));
+        }
+
+        // Keyset pagination: continue strictly after the cursor's (key, id)
+        sql = match (query.sort, cursor) {
+            (EscrowSort::CreatedAtAsc, Some(PageCursor::CreatedAt(at, last))) => sql
+                .filter(created_at.gt(at).or(created_at.eq(at).and(id.gt(last))))
+                .order((created_at.asc(), id.asc())),
+            (EscrowSort::CreatedAtDesc, Some(PageCursor::CreatedAt(at, last))) => sql
+                .filter(created_at.lt(at).or(created_at.eq(at).and(id.lt(last))))
+                .order((created_at.desc(), id.desc())),
+            (EscrowSort::LoanAmountAsc, Some(PageCursor::LoanAmount(amount, last))) => sql
+                .filter(
+                    loan_amount
+                        .gt(amount)
+                        .or(loan_amount.eq(amount).and(id.gt(last))),
+                )
+                .order((loan_amount.asc(), id.asc())),
+            (EscrowSort::LoanAmountDesc, Some(PageCursor::LoanAmount(amount, last))) => sql
+                .filter(
+                    loan_amount
+                        .lt(amount)
+                        .or(loan_amount.eq(amount).and(id.lt(last))),
+                )
+                .order((loan_amount.desc(), id.desc())),
+            (EscrowSort::CreatedAtAsc, _) => sql.order((created_at.asc(), id.asc())),
+            (EscrowSort::CreatedAtDesc, _) => sql.order((created_at.desc(), id.desc())),
+            (EscrowSort::LoanAmountAsc, _) => sql.order((loan_amount.asc(), id.asc())),
+            (EscrowSort::LoanAmountDesc, _) => sql.order((loan_amount.desc(), id.desc())),
         };
 
-        let horizon_url = std::env::var("STELLAR_HORIZON_URL")
-            .unwrap_or_else(|_| "https://horizon-testnet.stellar.org".to_string());
+        let mut conn = self.pool.get()?;
+        // One extra row tells whether there is another page
+        let mut items: Vec<Escrow> = sql.limit(limit + 1).load(&mut conn)?;
+        let next_cursor = if items.len() as i64 > limit {
+            items.truncate(limit as usize);
+            items
+                .last()
+                .map(|last| PageCursor::after(last, query.sort).encode(query.sort))
+        } else {
+            None
+        };
+
+        Ok(EscrowPage { items, next_cursor })
+    }
 
-        let secret_key = std::env::var("STELLAR_ESCROW_SECRET_KEY")
-            .expect("STELLAR_ESCROW_SECRET_KEY must be set");
+    pub async fn participants(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+    ) -> Result<Vec<EscrowParticipant>, EscrowError> {
+        let mut conn = self.pool.get()?;
+
+        find_escrow(&mut conn, _id)?;
+        let participants = load_participants(&mut conn, _id)?;
+        EscrowAccess::of(actor, &participants).require_view()?;
+
+        Ok(participants)
+    }
 
-        Self {
-            network,
-            horizon_url,
-            escrow_keypair: Keypair::from_secret_seed(&secret_key)
-                .expect("Invalid Stellar secret key"),
+    /// The escrow's milestones in order; empty if it is released in one go.
+    pub async fn milestones(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+    ) -> Result<Vec<EscrowMilestone>, EscrowError> {
+        let mut conn = self.pool.get()?;
+
+        find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(actor, &load_participants(&mut conn, _id)?).require_view()?;
+
+        load_milestones(&mut conn, _id)
+    }
+
+    pub async fn status_history(
+        &self,
+        _id: i32,
+        user: &AuthUser,
+    ) -> Result<Vec<EscrowStatusHistory>, EscrowError> {
+        use crate::schema::escrow_status_history::dsl::*;
+
+        let mut conn = self.pool.get()?;
+
+        // Fail with NotFound rather than an empty history for unknown escrows
+        find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(user, &load_participants(&mut conn, _id)?).require_view()?;
+
+        Ok(escrow_status_history
+            .filter(escrow_id.eq(_id))
+            .order((created_at.asc(), id.asc()))
+            .load(&mut conn)?)
+    }
+
+    /// Moves an escrow to `new_status` through the operation that owns that
+    /// transition, so a generic status change can never skip its side effects.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, actor = %actor.uid))]
+    pub async fn update_status(
+        &self,
+        _id: i32,
+        new_status: EscrowStatus,
+        actor: &AuthUser,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        let current_escrow = self.get_escrow(_id, actor).await?;
+        match new_status {
+            EscrowStatus::Funded if current_escrow.status == EscrowStatus::Pending => Err(
+                EscrowError::validation("status", "Funding requires an amount, lock funds instead"),
+            ),
+            EscrowStatus::Released => {
+                self.release_funds(_id, actor, reason, expected_version)
+                    .await
+            }
+            EscrowStatus::Cancelled => {
+                self.cancel_and_refund(_id, actor, reason, expected_version)
+                    .await
+            }
+            _ => Err(EscrowError::InvalidTransition {
+                from: current_escrow.status,
+                to: new_status,
+            }),
         }
     }
 
-    pub fn create_client(&self) -> Client {
-        Client::new(&self.horizon_url, self.network)
+    /// Locks `funds` on Stellar in a dedicated escrow account funded by the
+    /// treasury, and records that account on the escrow. `funds` must be the
+    /// escrow's loan amount, in its asset.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, actor = %actor.uid))]
+    pub async fn lock_funds(
+        &self,
+        _id: i32,
+        funds: LockFunds,
+        actor: &AuthUser,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        let funds = funds.asset_amount();
+        if !funds.is_positive() {
+            return Err(EscrowError::validation(
+                "amount",
+                "Amount to lock must be greater than 0",
+            ));
+        }
+
+        let actor = actor.clone();
+        self.blocking(move |service| {
+            let mut conn = service.pool.get()?;
+
+            // The row stays locked while the ledger transaction is submitted,
+            // so a concurrent lock of the same escrow waits and then finds it
+            // funded instead of funding a second account.
+            transaction_keeping_attempts(&mut conn, |conn| {
+                let escrow = lock_escrow(conn, _id, expected_version)?;
+                EscrowAccess::of(&actor, &load_participants(conn, _id)?)
+                    .require(&[ParticipantRole::Sender], "fund")?;
+                service.fund(conn, escrow, &funds, &actor.uid, "Funds locked", None)
+            })
+        })
+        .await
     }
-}
 
-pub struct EscrowService {
-    pool: DbPool,
-    stellar_config: StellarConfig
-}
+    /// Moves `funds` from the treasury into a new escrow account and marks
+    /// the locked `escrow` funded. `deposit` is the hash of the sender's
+    /// payment to the treasury, when reconciliation found one.
+    fn fund(
+        &self,
+        conn: &mut PgConnection,
+        escrow: Escrow,
+        funds: &AssetAmount,
+        actor: &str,
+        reason: &str,
+        deposit: Option<&str>,
+    ) -> Result<Escrow, EscrowError> {
+        use crate::schema::escrows::dsl::*;
 
-impl EscrowService {
-    pub fn new(database_url: &str, stellar_config: StellarConfig) -> Self {
-        let manager = ConnectionManager::<PgConnection>::new(database_url);
-        let pool = r2d2::Pool::builder()
-            .build(manager)
-            .expect("Failed to create pool.");
-        
-        EscrowService { 
-            pool,
-            stellar_config 
+        let _id = escrow.id;
+        let new_status = EscrowTransition::Fund.apply(&escrow)?;
+        // The scheduler may not have expired it yet
+        if escrow
+            .funding_deadline
+            .is_some_and(|deadline| deadline <= Utc::now())
+        {
+            return Err(EscrowError::validation(
+                "funding_deadline",
+                "Funding deadline has passed",
+            ));
+        }
+        // Whoever funds it, an escrow holds exactly its loan amount, which
+        // milestones were checked to add up to when it was created
+        let loan = escrow.loan();
+        if loan.compare(funds)? != Ordering::Equal {
+            return Err(EscrowError::validation(
+                "amount",
+                format!("Must equal the loan amount of {}", loan),
+            ));
         }
+        let asset = self
+            .stellar_config
+            .supported_asset(&escrow.asset_code, escrow.asset_issuer.as_deref())?;
+        asset.check_precision("amount", funds.amount)?;
+        // Payouts to a missing account, or one without a trustline, fail
+        // and would leave the funds stuck in the escrow account
+        self.require_payable("recipient_address", &escrow.recipient_address, asset)?;
+        self.require_payable("sender_address", &escrow.sender_address, asset)?;
+
+        let escrow_account = self
+            .stellar_config
+            .escrow_account_keypair(_id, escrow.created_at)?;
+        let tx_hash =
+            self.submit_escrow_account(conn, _id, &escrow_account, &asset.to_stellar()?, funds)?;
+
+        let updated = diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+            .set((
+                locked_funds.eq(funds.amount),
+                status.eq(new_status),
+                escrow_account_id.eq(escrow_account.public_key().account_id()),
+                funding_tx_hash.eq(&tx_hash),
+                funded_at.eq(Utc::now()),
+                deposit_tx_hash.eq(deposit),
+                version.eq(version + 1),
+            ))
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| concurrent_update(_id))?;
+
+        record_transition(
+            conn,
+            _id,
+            Some(escrow.status),
+            new_status,
+            actor,
+            Some(reason),
+        )?;
+
+        Ok(updated)
     }
 
-    pub async fn create_stellar_escrow(&self, new_escrow: Escrow) -> Result<Escrow, String> {
-        let db_escrow = self.create_escrow(new_escrow).await?;
-        let client = self.stellar_config.create_client();
+    /// Creates the escrow account on the ledger and returns the transaction hash.
+    ///
+    /// The attempt is saved on `conn` before submitting, which must run in
+    /// `transaction_keeping_attempts`. If an earlier attempt reached the
+    /// ledger after all, its hash is returned instead of submitting again.
+    fn submit_escrow_account(
+        &self,
+        conn: &mut PgConnection,
+        escrow_id: i32,
+        escrow_account: &KeyPair,
+        asset: &Asset,
+        funds: &AssetAmount,
+    ) -> Result<String, EscrowError> {
+        use crate::schema::escrow_fundings::dsl;
 
-        let source_account = client.load_account(&self.stellar_config.escrow_keypair.public_key()).map_err(|e| format!("Failed to load Stellar account: {:?}", e))?;
+        let previous: Option<EscrowFunding> = dsl::escrow_fundings
+            .find(escrow_id)
+            .first(conn)
+            .optional()?;
+        if let Some(previous) = previous {
+            if let Some(transaction) = self.ledger.load_transaction(&previous.tx_hash)? {
+                if transaction.successful {
+                    // In the escrow's asset, like `funds`
+                    let locked = AssetAmount::new(previous.amount, funds.asset.clone());
+                    if locked.compare(funds)? != Ordering::Equal {
+                        return Err(EscrowError::validation(
+                            "amount",
+                            format!(
+                                "Must equal the {} already locked in the escrow account",
+                                locked
+                            ),
+                        ));
+                    }
+                    return Ok(transaction.hash);
+                }
+            }
+        }
+
+        let (_treasury, sequence) = self.next_treasury_sequence()?;
+
+        let transaction = stellar::build_escrow_account_transaction(
+            &self.stellar_config,
+            sequence,
+            escrow_id,
+            escrow_account,
+            asset,
+            funds.amount,
+        )?;
+        let hash = stellar::transaction_hash(&self.stellar_config.network, &transaction)?;
 
-        let transaction = TransactionBuilder::new(&source_account, &self.stellar_config.network)
-            .add_operation(Operation::Payment {
-                destination: Keypair::from_public_key(&db_escrow.recipient_public_key).map_err(|e| format!("Invalid recipient key: {:?}", e))?,
-                asset: stellar_sdk::Asset::native(),
-                amount: db_escrow.loan_amount as f64,
+        let account_id = escrow_account.public_key().account_id();
+        diesel::insert_into(dsl::escrow_fundings)
+            .values(NewEscrowFunding {
+                escrow_id,
+                escrow_account_id: &account_id,
+                amount: funds.amount,
+                tx_hash: &hash,
             })
-            .add_memo(Memo::Text("Escrow Transaction"))
-            .build()
-            .map_err(|e| format!("Failed to build Stellar transaction: {:?}", e))?;
+            .on_conflict(dsl::escrow_id)
+            .do_update()
+            .set((
+                dsl::escrow_account_id.eq(&account_id),
+                dsl::amount.eq(funds.amount),
+                dsl::tx_hash.eq(&hash),
+                dsl::submitted_at.eq(Utc::now()),
+            ))
+            .execute(conn)?;
 
-        let signed_tx = transaction.sign(&self.stellar_config.escrow_keypair, &self.stellar_config.network);
+        self.submit(transaction, &hash)?;
 
-        let response = client.submit_transaction(&signed_tx).map_err(|e| format!("Failed to submit Stellar transaction: {:?}", e))?;
+        tracing::info!(
+            escrow_id,
+            escrow_account = %escrow_account.public_key().account_id(),
+            tx_hash = %hash,
+            "Escrow funds locked"
+        );
 
-        println!("Stellar transaction successful: {:?}", response);
-        
-        Ok(db_escrow)
+        Ok(hash)
     }
 
-    pub async fn create_escrow(&self, new_escrow: Escrow) -> Result<Escrow, String> {
+    /// Fails unless `address` can be paid in `asset`: its account must exist
+    /// and, for an issued asset, have an authorized trustline to the issuer.
+    fn require_payable(
+        &self,
+        field: &'static str,
+        address: &StellarAddress,
+        asset: &SupportedAsset,
+    ) -> Result<(), EscrowError> {
+        let account_id = address
+            .account_id()
+            .map_err(|err| EscrowError::validation(field, err.to_string()))?;
+        let account = self.ledger.find_account(&account_id)?.ok_or_else(|| {
+            EscrowError::validation(
+                field,
+                format!("Account {} does not exist on the ledger", account_id),
+            )
+        })?;
+        if asset.is_native() {
+            return Ok(());
+        }
+
+        let trusted = account.trustlines.iter().any(|trustline| {
+            trustline.code == asset.code
+                && Some(&trustline.issuer) == asset.issuer.as_ref()
+                && trustline.authorized
+        });
+        if !trusted {
+            return Err(EscrowError::validation(
+                field,
+                format!("Account {} has no trustline for {}", account_id, asset.code),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Takes the treasury lock and returns it with the sequence number of the
+    /// treasury's next transaction. Keep the guard until that transaction
+    /// has been submitted, and get any connection needed meanwhile first.
+    fn next_treasury_sequence(&self) -> Result<(MutexGuard<'_, ()>, i64), EscrowError> {
+        let guard = self.treasury_lock.lock().unwrap();
+        let sequence = self
+            .ledger
+            .load_account(&self.stellar_config.treasury().account_id())?
+            .sequence;
+        Ok((guard, sequence + 1))
+    }
+
+    /// Submits `transaction` and fails unless the ledger applied it.
+    fn submit(&self, transaction: Transaction, hash: &str) -> Result<(), EscrowError> {
+        if !self.ledger.submit_transaction(transaction)?.successful {
+            return Err(EscrowError::Ledger(format!(
+                "Transaction {} was not successful",
+                hash
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pays the locked funds out to the recipient on the ledger.
+    ///
+    /// The arbiter releases on their own. The sender and recipient each
+    /// approve the release instead, and the funds only move once both have:
+    /// until then the escrow is returned unchanged.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, actor = %actor.uid))]
+    pub async fn release_funds(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        let mut conn = self.pool.get()?;
+
+        let approved = conn.transaction(|conn| -> Result<bool, EscrowError> {
+            let escrow = lock_escrow(conn, _id, expected_version)?;
+            let access = EscrowAccess::of(actor, &load_participants(conn, _id)?);
+            if access.is_admin() || access.is(ParticipantRole::Arbiter) {
+                return Ok(true);
+            }
+            access.require(
+                &[ParticipantRole::Sender, ParticipantRole::Recipient],
+                "release",
+            )?;
+
+            match escrow.status {
+                EscrowStatus::Funded | EscrowStatus::PartiallyReleased => {
+                    approve_release(conn, _id, &actor.uid)?
+                }
+                EscrowStatus::Settling => {}
+                from => {
+                    return Err(EscrowError::InvalidTransition {
+                        from,
+                        to: EscrowStatus::Released,
+                    })
+                }
+            }
+            both_parties_approved(conn, _id)
+        })?;
+
+        if !approved {
+            return find_escrow(&mut conn, _id);
+        }
+        // Settling checks out its own connections
+        drop(conn);
+
+        self.settle(
+            _id,
+            EscrowStatus::Released,
+            &actor.uid,
+            reason.or(Some("Funds released")),
+            expected_version,
+        )
+        .await
+    }
+
+    /// Pays one milestone out to the recipient and takes its amount off the
+    /// locked funds. Approval works as for `release_funds`, per milestone.
+    ///
+    /// The escrow is `PartiallyReleased` until the last milestone, which
+    /// settles the escrow like a full release.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, milestone = position, actor = %actor.uid))]
+    pub async fn release_milestone(
+        &self,
+        _id: i32,
+        position: i32,
+        actor: &AuthUser,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        let actor = actor.clone();
+        let reason = reason.map(str::to_string);
+        self.blocking(move |service| {
+            service.pay_milestone(_id, position, &actor, reason.as_deref(), expected_version)
+        })
+        .await
+    }
+
+    fn pay_milestone(
+        &self,
+        _id: i32,
+        position: i32,
+        actor: &AuthUser,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
         use crate::schema::escrows::dsl::*;
 
-        let mut conn = self
-            .pool
-            .get()
-            .map_err(|e| format!("Failed to get database connection: {}", e))?;
+        let mut conn = self.pool.get()?;
 
-        // Validate the escrow
-        if new_escrow.loan_amount <= 0 {
-            return Err("Loan amount must be greater than 0".to_string());
+        let approved = conn.transaction(|conn| -> Result<bool, EscrowError> {
+            let escrow = lock_escrow(conn, _id, expected_version)?;
+            let milestone = find_milestone(conn, _id, position)?;
+            if milestone.released_at.is_some() {
+                return Err(EscrowError::Conflict(format!(
+                    "Milestone {} of escrow {} was already released",
+                    position, _id
+                )));
+            }
+            if !matches!(
+                escrow.status,
+                EscrowStatus::Funded | EscrowStatus::PartiallyReleased
+            ) {
+                return Err(EscrowError::InvalidTransition {
+                    from: escrow.status,
+                    to: EscrowStatus::PartiallyReleased,
+                });
+            }
+
+            let access = EscrowAccess::of(actor, &load_participants(conn, _id)?);
+            if access.is_admin() || access.is(ParticipantRole::Arbiter) {
+                return Ok(true);
+            }
+            access.require(
+                &[ParticipantRole::Sender, ParticipantRole::Recipient],
+                "release",
+            )?;
+            approve_milestone(conn, _id, position, &access)
+        })?;
+
+        if !approved {
+            return find_escrow(&mut conn, _id);
         }
 
-        if new_escrow.loan_term.is_empty() {
-            return Err("Loan term must be provided".to_string());
+        let default_reason = format!("Milestone {} released", position);
+        let reason = reason.unwrap_or(&default_reason);
+
+        // The row stays locked while the payment is submitted, so the same
+        // milestone cannot be paid twice.
+        let partial = transaction_keeping_attempts(&mut conn, |conn| {
+            let escrow = lock_escrow(conn, _id, None)?;
+            let milestone = find_milestone(conn, _id, position)?;
+            if milestone.released_at.is_some() {
+                // Paid out by a concurrent approval
+                return Ok(Some(escrow));
+            }
+            let others_unreleased = load_milestones(conn, _id)?
+                .iter()
+                .any(|other| other.position != position && other.released_at.is_none());
+            if !others_unreleased {
+                return Ok(None);
+            }
+
+            let new_status = EscrowTransition::ReleaseMilestone.apply(&escrow)?;
+            if escrow.amount(milestone.amount).compare(&escrow.locked())? != Ordering::Less {
+                return Err(EscrowError::validation(
+                    "locked_funds",
+                    "Milestone amount exceeds the locked funds",
+                ));
+            }
+            let tx_hash = self.submit_milestone(conn, &escrow, &milestone)?;
+
+            let updated = diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((
+                    status.eq(new_status),
+                    locked_funds.eq(locked_funds - milestone.amount),
+                    version.eq(version + 1),
+                ))
+                .get_result(conn)
+                .optional()?
+                .ok_or_else(|| concurrent_update(_id))?;
+            mark_milestones_released(conn, _id, Some(position), &tx_hash)?;
+
+            record_transition(
+                conn,
+                _id,
+                Some(escrow.status),
+                new_status,
+                &actor.uid,
+                Some(reason),
+            )?;
+
+            Ok(Some(updated))
+        })?;
+
+        if let Some(escrow) = partial {
+            return Ok(escrow);
         }
+        // The last milestone closes the escrow; settling checks out its own
+        // connections
+        drop(conn);
+        self.run_settlement(
+            _id,
+            EscrowStatus::Released,
+            &actor.uid,
+            Some(reason),
+            expected_version,
+        )
+    }
 
-        if new_escrow.purpose_of_loan.is_empty() {
-            return Err("Purpose of loan must be provided".to_string());
+    /// Pays `milestone` out of the escrow account and returns the transaction hash.
+    ///
+    /// The hash is saved on the milestone before submitting, on `conn`, which
+    /// must run in `transaction_keeping_attempts`. A retry after an error
+    /// finds a payout that made it onto the ledger and reuses it instead of
+    /// paying a second time.
+    fn submit_milestone(
+        &self,
+        conn: &mut PgConnection,
+        escrow: &Escrow,
+        milestone: &EscrowMilestone,
+    ) -> Result<String, EscrowError> {
+        use crate::schema::escrow_milestones::dsl;
+
+        if let Some(previous) = &milestone.release_tx_hash {
+            if let Some(transaction) = self.ledger.load_transaction(previous)? {
+                if transaction.successful {
+                    return Ok(transaction.hash);
+                }
+            }
         }
 
-        if new_escrow.monthly_income <= 0 {
-            return Err("Monthly income must be greater than 0".to_string());
+        let destination = stellar::parse_address("recipient_address", &escrow.recipient_address)?;
+        let escrow_account = stellar::parse_account(
+            "escrow_account_id",
+            escrow.escrow_account_id.as_deref().unwrap_or_default(),
+        )?;
+
+        let (_treasury, sequence) = self.next_treasury_sequence()?;
+
+        let transaction = stellar::build_milestone_transaction(
+            &self.stellar_config,
+            sequence,
+            escrow.id,
+            &escrow_account,
+            &destination,
+            &stellar::escrow_asset(&escrow.asset_code, escrow.asset_issuer.as_deref())?,
+            milestone.amount,
+        )?;
+        let hash = stellar::transaction_hash(&self.stellar_config.network, &transaction)?;
+
+        diesel::update(dsl::escrow_milestones.find((escrow.id, milestone.position)))
+            .set(dsl::release_tx_hash.eq(&hash))
+            .execute(conn)?;
+
+        self.submit(transaction, &hash)?;
+
+        tracing::info!(
+            escrow_id = escrow.id,
+            milestone = milestone.position,
+            amount = %milestone.amount,
+            tx_hash = %hash,
+            "Escrow milestone paid out"
+        );
+
+        Ok(hash)
+    }
+
+    /// Cancels a pending escrow, or refunds a funded one to the sender on the ledger.
+    ///
+    /// Before funding only the sender may cancel; once money is locked only
+    /// the arbiter may refund it.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, actor = %actor.uid))]
+    pub async fn cancel_and_refund(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        use crate::schema::escrows::dsl::*;
+
+        let reason = reason.or(Some("Escrow cancelled"));
+        let mut conn = self.pool.get()?;
+
+        // Only a pending escrow can be cancelled without touching the ledger
+        if find_escrow(&mut conn, _id)?.status != EscrowStatus::Pending {
+            EscrowAccess::of(actor, &load_participants(&mut conn, _id)?)
+                .require(&[ParticipantRole::Arbiter], "refund")?;
+            drop(conn);
+            return self
+                .settle(
+                    _id,
+                    EscrowStatus::Cancelled,
+                    &actor.uid,
+                    reason,
+                    expected_version,
+                )
+                .await;
         }
 
-        // Set initial status to Pending if not set
-        let mut escrow_to_create = new_escrow;
-        if escrow_to_create.status.is_empty() {
-            escrow_to_create.status = EscrowStatus::Pending.to_string();
+        conn.transaction(|conn| {
+            let escrow = lock_escrow(conn, _id, expected_version)?;
+            EscrowAccess::of(actor, &load_participants(conn, _id)?)
+                .require(&[ParticipantRole::Sender], "cancel")?;
+            let new_status = EscrowTransition::Cancel.apply(&escrow)?;
+
+            let updated = diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((
+                    status.eq(new_status),
+                    cancelled_at.eq(Utc::now()),
+                    version.eq(version + 1),
+                ))
+                .get_result(conn)
+                .optional()?
+                .ok_or_else(|| concurrent_update(_id))?;
+
+            record_transition(
+                conn,
+                _id,
+                Some(escrow.status),
+                new_status,
+                &actor.uid,
+                reason,
+            )?;
+
+            Ok(updated)
+        })
+    }
+
+    /// Applies every deadline that has passed by `now`: pending escrows past
+    /// their funding deadline expire, and funded escrows past their release
+    /// deadline are refunded to the sender. Each change is recorded as made
+    /// by `SYSTEM_ACTOR`.
+    ///
+    /// An escrow that cannot be handled is logged and left for the next run.
+    pub async fn enforce_deadlines(&self, now: DateTime<Utc>) -> Result<DeadlineRun, EscrowError> {
+        use crate::schema::escrows::dsl::*;
+
+        let mut conn = self.pool.get()?;
+        let unfunded: Vec<i32> = escrows
+            .select(id)
+            .filter(status.eq(EscrowStatus::Pending))
+            .filter(funding_deadline.le(now))
+            .order(id.asc())
+            .load(&mut conn)?;
+        let unreleased: Vec<i32> = escrows
+            .select(id)
+            .filter(status.eq_any([EscrowStatus::Funded, EscrowStatus::PartiallyReleased]))
+            .filter(release_deadline.le(now))
+            .order(id.asc())
+            .load(&mut conn)?;
+        drop(conn);
+
+        let mut run = DeadlineRun::default();
+        for escrow_id in unfunded {
+            match self.expire(escrow_id, now) {
+                Ok(true) => run.expired += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!(escrow_id, error = %e, "Could not expire escrow");
+                    run.failed += 1;
+                }
+            }
+        }
+        for escrow_id in unreleased {
+            let refund = self
+                .settle(
+                    escrow_id,
+                    EscrowStatus::Cancelled,
+                    SYSTEM_ACTOR,
+                    Some("Release deadline passed"),
+                    None,
+                )
+                .await;
+            match refund {
+                Ok(_) => run.refunded += 1,
+                Err(e) => {
+                    tracing::warn!(escrow_id, error = %e, "Could not refund escrow");
+                    run.failed += 1;
+                }
+            }
         }
 
-        // Create the escrow
-        diesel::insert_into(escrows)
-            .values(&escrow_to_create)
-            .get_result(&mut conn)
-            .map_err(|e| format!("Failed to create escrow: {}", e))
+        Ok(run)
     }
 
-    pub async fn get_escrow(&self, _id: i32) -> Result<Escrow, String> {
+    /// Expires a pending escrow whose funding deadline has passed by `now`.
+    /// Returns false if it was funded or cancelled in the meantime.
+    fn expire(&self, _id: i32, now: DateTime<Utc>) -> Result<bool, EscrowError> {
         use crate::schema::escrows::dsl::*;
 
-        let mut conn = self
-            .pool
-            .get()
-            .map_err(|e| format!("Failed to get database connection: {}", e))?;
+        let mut conn = self.pool.get()?;
 
-        escrows
-            .find(_id)
-            .first(&mut conn)
-            .map_err(|_| "Escrow not found".to_string())
+        conn.transaction(|conn| {
+            let escrow = lock_escrow(conn, _id, None)?;
+            if escrow.status != EscrowStatus::Pending
+                || escrow
+                    .funding_deadline
+                    .is_none_or(|deadline| deadline > now)
+            {
+                return Ok(false);
+            }
+            let new_status = EscrowTransition::Expire.apply(&escrow)?;
+
+            diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((
+                    status.eq(new_status),
+                    expired_at.eq(now),
+                    version.eq(version + 1),
+                ))
+                .execute(conn)?;
+
+            record_transition(
+                conn,
+                _id,
+                Some(escrow.status),
+                new_status,
+                SYSTEM_ACTOR,
+                Some("Funding deadline passed"),
+            )?;
+
+            Ok(true)
+        })
+    }
+
+    /// Brings the database in line with the ledger. Payments to the treasury
+    /// carrying an escrow's memo ID are recorded as its deposits and fund it
+    /// when they cover its loan amount (see `apply_deposit`), and whatever is
+    /// not credited is refunded. Then every escrow account with new payments
+    /// has its balance compared with the escrow's locked funds, and any
+    /// difference is recorded as a `LedgerDiscrepancy` until a later check
+    /// finds them equal again.
+    ///
+    /// Each account's history is read from where the previous run stopped,
+    /// as stored in `ledger_cursors`. An escrow account that cannot be
+    /// checked is logged and left for the next run.
+    pub async fn reconcile_ledger(&self) -> Result<ReconcileRun, EscrowError> {
+        self.blocking(|service| service.reconcile()).await
     }
 
-    pub async fn update_status(&self, _id: i32, new_status: EscrowStatus) -> Result<Escrow, String> {
+    fn reconcile(&self) -> Result<ReconcileRun, EscrowError> {
         use crate::schema::escrows::dsl::*;
 
-        let mut conn = self
-            .pool
-            .get()
-            .map_err(|e| format!("Failed to get database connection: {}", e))?;
+        let mut run = ReconcileRun::default();
+        self.reconcile_deposits(&mut run)?;
+        self.refund_deposits(&mut run)?;
 
-        // First get the current escrow
-        let current_escrow = escrows
-            .find(_id)
-            .first::<Escrow>(&mut conn)
-            .map_err(|_| "Escrow not found".to_string())?;
+        let mut conn = self.pool.get()?;
+        let accounts: Vec<(i32, Option<String>)> = escrows
+            .select((id, escrow_account_id))
+            .filter(status.eq_any(ACTIVE_STATUSES))
+            .filter(escrow_account_id.is_not_null())
+            .order(id.asc())
+            .load(&mut conn)?;
+        drop(conn);
 
-        // Validate status transition
-        match EscrowStatus::from_string(&current_escrow.status) {
-            Ok(EscrowStatus::Released) | Ok(EscrowStatus::Cancelled) => {
-                return Err("Cannot update status of completed escrow".to_string());
+        for (escrow_id, account_id) in accounts {
+            let Some(account_id) = account_id else {
+                continue;
+            };
+            match self.reconcile_escrow_account(escrow_id, &account_id) {
+                Ok(true) => run.discrepancies += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!(escrow_id, error = %e, "Could not reconcile escrow account");
+                    run.failed += 1;
+                }
             }
-            _ => {}
         }
 
-        // Update the status
-        diesel::update(escrows.find(_id))
-            .set(status.eq(new_status.to_string()))
-            .get_result(&mut conn)
-            .map_err(|e| format!("Failed to update status: {}", e))
+        Ok(run)
+    }
+
+    /// Credits the deposits paid to the treasury since the last run to the
+    /// escrows their memo IDs name.
+    ///
+    /// A transient error, such as the ledger or the database being
+    /// unreachable, stops the run before the cursor passes the deposit, so
+    /// the next run tries it again. Any other error will not go away by
+    /// retrying: the deposit is flagged as a `FailedDeposit`, owed back to
+    /// its payer in full and skipped, so one bad payment cannot stall every
+    /// later run.
+    fn reconcile_deposits(&self, run: &mut ReconcileRun) -> Result<(), EscrowError> {
+        let treasury = self.stellar_config.treasury().account_id();
+
+        loop {
+            let cursor = self.ledger_cursor(&treasury)?;
+            let page = self
+                .ledger
+                .payments(&treasury, cursor.as_deref(), PAYMENT_PAGE_SIZE)?;
+            let Some(page_end) = page.cursor else {
+                return Ok(());
+            };
+
+            for payment in &page.payments {
+                let memo_id = match payment.memo {
+                    Memo::Id(memo_id) if payment.to == treasury && payment.from != treasury => {
+                        Some(memo_id)
+                    }
+                    _ => None,
+                };
+                if let Some(memo_id) = memo_id {
+                    run.deposits += 1;
+                    match self.apply_deposit(memo_id, payment) {
+                        Ok(true) => run.funded += 1,
+                        Ok(false) => {}
+                        Err(e) if e.is_transient() => return Err(e),
+                        Err(e) => {
+                            tracing::warn!(
+                                memo_id,
+                                tx_hash = %payment.transaction_hash,
+                                error = %e,
+                                "Could not apply deposit"
+                            );
+                            run.failed += 1;
+                            if let Err(e) = self.record_failed_deposit(memo_id, payment, &e) {
+                                tracing::error!(
+                                    memo_id,
+                                    tx_hash = %payment.transaction_hash,
+                                    error = %e,
+                                    "Could not flag failed deposit"
+                                );
+                            }
+                        }
+                    }
+                }
+                self.save_ledger_cursor(&treasury, &payment.paging_token)?;
+            }
+            self.save_ledger_cursor(&treasury, &page_end)?;
+        }
     }
 
-    pub async fn lock_funds(&self, _id: i32, amount: i64) -> Result<Escrow, String> {
+    /// Records a deposit for the escrow whose memo ID it carries and, if the
+    /// escrow is pending and the deposit covers its loan amount, funds the
+    /// escrow with exactly that amount. The rest of the deposit, or all of
+    /// it if it is short or the escrow cannot be funded, is owed back to the
+    /// payer; see `refund_deposits`. Deposits in another asset are flagged as
+    /// an `UnmatchedDeposit` instead. Returns whether the escrow was funded.
+    fn apply_deposit(&self, memo_id: u64, deposit: &LedgerPayment) -> Result<bool, EscrowError> {
+        use crate::schema::escrow_deposits::dsl as deposits;
         use crate::schema::escrows::dsl::*;
 
-        let mut conn = self
-            .pool
-            .get()
-            .map_err(|e| format!("Failed to get database connection: {}", e))?;
+        let mut conn = self.pool.get()?;
+        let escrow_id: Option<i32> = match i64::try_from(memo_id) {
+            Ok(memo_id) => escrows
+                .select(id)
+                .filter(deposit_memo_id.eq(memo_id))
+                .first(&mut conn)
+                .optional()?,
+            Err(_) => None,
+        };
+        let Some(escrow_id) = escrow_id else {
+            tracing::warn!(
+                memo_id,
+                tx_hash = %deposit.transaction_hash,
+                "Deposit memo names no escrow"
+            );
+            return Ok(false);
+        };
+
+        // Funding saves its attempt like `lock_funds` does
+        let outcome = transaction_keeping_attempts(&mut conn, |conn| {
+            let escrow = lock_escrow(conn, escrow_id, None)?;
+            // Read again after a run stopped before saving its cursor
+            let seen: i64 = deposits::escrow_deposits
+                .filter(deposits::transaction_hash.eq(&deposit.transaction_hash))
+                .filter(deposits::paging_token.eq(&deposit.paging_token))
+                .count()
+                .get_result(conn)?;
+            if seen > 0 {
+                return Ok(DepositOutcome::AlreadyApplied);
+            }
+            let paid = deposit.asset_amount();
+            let due = escrow.loan();
+            let Ok(covered) = paid.compare(&due) else {
+                return Ok(DepositOutcome::Unmatched(format!(
+                    "Paid in {}, but the escrow is in {}",
+                    paid.asset.code, due.asset.code
+                )));
+            };
+
+            let refused = if escrow.status != EscrowStatus::Pending {
+                Some(format!("Escrow is {}", escrow.status.as_str()))
+            } else if covered == Ordering::Less {
+                Some(format!("Underpaid: {} of {} due", paid.amount, due))
+            } else {
+                let reason = format!("Deposit {} received", deposit.transaction_hash);
+                match self.fund(
+                    conn,
+                    escrow,
+                    &due,
+                    SYSTEM_ACTOR,
+                    &reason,
+                    Some(&deposit.transaction_hash),
+                ) {
+                    Ok(_) => None,
+                    Err(EscrowError::Validation { message, .. }) => Some(message),
+                    Err(e) => return Err(e),
+                }
+            };
+
+            let credited = match refused {
+                None => due,
+                Some(_) => AssetAmount::zero(paid.asset.clone()),
+            };
+            let refund = paid
+                .checked_sub(&credited)
+                .expect("Credits at most the amount paid");
+            let refund_reason = match refused {
+                Some(reason) => Some(reason),
+                None if refund.is_positive() => Some(format!("Overpaid by {}", refund)),
+                None => None,
+            };
+            if let Some(reason) = &refund_reason {
+                tracing::info!(
+                    escrow_id,
+                    tx_hash = %deposit.transaction_hash,
+                    refund = %refund,
+                    reason,
+                    "Deposit will be refunded"
+                );
+            }
+
+            diesel::insert_into(deposits::escrow_deposits)
+                .values(NewEscrowDeposit {
+                    escrow_id,
+                    transaction_hash: &deposit.transaction_hash,
+                    paging_token: &deposit.paging_token,
+                    from_address: &deposit.from,
+                    amount: deposit.amount,
+                    credited_amount: credited.amount,
+                    refund_amount: refund.amount,
+                    refund_reason: refund_reason.as_deref(),
+                })
+                .execute(conn)?;
+
+            Ok(if credited.is_positive() {
+                DepositOutcome::Funded
+            } else {
+                DepositOutcome::Refused
+            })
+        })?;
+
+        match outcome {
+            DepositOutcome::Funded => Ok(true),
+            DepositOutcome::AlreadyApplied | DepositOutcome::Refused => Ok(false),
+            DepositOutcome::Unmatched(detail) => {
+                let escrow = find_escrow(&mut conn, escrow_id)?;
+                tracing::warn!(
+                    escrow_id,
+                    tx_hash = %deposit.transaction_hash,
+                    amount = %deposit.amount,
+                    detail,
+                    "Deposit did not fund escrow"
+                );
+                diesel::insert_into(ledger_discrepancies::table)
+                    .values(NewLedgerDiscrepancy {
+                        escrow_id,
+                        kind: DiscrepancyKind::UnmatchedDeposit,
+                        expected_amount: escrow.loan_amount,
+                        actual_amount: deposit.amount,
+                        transaction_hash: Some(&deposit.transaction_hash),
+                        detail: &detail,
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(&mut conn)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Flags a deposit `apply_deposit` failed on for good as a
+    /// `FailedDeposit` and records it as refunded in full, since
+    /// reconciliation will not read it again. Nothing is recorded if its memo
+    /// names no escrow.
+    fn record_failed_deposit(
+        &self,
+        memo_id: u64,
+        deposit: &LedgerPayment,
+        error: &EscrowError,
+    ) -> Result<(), EscrowError> {
+        use crate::schema::escrows::dsl::*;
 
-        let escrow: Escrow = escrows
-            .find(_id)
+        let Ok(memo_id) = i64::try_from(memo_id) else {
+            return Ok(());
+        };
+        let mut conn = self.pool.get()?;
+        let escrow: Option<(i32, Money)> = escrows
+            .select((id, loan_amount))
+            .filter(deposit_memo_id.eq(memo_id))
             .first(&mut conn)
-            .map_err(|_| "Escrow not found".to_string())?;
+            .optional()?;
+        let Some((escrow_id, due)) = escrow else {
+            return Ok(());
+        };
+
+        let reason = format!("Deposit could not be applied: {}", error);
+        conn.transaction(|conn| {
+            diesel::insert_into(ledger_discrepancies::table)
+                .values(NewLedgerDiscrepancy {
+                    escrow_id,
+                    kind: DiscrepancyKind::FailedDeposit,
+                    expected_amount: due,
+                    actual_amount: deposit.amount,
+                    transaction_hash: Some(&deposit.transaction_hash),
+                    detail: &reason,
+                })
+                .execute(conn)?;
+            // Already recorded if the failure came after `apply_deposit`
+            // stored it
+            diesel::insert_into(escrow_deposits::table)
+                .values(NewEscrowDeposit {
+                    escrow_id,
+                    transaction_hash: &deposit.transaction_hash,
+                    paging_token: &deposit.paging_token,
+                    from_address: &deposit.from,
+                    amount: deposit.amount,
+                    credited_amount: Money::ZERO,
+                    refund_amount: deposit.amount,
+                    refund_reason: Some(&reason),
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Pays back every deposit refund still owed. A refund that cannot be
+    /// paid is logged and left for the next run.
+    fn refund_deposits(&self, run: &mut ReconcileRun) -> Result<(), EscrowError> {
+        use crate::schema::escrow_deposits::dsl::*;
+
+        let owed: Vec<EscrowDeposit> = escrow_deposits
+            .filter(refund_amount.gt(Money::ZERO))
+            .filter(refunded_at.is_null())
+            .order(id.asc())
+            .load(&mut self.pool.get()?)?;
 
-        if escrow.status != EscrowStatus::Pending.to_string() {
-            return Err("Escrow must be in PENDING status to lock funds".to_string());
+        for deposit in owed {
+            match self.refund_deposit(&deposit) {
+                Ok(true) => run.refunded += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        escrow_id = deposit.escrow_id,
+                        tx_hash = %deposit.transaction_hash,
+                        error = %e,
+                        "Could not refund deposit"
+                    );
+                    run.failed += 1;
+                }
+            }
         }
+        Ok(())
+    }
 
-        diesel::update(escrows.find(_id))
-            .set((
-                locked_funds.eq(amount),
-                status.eq(EscrowStatus::Funded.to_string()),
-            ))
-            .get_result(&mut conn)
-            .map_err(|e| format!("Failed to lock funds: {}", e))
+    /// Returns a deposit's refund to its payer, unless that already happened.
+    ///
+    /// Refunds of one escrow run under its row lock, one at a time. The
+    /// transaction hash is saved before submitting, so a refund that reached
+    /// the ledger despite an error is found on the next attempt rather than
+    /// paid twice.
+    fn refund_deposit(&self, deposit: &EscrowDeposit) -> Result<bool, EscrowError> {
+        use crate::schema::escrow_deposits::dsl::*;
+
+        let mut conn = self.pool.get()?;
+
+        transaction_keeping_attempts(&mut conn, |conn| {
+            let escrow = lock_escrow(conn, deposit.escrow_id, None)?;
+            let deposit: EscrowDeposit = escrow_deposits.find(deposit.id).first(conn)?;
+            if deposit.refunded_at.is_some() {
+                return Ok(false);
+            }
+
+            let mut tx_hash = None;
+            if let Some(previous) = &deposit.refund_tx_hash {
+                if let Some(transaction) = self.ledger.load_transaction(previous)? {
+                    if transaction.successful {
+                        tx_hash = Some(transaction.hash);
+                    }
+                }
+            }
+            let tx_hash = match tx_hash {
+                Some(hash) => hash,
+                None => {
+                    let payer = stellar::parse_account("from_address", &deposit.from_address)?;
+                    let (_treasury, sequence) = self.next_treasury_sequence()?;
+                    let transaction = stellar::build_deposit_refund_transaction(
+                        &self.stellar_config,
+                        sequence,
+                        escrow.id,
+                        &payer.into(),
+                        &stellar::escrow_asset(&escrow.asset_code, escrow.asset_issuer.as_deref())?,
+                        deposit.refund_amount,
+                    )?;
+                    let hash =
+                        stellar::transaction_hash(&self.stellar_config.network, &transaction)?;
+
+                    diesel::update(escrow_deposits.find(deposit.id))
+                        .set(refund_tx_hash.eq(&hash))
+                        .execute(conn)?;
+
+                    self.submit(transaction, &hash)?;
+                    hash
+                }
+            };
+
+            diesel::update(escrow_deposits.find(deposit.id))
+                .set((refund_tx_hash.eq(&tx_hash), refunded_at.eq(Utc::now())))
+                .execute(conn)?;
+
+            tracing::info!(
+                escrow_id = escrow.id,
+                deposit = %deposit.transaction_hash,
+                refund = %deposit.refund_amount,
+                tx_hash = %tx_hash,
+                "Deposit refunded"
+            );
+
+            Ok(true)
+        })
+    }
+
+    /// Reads the payments to and from an active escrow's account since the
+    /// last run and, if there are any, checks its balance against the
+    /// escrow's locked funds. Returns whether they differ.
+    fn reconcile_escrow_account(
+        &self,
+        escrow_id: i32,
+        account_id: &str,
+    ) -> Result<bool, EscrowError> {
+        let cursor = self.ledger_cursor(account_id)?;
+        let mut page_end = None;
+        loop {
+            let page = self.ledger.payments(
+                account_id,
+                page_end.as_deref().or(cursor.as_deref()),
+                PAYMENT_PAGE_SIZE,
+            )?;
+            match page.cursor {
+                Some(end) => page_end = Some(end),
+                None => break,
+            }
+        }
+        let Some(page_end) = page_end else {
+            return Ok(false);
+        };
+
+        let mut conn = self.pool.get()?;
+        let mismatch = conn.transaction(|conn| -> Result<bool, EscrowError> {
+            // Waits for a release in progress to record what it paid out
+            let escrow = lock_escrow(conn, escrow_id, None)?;
+            if !ACTIVE_STATUSES.contains(&escrow.status)
+                || escrow.escrow_account_id.as_deref() != Some(account_id)
+            {
+                return Ok(false);
+            }
+
+            let asset = stellar::escrow_asset(&escrow.asset_code, escrow.asset_issuer.as_deref())?;
+            let nothing = AssetAmount::zero(escrow.asset());
+            let held = match self.ledger.find_account(account_id)? {
+                None => nothing,
+                Some(account) if asset.is_native() => {
+                    let reserve = Money::from_units(stellar::escrow_account_reserve(&asset));
+                    AssetAmount::new(account.balance, AssetId::native())
+                        .checked_sub(&AssetAmount::new(reserve, AssetId::native()))
+                        .unwrap_or(nothing)
+                }
+                Some(account) => account
+                    .trustlines
+                    .iter()
+                    .find(|trustline| {
+                        trustline.code == escrow.asset_code
+                            && Some(&trustline.issuer) == escrow.asset_issuer.as_ref()
+                    })
+                    .map_or(nothing, |trustline| escrow.amount(trustline.balance)),
+            };
+
+            record_balance_check(conn, &escrow, held)
+        })?;
+
+        drop(conn);
+        self.save_ledger_cursor(account_id, &page_end)?;
+        Ok(mismatch)
     }
 
-    pub async fn release_funds(&self, _id: i32) -> Result<Escrow, String> {
+    /// Where reconciliation stopped reading the payments of `account_id`.
+    fn ledger_cursor(&self, account_id: &str) -> Result<Option<String>, EscrowError> {
+        use crate::schema::ledger_cursors::dsl;
+
+        Ok(dsl::ledger_cursors
+            .find(account_id)
+            .select(dsl::cursor)
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn save_ledger_cursor(&self, account_id: &str, cursor: &str) -> Result<(), EscrowError> {
+        use crate::schema::ledger_cursors::dsl;
+
+        diesel::insert_into(dsl::ledger_cursors)
+            .values((dsl::account_id.eq(account_id), dsl::cursor.eq(cursor)))
+            .on_conflict(dsl::account_id)
+            .do_update()
+            .set((dsl::cursor.eq(cursor), dsl::updated_at.eq(Utc::now())))
+            .execute(&mut self.pool.get()?)?;
+        Ok(())
+    }
+
+    /// Lets the sender or recipient dispute a funded escrow. Its funds are
+    /// frozen, releases and refunds included, until the arbiter resolves it.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, actor = %actor.uid))]
+    pub async fn open_dispute(
+        &self,
+        _id: i32,
+        request: OpenDispute,
+        actor: &AuthUser,
+        expected_version: Option<i32>,
+    ) -> Result<Dispute, EscrowError> {
         use crate::schema::escrows::dsl::*;
 
-        let mut conn = self
-            .pool
-            .get()
-            .map_err(|e| format!("Failed to get database connection: {}", e))?;
+        if request.reason.trim().is_empty() {
+            return Err(EscrowError::validation(
+                "reason",
+                "A reason must be provided",
+            ));
+        }
+        for (index, evidence) in request.evidence.iter().enumerate() {
+            if evidence.name.trim().is_empty() {
+                return Err(EscrowError::validation(
+                    "evidence",
+                    format!("Evidence {} must have a name", index + 1),
+                ));
+            }
+            if !evidence.url.starts_with("https://") && !evidence.url.starts_with("http://") {
+                return Err(EscrowError::validation(
+                    "evidence",
+                    format!("Evidence {} must be an http(s) URL", index + 1),
+                ));
+            }
+        }
 
-        let escrow: Escrow = escrows
-            .find(_id)
-            .first(&mut conn)
-            .map_err(|_| "Escrow not found".to_string())?;
+        let mut conn = self.pool.get()?;
+
+        conn.transaction(|conn| {
+            let escrow = lock_escrow(conn, _id, expected_version)?;
+            EscrowAccess::of(actor, &load_participants(conn, _id)?).require(
+                &[ParticipantRole::Sender, ParticipantRole::Recipient],
+                "dispute",
+            )?;
+            let new_status = EscrowTransition::Dispute.apply(&escrow)?;
 
-        if escrow.status != EscrowStatus::Funded.to_string() {
-            return Err("Escrow must be in FUNDED status to release funds".to_string());
+            let dispute: EscrowDispute = {
+                use crate::schema::escrow_disputes::dsl as disputes;
+                diesel::insert_into(disputes::escrow_disputes)
+                    .values((
+                        disputes::escrow_id.eq(_id),
+                        disputes::opened_by.eq(&actor.uid),
+                        disputes::reason.eq(&request.reason),
+                    ))
+                    .get_result(conn)?
+            };
+            if !request.evidence.is_empty() {
+                use crate::schema::escrow_dispute_evidence::dsl as evidence;
+                let rows: Vec<_> = (1..)
+                    .zip(&request.evidence)
+                    .map(|(position, new_evidence)| {
+                        (
+                            evidence::dispute_id.eq(dispute.id),
+                            evidence::position.eq(position),
+                            new_evidence,
+                        )
+                    })
+                    .collect();
+                diesel::insert_into(evidence::escrow_dispute_evidence)
+                    .values(rows)
+                    .execute(conn)?;
+            }
+
+            diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((status.eq(new_status), version.eq(version + 1)))
+                .execute(conn)?;
+
+            record_transition(
+                conn,
+                _id,
+                Some(escrow.status),
+                new_status,
+                &actor.uid,
+                Some(&request.reason),
+            )?;
+
+            Ok(Dispute {
+                evidence: load_evidence(conn, dispute.id)?,
+                dispute,
+            })
+        })
+    }
+
+    /// The escrow's disputes, oldest first.
+    pub async fn disputes(&self, _id: i32, actor: &AuthUser) -> Result<Vec<Dispute>, EscrowError> {
+        use crate::schema::escrow_disputes::dsl;
+
+        let mut conn = self.pool.get()?;
+
+        find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(actor, &load_participants(&mut conn, _id)?).require_view()?;
+
+        let disputes: Vec<EscrowDispute> = dsl::escrow_disputes
+            .filter(dsl::escrow_id.eq(_id))
+            .order(dsl::id.asc())
+            .load(&mut conn)?;
+        disputes
+            .into_iter()
+            .map(|dispute| {
+                Ok(Dispute {
+                    evidence: load_evidence(&mut conn, dispute.id)?,
+                    dispute,
+                })
+            })
+            .collect()
+    }
+
+    /// Where and how to pay to fund a pending escrow from any wallet: its
+    /// loan amount, to the treasury, with the escrow's memo ID.
+    pub async fn deposit_instructions(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+    ) -> Result<DepositInstructions, EscrowError> {
+        let mut conn = self.pool.get()?;
+
+        let escrow = find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(actor, &load_participants(&mut conn, _id)?).require_view()?;
+        EscrowTransition::Fund.apply(&escrow)?;
+
+        Ok(DepositInstructions {
+            escrow_id: escrow.id,
+            destination: self.stellar_config.treasury().account_id(),
+            asset_code: escrow.asset_code,
+            asset_issuer: escrow.asset_issuer,
+            amount: escrow.loan_amount,
+            memo_type: "id",
+            memo: escrow.deposit_memo_id.to_string(),
+        })
+    }
+
+    /// Deposits received for the escrow and their refunds, oldest first.
+    pub async fn deposits(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+    ) -> Result<Vec<EscrowDeposit>, EscrowError> {
+        use crate::schema::escrow_deposits::dsl;
+
+        let mut conn = self.pool.get()?;
+
+        find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(actor, &load_participants(&mut conn, _id)?).require_view()?;
+
+        Ok(dsl::escrow_deposits
+            .filter(dsl::escrow_id.eq(_id))
+            .order(dsl::id.asc())
+            .load(&mut conn)?)
+    }
+
+    /// Discrepancies reconciliation found between the escrow and the ledger,
+    /// oldest first.
+    pub async fn discrepancies(
+        &self,
+        _id: i32,
+        actor: &AuthUser,
+    ) -> Result<Vec<LedgerDiscrepancy>, EscrowError> {
+        use crate::schema::ledger_discrepancies::dsl;
+
+        let mut conn = self.pool.get()?;
+
+        find_escrow(&mut conn, _id)?;
+        EscrowAccess::of(actor, &load_participants(&mut conn, _id)?).require_view()?;
+
+        Ok(dsl::ledger_discrepancies
+            .filter(dsl::escrow_id.eq(_id))
+            .order(dsl::id.asc())
+            .load(&mut conn)?)
+    }
+
+    /// Settles a disputed escrow as the arbiter decides: everything to the
+    /// recipient, everything back to the sender, or split between them. The
+    /// decision and its justification are recorded on the dispute.
+    #[tracing::instrument(skip_all, fields(escrow_id = _id, actor = %actor.uid))]
+    pub async fn resolve_dispute(
+        &self,
+        _id: i32,
+        resolution: DisputeResolution,
+        actor: &AuthUser,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        use crate::schema::escrows::dsl::*;
+
+        let justification = resolution.justification.trim();
+        if justification.is_empty() {
+            return Err(EscrowError::validation(
+                "justification",
+                "A justification must be provided",
+            ));
         }
 
-        diesel::update(escrows.find(_id))
-            .set(status.eq(EscrowStatus::Released.to_string()))
-            .get_result(&mut conn)
-            .map_err(|e| format!("Failed to release funds: {}", e))
+        let mut conn = self.pool.get()?;
+
+        // Moving straight to Settling commits the decision: should the payout
+        // fail, the arbiter resumes it with a release or refund
+        let target = conn.transaction(|conn| -> Result<EscrowStatus, EscrowError> {
+            let escrow = lock_escrow(conn, _id, expected_version)?;
+            EscrowAccess::of(actor, &load_participants(conn, _id)?)
+                .require(&[ParticipantRole::Arbiter], "resolve disputes on")?;
+            let new_status = EscrowTransition::Resolve.apply(&escrow)?;
+
+            let locked = escrow.locked();
+            let recipient_share = match (resolution.outcome, resolution.recipient_amount) {
+                (DisputeOutcome::Release, None) => locked.clone(),
+                (DisputeOutcome::Refund, None) => AssetAmount::zero(escrow.asset()),
+                (DisputeOutcome::Split, Some(amount))
+                    if amount.is_positive()
+                        && locked.compare(&escrow.amount(amount)) == Ok(Ordering::Greater) =>
+                {
+                    escrow.amount(amount)
+                }
+                (DisputeOutcome::Split, _) => {
+                    return Err(EscrowError::validation(
+                        "recipient_amount",
+                        format!(
+                            "A split must give the recipient more than 0 and less than {}",
+                            locked
+                        ),
+                    ))
+                }
+                (_, Some(_)) => {
+                    return Err(EscrowError::validation(
+                        "recipient_amount",
+                        "Only allowed for a split",
+                    ))
+                }
+            };
+            // An asset dropped from the allowlist since funding must still
+            // be paid out, at whatever precision it was funded in
+            if let Ok(asset) = self
+                .stellar_config
+                .supported_asset(&escrow.asset_code, escrow.asset_issuer.as_deref())
+            {
+                asset.check_precision("recipient_amount", recipient_share.amount)?;
+            }
+            let sender_share = locked
+                .checked_sub(&recipient_share)
+                .expect("recipient share is at most the locked funds");
+            let target = match resolution.outcome {
+                DisputeOutcome::Refund => EscrowStatus::Cancelled,
+                DisputeOutcome::Release | DisputeOutcome::Split => EscrowStatus::Released,
+            };
+
+            {
+                use crate::schema::escrow_disputes::dsl as disputes;
+                let resolved = diesel::update(
+                    disputes::escrow_disputes
+                        .filter(disputes::escrow_id.eq(_id))
+                        .filter(disputes::resolved_at.is_null()),
+                )
+                .set((
+                    disputes::outcome.eq(resolution.outcome),
+                    disputes::recipient_amount.eq(recipient_share.amount),
+                    disputes::sender_amount.eq(sender_share.amount),
+                    disputes::justification.eq(justification),
+                    disputes::resolved_by.eq(&actor.uid),
+                    disputes::resolved_at.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+                if resolved == 0 {
+                    return Err(EscrowError::Conflict(format!(
+                        "Escrow {} has no open dispute",
+                        _id
+                    )));
+                }
+            }
+
+            diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((
+                    status.eq(new_status),
+                    settlement_target.eq(Some(target)),
+                    settlement_recipient_amount.eq((resolution.outcome == DisputeOutcome::Split)
+                        .then_some(recipient_share.amount)),
+                    version.eq(version + 1),
+                ))
+                .execute(conn)?;
+
+            record_transition(
+                conn,
+                _id,
+                Some(escrow.status),
+                new_status,
+                &actor.uid,
+                Some(&format!(
+                    "Dispute resolved ({}): {}",
+                    resolution.outcome.as_str(),
+                    justification
+                )),
+            )?;
+
+            Ok(target)
+        })?;
+
+        // Settling checks out its own connections
+        drop(conn);
+        self.settle(_id, target, &actor.uid, Some(justification), None)
+            .await
+    }
+
+    /// Moves the locked funds out of a funded escrow and into `target`
+    /// (`Released` or `Cancelled`).
+    ///
+    /// The escrow is first marked `Settling`, then the payout is submitted, and
+    /// only once the ledger confirms it is the final status committed. If the
+    /// submission fails the escrow stays `Settling`; calling this again with
+    /// the same target resumes the settlement instead of starting a new one.
+    async fn settle(
+        &self,
+        _id: i32,
+        target: EscrowStatus,
+        actor: &str,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
+        let actor = actor.to_string();
+        let reason = reason.map(str::to_string);
+        self.blocking(move |service| {
+            service.run_settlement(_id, target, &actor, reason.as_deref(), expected_version)
+        })
+        .await
     }
 
-    pub async fn cancel_and_refund(&self, _id: i32) -> Result<Escrow, String> {
+    fn run_settlement(
+        &self,
+        _id: i32,
+        target: EscrowStatus,
+        actor: &str,
+        reason: Option<&str>,
+        expected_version: Option<i32>,
+    ) -> Result<Escrow, EscrowError> {
         use crate::schema::escrows::dsl::*;
 
-        let mut conn = self
-            .pool
-            .get()
-            .map_err(|e| format!("Failed to get database connection: {}", e))?;
+        let confirm = match target {
+            EscrowStatus::Released => EscrowTransition::Release,
+            _ => EscrowTransition::Refund,
+        };
 
-        let escrow: Escrow = escrows
-            .find(_id)
-            .first(&mut conn)
-            .map_err(|_| "Escrow not found".to_string())?;
+        let mut conn = self.pool.get()?;
 
-        if escrow.status != EscrowStatus::Pending.to_string()
-            && escrow.status != EscrowStatus::Funded.to_string()
-        {
-            return Err("Cannot cancel escrow in current status".to_string());
+        let settling = conn.transaction(|conn| -> Result<Escrow, EscrowError> {
+            let escrow = lock_escrow(conn, _id, expected_version)?;
+            if escrow.status == EscrowStatus::Settling {
+                // Resuming: only allowed towards the outcome already chosen
+                confirm.guard(&escrow)?;
+                return Ok(escrow);
+            }
+
+            EscrowTransition::Settle
+                .target(escrow.status)
+                .map_err(|_| EscrowError::InvalidTransition {
+                    from: escrow.status,
+                    to: target,
+                })?;
+            let new_status = EscrowTransition::Settle.apply(&escrow)?;
+
+            let updated = diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((
+                    status.eq(new_status),
+                    settlement_target.eq(Some(target)),
+                    version.eq(version + 1),
+                ))
+                .get_result(conn)
+                .optional()?
+                .ok_or_else(|| concurrent_update(_id))?;
+
+            record_transition(conn, _id, Some(escrow.status), new_status, actor, reason)?;
+
+            Ok(updated)
+        })?;
+
+        let tx_hash = self.submit_settlement(&mut conn, &settling)?;
+
+        conn.transaction(|conn| {
+            let escrow = lock_escrow(conn, _id, None)?;
+            let new_status = confirm.apply(&escrow)?;
+            let settled_at = Some(Utc::now());
+
+            let updated = diesel::update(escrows.find(_id).filter(version.eq(escrow.version)))
+                .set((
+                    status.eq(new_status),
+                    released_at.eq(settled_at.filter(|_| new_status == EscrowStatus::Released)),
+                    cancelled_at.eq(settled_at.filter(|_| new_status == EscrowStatus::Cancelled)),
+                    locked_funds.eq(0),
+                    settlement_target.eq(None::<EscrowStatus>),
+                    settlement_recipient_amount.eq(None::<Money>),
+                    settlement_tx_hash.eq(&tx_hash),
+                    version.eq(version + 1),
+                ))
+                .get_result(conn)
+                .optional()?
+                .ok_or_else(|| concurrent_update(_id))?;
+            // A split pays out no milestone in full
+            if new_status == EscrowStatus::Released && escrow.settlement_recipient_amount.is_none()
+            {
+                mark_milestones_released(conn, _id, None, &tx_hash)?;
+            }
+
+            record_transition(
+                conn,
+                _id,
+                Some(escrow.status),
+                new_status,
+                actor,
+                Some(&format!("Ledger transaction {} confirmed", tx_hash)),
+            )?;
+
+            Ok(updated)
+        })
+    }
+
+    /// Gets the settlement of a `Settling` escrow onto the ledger and returns
+    /// the hash of the confirmed transaction.
+    ///
+    /// A previously submitted transaction that made it is reused. Otherwise a
+    /// new one is built; resubmitting can never pay out twice, because a
+    /// successful settlement merges the escrow account away.
+    fn submit_settlement(
+        &self,
+        conn: &mut PgConnection,
+        escrow: &Escrow,
+    ) -> Result<String, EscrowError> {
+        use crate::schema::escrows::dsl::*;
+
+        if let Some(previous) = &escrow.settlement_tx_hash {
+            if let Some(transaction) = self.ledger.load_transaction(previous)? {
+                if transaction.successful {
+                    return Ok(transaction.hash);
+                }
+            }
         }
 
-        diesel::update(escrows.find(_id))
-            .set((
-                status.eq(EscrowStatus::Cancelled.to_string()),
-                locked_funds.eq(0),
-            ))
-            .get_result(&mut conn)
-            .map_err(|e| format!("Failed to cancel escrow: {}", e))
+        let recipient = stellar::parse_address("recipient_address", &escrow.recipient_address)?;
+        let sender = stellar::parse_address("sender_address", &escrow.sender_address)?;
+        let payouts = match (escrow.settlement_target, escrow.settlement_recipient_amount) {
+            (_, Some(recipient_amount)) => vec![
+                (&recipient, recipient_amount),
+                (
+                    &sender,
+                    escrow
+                        .locked()
+                        .checked_sub(&escrow.amount(recipient_amount))
+                        .map_err(|_| {
+                            EscrowError::validation("recipient_amount", "Exceeds the locked funds")
+                        })?
+                        .amount,
+                ),
+            ],
+            (Some(EscrowStatus::Released), None) => vec![(&recipient, escrow.locked_funds)],
+            _ => vec![(&sender, escrow.locked_funds)],
+        };
+        let escrow_account = stellar::parse_account(
+            "escrow_account_id",
+            escrow.escrow_account_id.as_deref().unwrap_or_default(),
+        )?;
+
+        let (_treasury, sequence) = self.next_treasury_sequence()?;
+
+        let transaction = stellar::build_settlement_transaction(
+            &self.stellar_config,
+            sequence,
+            escrow.id,
+            &escrow_account,
+            &stellar::escrow_asset(&escrow.asset_code, escrow.asset_issuer.as_deref())?,
+            &payouts,
+        )?;
+        let hash = stellar::transaction_hash(&self.stellar_config.network, &transaction)?;
+
+        // Remember the attempt before submitting, so a retry can look it up
+        diesel::update(escrows.find(escrow.id))
+            .set((settlement_tx_hash.eq(&hash), version.eq(version + 1)))
+            .execute(conn)?;
+
+        self.submit(transaction, &hash)?;
+
+        tracing::info!(
+            escrow_id = escrow.id,
+            payouts = ?payouts
+                .iter()
+                .map(|(destination, amount)| (destination.account_id(), amount))
+                .collect::<Vec<_>>(),
+            tx_hash = %hash,
+            "Escrow settlement submitted"
+        );
+
+        Ok(hash)
     }
 }
+
+/// What one `EscrowService::enforce_deadlines` run did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineRun {
+    pub expired: usize,
+    pub refunded: usize,
+    /// Escrows left for the next run after an error.
+    pub failed: usize,
+}
+
+/// What one `EscrowService::reconcile_ledger` run did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReconcileRun {
+    /// Payments to the treasury naming an escrow in their memo.
+    pub deposits: usize,
+    /// Escrows those deposits funded.
+    pub funded: usize,
+    /// Deposits, or parts of them, returned to their payers.
+    pub refunded: usize,
+    /// Escrow accounts whose balance differs from the locked funds.
+    pub discrepancies: usize,
+    /// Escrow accounts and refunds left for the next run after an error, and
+    /// deposits that could never be applied and are refunded instead.
+    pub failed: usize,
+}
+
+enum DepositOutcome {
+    Funded,
+    /// Recorded, to be refunded in full.
+    Refused,
+    AlreadyApplied,
+    /// Why the deposit could not fund the escrow.
+    Unmatched(String),
+}
+
+/// Where a listing page ended: the sort key and ID of its last escrow.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum PageCursor {
+    CreatedAt(DateTime<Utc>, i32),
+    LoanAmount(Money, i32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncodedCursor {
+    sort: EscrowSort,
+    after: PageCursor,
+}
+
+impl PageCursor {
+    fn after(escrow: &Escrow, sort: EscrowSort) -> Self {
+        match sort {
+            EscrowSort::CreatedAtAsc | EscrowSort::CreatedAtDesc => {
+                PageCursor::CreatedAt(escrow.created_at, escrow.id)
+            }
+            EscrowSort::LoanAmountAsc | EscrowSort::LoanAmountDesc => {
+                PageCursor::LoanAmount(escrow.loan_amount, escrow.id)
+            }
+        }
+    }
+
+    /// Opaque to clients; it remembers the sort so it cannot be reused with
+    /// another one.
+    fn encode(self, sort: EscrowSort) -> String {
+        let cursor = EncodedCursor { sort, after: self };
+        hex::encode(serde_json::to_vec(&cursor).expect("Cursor serializes to JSON"))
+    }
+
+    fn decode(cursor: &str, sort: EscrowSort) -> Result<Self, EscrowError> {
+        let invalid = || EscrowError::validation("cursor", "Not a cursor returned by this API");
+        let decoded: EncodedCursor = hex::decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(invalid)?;
+
+        if decoded.sort != sort {
+            return Err(EscrowError::validation(
+                "cursor",
+                "Cursor was returned for a different sort",
+            ));
+        }
+        Ok(decoded.after)
+    }
+}
+
+fn find_escrow(conn: &mut PgConnection, escrow_id: i32) -> Result<Escrow, EscrowError> {
+    use crate::schema::escrows::dsl::*;
+
+    escrows
+        .find(escrow_id)
+        .first(conn)
+        .optional()?
+        .ok_or(EscrowError::NotFound(escrow_id))
+}
+
+fn load_participants(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+) -> Result<Vec<EscrowParticipant>, EscrowError> {
+    use crate::schema::escrow_participants::dsl;
+
+    Ok(dsl::escrow_participants
+        .filter(dsl::escrow_id.eq(escrow_id))
+        .order(dsl::role.asc())
+        .load(conn)?)
+}
+
+fn load_evidence(
+    conn: &mut PgConnection,
+    dispute_id: i32,
+) -> Result<Vec<DisputeEvidence>, EscrowError> {
+    use crate::schema::escrow_dispute_evidence::dsl;
+
+    Ok(dsl::escrow_dispute_evidence
+        .filter(dsl::dispute_id.eq(dispute_id))
+        .order(dsl::position.asc())
+        .load(conn)?)
+}
+
+fn load_milestones(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+) -> Result<Vec<EscrowMilestone>, EscrowError> {
+    use crate::schema::escrow_milestones::dsl;
+
+    Ok(dsl::escrow_milestones
+        .filter(dsl::escrow_id.eq(escrow_id))
+        .order(dsl::position.asc())
+        .load(conn)?)
+}
+
+fn find_milestone(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+    position: i32,
+) -> Result<EscrowMilestone, EscrowError> {
+    use crate::schema::escrow_milestones::dsl;
+
+    dsl::escrow_milestones
+        .find((escrow_id, position))
+        .first(conn)
+        .optional()?
+        .ok_or(EscrowError::MilestoneNotFound {
+            escrow_id,
+            position,
+        })
+}
+
+/// Records the sender's or recipient's approval of one milestone and tells
+/// whether both have now approved it.
+fn approve_milestone(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+    position: i32,
+    access: &EscrowAccess,
+) -> Result<bool, EscrowError> {
+    use crate::schema::escrow_milestones::dsl;
+
+    let now = Utc::now();
+    let milestone = dsl::escrow_milestones.find((escrow_id, position));
+    if access.is(ParticipantRole::Sender) {
+        diesel::update(milestone.filter(dsl::sender_approved_at.is_null()))
+            .set(dsl::sender_approved_at.eq(now))
+            .execute(conn)?;
+    }
+    if access.is(ParticipantRole::Recipient) {
+        diesel::update(milestone.filter(dsl::recipient_approved_at.is_null()))
+            .set(dsl::recipient_approved_at.eq(now))
+            .execute(conn)?;
+    }
+
+    let milestone = find_milestone(conn, escrow_id, position)?;
+    Ok(milestone.sender_approved_at.is_some() && milestone.recipient_approved_at.is_some())
+}
+
+/// Marks the milestone at `position`, or every one still unreleased, as paid
+/// out by `tx_hash`.
+fn mark_milestones_released(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+    position: Option<i32>,
+    tx_hash: &str,
+) -> Result<(), EscrowError> {
+    use crate::schema::escrow_milestones::dsl;
+
+    let mut milestones = diesel::update(dsl::escrow_milestones)
+        .filter(dsl::escrow_id.eq(escrow_id))
+        .filter(dsl::released_at.is_null())
+        .into_boxed();
+    if let Some(position) = position {
+        milestones = milestones.filter(dsl::position.eq(position));
+    }
+    milestones
+        .set((
+            dsl::released_at.eq(Utc::now()),
+            dsl::release_tx_hash.eq(tx_hash),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Records that `user_id` agrees to release the escrow, if they are its
+/// sender or recipient.
+fn approve_release(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+    user_id: &str,
+) -> Result<(), EscrowError> {
+    use crate::schema::escrow_participants::dsl;
+
+    diesel::update(
+        dsl::escrow_participants
+            .filter(dsl::escrow_id.eq(escrow_id))
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::role.eq_any([ParticipantRole::Sender, ParticipantRole::Recipient]))
+            .filter(dsl::release_approved_at.is_null()),
+    )
+    .set(dsl::release_approved_at.eq(chrono::Utc::now()))
+    .execute(conn)?;
+
+    Ok(())
+}
+
+fn both_parties_approved(conn: &mut PgConnection, escrow_id: i32) -> Result<bool, EscrowError> {
+    use crate::schema::escrow_participants::dsl;
+
+    let approvals: i64 = dsl::escrow_participants
+        .filter(dsl::escrow_id.eq(escrow_id))
+        .filter(dsl::role.eq_any([ParticipantRole::Sender, ParticipantRole::Recipient]))
+        .filter(dsl::release_approved_at.is_not_null())
+        .count()
+        .get_result(conn)?;
+
+    Ok(approvals == 2)
+}
+
+/// Opens, updates or resolves the escrow's `BalanceMismatch` after finding
+/// `held` in its account. Returns whether they differ.
+fn record_balance_check(
+    conn: &mut PgConnection,
+    escrow: &Escrow,
+    held: AssetAmount,
+) -> Result<bool, EscrowError> {
+    use crate::schema::ledger_discrepancies::dsl;
+
+    let open = dsl::ledger_discrepancies
+        .filter(dsl::escrow_id.eq(escrow.id))
+        .filter(dsl::kind.eq(DiscrepancyKind::BalanceMismatch))
+        .filter(dsl::resolved_at.is_null());
+
+    if held.compare(&escrow.locked())? == Ordering::Equal {
+        diesel::update(open)
+            .set(dsl::resolved_at.eq(Utc::now()))
+            .execute(conn)?;
+        return Ok(false);
+    }
+
+    tracing::warn!(
+        escrow_id = escrow.id,
+        locked_funds = %escrow.locked_funds,
+        held = %held.amount,
+        "Escrow account balance differs from locked funds"
+    );
+    let detail = format!(
+        "Escrow account {} holds {}, but {} are locked",
+        escrow.escrow_account_id.as_deref().unwrap_or_default(),
+        held,
+        escrow.locked_funds
+    );
+    let updated = diesel::update(open)
+        .set((
+            dsl::expected_amount.eq(escrow.locked_funds),
+            dsl::actual_amount.eq(held.amount),
+            dsl::detail.eq(&detail),
+        ))
+        .execute(conn)?;
+    if updated == 0 {
+        diesel::insert_into(dsl::ledger_discrepancies)
+            .values(NewLedgerDiscrepancy {
+                escrow_id: escrow.id,
+                kind: DiscrepancyKind::BalanceMismatch,
+                expected_amount: escrow.locked_funds,
+                actual_amount: held.amount,
+                transaction_hash: None,
+                detail: &detail,
+            })
+            .execute(conn)?;
+    }
+    Ok(true)
+}
+
+/// Runs `f` in a transaction on `conn`, except that a ledger error commits
+/// what `f` wrote before it is returned. Operations that save a transaction
+/// hash before submitting it keep the hash for a retry that way, on the
+/// connection that holds the row lock rather than a second one from the pool.
+fn transaction_keeping_attempts<T>(
+    conn: &mut PgConnection,
+    f: impl FnOnce(&mut PgConnection) -> Result<T, EscrowError>,
+) -> Result<T, EscrowError> {
+    conn.transaction(|conn| match f(conn) {
+        Err(e @ EscrowError::Ledger(_)) => Ok(Err(e)),
+        result => result.map(Ok),
+    })?
+}
+
+/// Loads an escrow and locks its row until the surrounding transaction ends,
+/// so concurrent operations on one escrow run one after the other. Fails if
+/// the client expected a different version.
+fn lock_escrow(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+    expected_version: Option<i32>,
+) -> Result<Escrow, EscrowError> {
+    use crate::schema::escrows::dsl::*;
+
+    let escrow: Escrow = escrows
+        .find(escrow_id)
+        .for_update()
+        .first(conn)
+        .optional()?
+        .ok_or(EscrowError::NotFound(escrow_id))?;
+
+    match expected_version {
+        Some(expected) if expected != escrow.version => Err(EscrowError::VersionMismatch {
+            expected,
+            actual: escrow.version,
+        }),
+        _ => Ok(escrow),
+    }
+}
+
+/// Returned when a conditional update finds the escrow at another version.
+fn concurrent_update(escrow_id: i32) -> EscrowError {
+    EscrowError::Conflict(format!("Escrow {} was modified concurrently", escrow_id))
+}
+
+/// Appends a row to `escrow_status_history`. Must run in the same database
+/// transaction as the status change it describes.
+fn record_transition(
+    conn: &mut PgConnection,
+    escrow_id: i32,
+    from_status: Option<EscrowStatus>,
+    to_status: EscrowStatus,
+    actor: &str,
+    reason: Option<&str>,
+) -> Result<(), EscrowError> {
+    use crate::schema::escrow_status_history;
+
+    diesel::insert_into(escrow_status_history::table)
+        .values(NewEscrowStatusHistory {
+            escrow_id,
+            from_status,
+            to_status,
+            actor,
+            reason,
+        })
+        .execute(conn)?;
+
+    tracing::info!(
+        escrow_id,
+        from = from_status.map(|status| status.as_str()),
+        to = to_status.as_str(),
+        actor,
+        "Escrow status changed"
+    );
+
+    Ok(())
+}
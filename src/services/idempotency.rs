@@ -0,0 +1,141 @@
+use crate::db::DbPool;
+use crate::errors::escrow::EscrowError;
+use crate::models::idempotency_key::{IdempotencyKey, NewIdempotencyKey};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+
+/// How long a key is remembered. After that it can be used for a new request,
+/// and `purge_expired` deletes it.
+pub const KEY_TTL_HOURS: i64 = 24;
+
+/// What to do with a request that carries an `Idempotency-Key`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdempotencyOutcome {
+    /// First request with this key: run it, then `complete` or `abandon` the key.
+    Started,
+    /// The key already produced a response; send it again.
+    Replay {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
+    },
+}
+
+pub struct IdempotencyService {
+    pool: DbPool,
+}
+
+impl IdempotencyService {
+    pub fn new(pool: DbPool) -> Self {
+        IdempotencyService { pool }
+    }
+
+    /// Claims `caller`'s `key` for the request identified by `hash`, or
+    /// returns the response recorded for it. Other callers' keys are never
+    /// seen, and an expired key is claimed afresh.
+    ///
+    /// Reusing a key for a different request, or while its first request is
+    /// still running, is a conflict.
+    pub async fn begin(
+        &self,
+        caller: &str,
+        key: &str,
+        hash: &str,
+    ) -> Result<IdempotencyOutcome, EscrowError> {
+        use crate::schema::idempotency_keys::dsl::*;
+
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(idempotency_keys.find((caller, key)))
+            .filter(created_at.le(expiry(Utc::now())))
+            .execute(&mut conn)?;
+        let claimed = diesel::insert_into(idempotency_keys)
+            .values(NewIdempotencyKey {
+                caller_uid: caller,
+                idempotency_key: key,
+                request_hash: hash,
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+        if claimed == 1 {
+            return Ok(IdempotencyOutcome::Started);
+        }
+
+        let existing: IdempotencyKey = idempotency_keys.find((caller, key)).first(&mut conn)?;
+        if existing.request_hash != hash {
+            return Err(EscrowError::Conflict(
+                "Idempotency-Key was already used for a different request".to_string(),
+            ));
+        }
+
+        match (existing.response_status, existing.response_body) {
+            (Some(status), Some(body)) => Ok(IdempotencyOutcome::Replay {
+                status: status as u16,
+                // Stored by `complete`, so always valid
+                headers: existing
+                    .response_headers
+                    .and_then(|headers| serde_json::from_str(&headers).ok())
+                    .unwrap_or_default(),
+                body,
+            }),
+            _ => Err(EscrowError::Conflict(
+                "A request with this Idempotency-Key is still in progress".to_string(),
+            )),
+        }
+    }
+
+    /// Stores the response of the request that claimed `caller`'s `key`,
+    /// with the `headers` to replay alongside it.
+    pub async fn complete(
+        &self,
+        caller: &str,
+        key: &str,
+        status: u16,
+        headers: &[(String, String)],
+        body: &str,
+    ) -> Result<(), EscrowError> {
+        use crate::schema::idempotency_keys::dsl::*;
+
+        let mut conn = self.pool.get()?;
+
+        diesel::update(idempotency_keys.find((caller, key)))
+            .set((
+                response_status.eq(status as i32),
+                response_headers.eq(serde_json::to_string(headers).ok()),
+                response_body.eq(body),
+                completed_at.eq(Utc::now()),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    /// Releases `caller`'s `key` without a response, so the client can retry
+    /// with it.
+    pub async fn abandon(&self, caller: &str, key: &str) -> Result<(), EscrowError> {
+        use crate::schema::idempotency_keys::dsl::*;
+
+        let mut conn = self.pool.get()?;
+
+        diesel::delete(idempotency_keys.find((caller, key))).execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes every key that has expired by `now` and returns how many.
+    pub async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, EscrowError> {
+        use crate::schema::idempotency_keys::dsl::*;
+
+        let mut conn = self.pool.get()?;
+
+        let purged = diesel::delete(idempotency_keys.filter(created_at.le(expiry(now))))
+            .execute(&mut conn)?;
+
+        Ok(purged)
+    }
+}
+
+/// Keys created at or before this have expired by `now`.
+fn expiry(now: DateTime<Utc>) -> DateTime<Utc> {
+    now - Duration::hours(KEY_TTL_HOURS)
+}
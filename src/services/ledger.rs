@@ -0,0 +1,662 @@
+use crate::errors::escrow::EscrowError;
+use crate::models::money::{AssetAmount, AssetId, Money};
+use crate::services::stellar;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Mutex;
+use stellar_base::crypto::MuxedAccount;
+use stellar_base::transaction::Transaction;
+use stellar_base::{Asset, Memo, Network, Operation};
+use stellar_sdk::utils::{Direction, Endpoint};
+use stellar_sdk::{CallBuilder, Server};
+
+/// What the backend needs to know about an account on the ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerAccount {
+    pub account_id: String,
+    pub sequence: i64,
+    /// Native (XLM) balance.
+    pub balance: Money,
+    pub trustlines: Vec<Trustline>,
+}
+
+/// An account's holding of an issued asset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trustline {
+    pub code: String,
+    pub issuer: String,
+    pub balance: Money,
+    /// Whether the issuer lets the account receive the asset.
+    pub authorized: bool,
+}
+
+/// A transaction the ledger has seen, and whether it was applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerTransaction {
+    pub hash: String,
+    pub successful: bool,
+}
+
+/// A payment or account creation in an account's history. Only payments of
+/// successful transactions are listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerPayment {
+    /// Position in the history; pass it back as the cursor to read on.
+    pub paging_token: String,
+    pub transaction_hash: String,
+    /// The transaction's memo. Hash and return memos, which escrows do not
+    /// use, read as `Memo::None`.
+    pub memo: Memo,
+    pub from: String,
+    /// The `G...` account paid, even when the payment was to a muxed address.
+    pub to: String,
+    /// `XLM` for the native asset, which has no issuer.
+    pub asset_code: String,
+    pub asset_issuer: Option<String>,
+    pub amount: Money,
+}
+
+impl LedgerPayment {
+    pub fn asset_amount(&self) -> AssetAmount {
+        AssetAmount::new(
+            self.amount,
+            AssetId::new(&self.asset_code, self.asset_issuer.as_deref()),
+        )
+    }
+}
+
+/// One page of an account's payment history, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaymentPage {
+    pub payments: Vec<LedgerPayment>,
+    /// Where the page ends; `None` if it is empty. It can be past the last
+    /// payment, as other operations in the history are skipped.
+    pub cursor: Option<String>,
+}
+
+/// The operations `EscrowService` performs against the Stellar network.
+///
+/// Calls block until the network answers, like the Horizon client underneath.
+pub trait LedgerClient: Send + Sync {
+    /// Looks up an account; `None` if it does not exist (yet).
+    fn find_account(&self, account_id: &str) -> Result<Option<LedgerAccount>, EscrowError>;
+
+    /// Like `find_account`, for accounts that must exist.
+    fn load_account(&self, account_id: &str) -> Result<LedgerAccount, EscrowError> {
+        self.find_account(account_id)?
+            .ok_or_else(|| EscrowError::Ledger(format!("Account {} not found", account_id)))
+    }
+
+    /// Submits a signed transaction and returns the ledger's verdict on it.
+    fn submit_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<LedgerTransaction, EscrowError>;
+
+    /// Looks up a transaction by hash; `None` if the ledger has never seen it.
+    fn load_transaction(&self, hash: &str) -> Result<Option<LedgerTransaction>, EscrowError>;
+
+    /// Reads up to `limit` entries of the payment history of `account_id`
+    /// following `cursor`, or from the start without one. Empty if the
+    /// account does not exist.
+    fn payments(
+        &self,
+        account_id: &str,
+        cursor: Option<&str>,
+        limit: u8,
+    ) -> Result<PaymentPage, EscrowError>;
+}
+
+/// `LedgerClient` backed by a Horizon server.
+pub struct HorizonLedger {
+    server: Server,
+}
+
+impl HorizonLedger {
+    pub fn new(horizon_url: &str) -> Result<Self, EscrowError> {
+        Ok(Self {
+            server: Server::new(horizon_url.to_string(), None).map_err(EscrowError::ledger)?,
+        })
+    }
+}
+
+impl LedgerClient for HorizonLedger {
+    fn find_account(&self, account_id: &str) -> Result<Option<LedgerAccount>, EscrowError> {
+        let account = match self.server.load_account(account_id) {
+            Ok(account) => account,
+            Err(err) if is_not_found(&err) => return Ok(None),
+            Err(err) => return Err(EscrowError::ledger(err)),
+        };
+
+        let mut balance = Money::ZERO;
+        let mut trustlines = Vec::new();
+        for held in &account.balances {
+            let amount = Money::from_str(&held.balance).map_err(EscrowError::ledger)?;
+            match (&held.asset_code, &held.asset_issuer) {
+                (Some(code), Some(issuer)) => trustlines.push(Trustline {
+                    code: code.clone(),
+                    issuer: issuer.clone(),
+                    balance: amount,
+                    authorized: held.is_authorized.unwrap_or(true),
+                }),
+                _ if held.asset_type == "native" => balance = amount,
+                // Liquidity pool shares
+                _ => {}
+            }
+        }
+
+        Ok(Some(LedgerAccount {
+            sequence: account.sequence.parse().map_err(EscrowError::ledger)?,
+            account_id: account.account_id,
+            balance,
+            trustlines,
+        }))
+    }
+
+    fn submit_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<LedgerTransaction, EscrowError> {
+        let response = self
+            .server
+            .submit_transaction(transaction)
+            .map_err(EscrowError::ledger)?;
+
+        Ok(LedgerTransaction {
+            hash: response.hash,
+            successful: response.successful,
+        })
+    }
+
+    fn load_transaction(&self, hash: &str) -> Result<Option<LedgerTransaction>, EscrowError> {
+        match self.server.load_transaction(hash) {
+            Ok(transaction) => Ok(Some(LedgerTransaction {
+                hash: transaction.hash,
+                successful: transaction.successful,
+            })),
+            Err(err) if is_not_found(&err) => Ok(None),
+            Err(err) => Err(EscrowError::ledger(err)),
+        }
+    }
+
+    fn payments(
+        &self,
+        account_id: &str,
+        cursor: Option<&str>,
+        limit: u8,
+    ) -> Result<PaymentPage, EscrowError> {
+        let mut call = self.server.payments();
+        call.for_endpoint(Endpoint::Accounts(account_id.to_string()))
+            .order(Direction::Asc)
+            .limit(limit);
+        if let Some(cursor) = cursor {
+            call.cursor(cursor);
+        }
+        let records = match call.call() {
+            Ok(page) => page._embedded.records,
+            Err(err) if is_not_found(&err) => return Ok(PaymentPage::default()),
+            Err(err) => return Err(EscrowError::ledger(err)),
+        };
+
+        let mut page = PaymentPage {
+            payments: Vec::new(),
+            cursor: records.last().map(|record| record.paging_token.clone()),
+        };
+        // Operation records leave out the memo, which is on the transaction
+        let mut memos: HashMap<String, Memo> = HashMap::new();
+        for record in records {
+            let (from, to, amount) = match record.r#type.as_str() {
+                "payment" => (record.from, record.to, record.amount),
+                "create_account" => (record.funder, record.account, record.starting_balance),
+                // Path payments and merges are not made by escrows
+                _ => continue,
+            };
+            if !record.transaction_successful {
+                continue;
+            }
+            let (Some(from), Some(to), Some(amount)) = (from, to, amount) else {
+                return Err(EscrowError::Ledger(format!(
+                    "Payment {} is missing its parties or amount",
+                    record.id
+                )));
+            };
+
+            let memo = match memos.get(&record.transaction_hash) {
+                Some(memo) => memo.clone(),
+                None => {
+                    let transaction = self
+                        .server
+                        .load_transaction(&record.transaction_hash)
+                        .map_err(EscrowError::ledger)?;
+                    let memo = match (transaction.memo_type.as_str(), transaction.memo) {
+                        ("text", Some(text)) => Memo::Text(text),
+                        ("id", Some(id)) => Memo::Id(id.parse().map_err(EscrowError::ledger)?),
+                        _ => Memo::None,
+                    };
+                    memos.insert(record.transaction_hash.clone(), memo.clone());
+                    memo
+                }
+            };
+
+            page.payments.push(LedgerPayment {
+                paging_token: record.paging_token,
+                transaction_hash: record.transaction_hash,
+                memo,
+                from,
+                to,
+                asset_code: record
+                    .asset_code
+                    .unwrap_or_else(|| stellar::NATIVE_ASSET_CODE.to_string()),
+                asset_issuer: record.asset_issuer,
+                amount: Money::from_str(&amount).map_err(EscrowError::ledger)?,
+            });
+        }
+
+        Ok(page)
+    }
+}
+
+/// The SDK only exposes Horizon's problem document through its Debug output.
+fn is_not_found(err: &impl std::fmt::Debug) -> bool {
+    format!("{:?}", err).contains("status: 404")
+}
+
+/// A failure `InMemoryLedger` injects into the next submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulatedFailure {
+    /// The request never reaches the ledger: nothing is applied or recorded.
+    Unreachable,
+    /// The ledger rejects the transaction: it is recorded as failed and
+    /// consumes the source account's sequence number, as on Stellar.
+    Rejected,
+    /// The transaction is applied, but the response never arrives, as when
+    /// Horizon times out after submitting it.
+    ResponseLost,
+}
+
+/// Deterministic, in-process stand-in for the Stellar network.
+///
+/// Tracks native balances, trustlines and sequence numbers and applies the
+/// operations the backend builds (create account, change trust, set options,
+/// payment, account merge) atomically, and lists the payments and account
+/// creations of successful transactions in each account's history. Issuers have unlimited supply of their
+/// own assets and trustlines are authorized as soon as they are opened. Signatures are not verified and no fees are charged, so
+/// balances only move by the amounts in the operations. New accounts start
+/// at sequence 0.
+pub struct InMemoryLedger {
+    network: Network,
+    state: Mutex<LedgerState>,
+}
+
+#[derive(Default)]
+struct LedgerState {
+    accounts: HashMap<String, LedgerAccount>,
+    transactions: HashMap<String, LedgerTransaction>,
+    /// Every payment applied, oldest first; paging tokens are positions here.
+    payments: Vec<LedgerPayment>,
+    failures: VecDeque<SimulatedFailure>,
+}
+
+impl InMemoryLedger {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            state: Mutex::new(LedgerState::default()),
+        }
+    }
+
+    /// Creates `account_id` holding `balance` XLM, or tops it up if it exists.
+    pub fn fund_account(&self, account_id: &str, balance: Money) {
+        let mut state = self.state.lock().unwrap();
+        let account = state
+            .accounts
+            .entry(account_id.to_string())
+            .or_insert_with(|| LedgerAccount {
+                account_id: account_id.to_string(),
+                sequence: 0,
+                balance: Money::ZERO,
+                trustlines: Vec::new(),
+            });
+        account.balance = add(account.balance, balance).expect("balance to fit");
+    }
+
+    /// Gives `account_id` a trustline for `code` issued by `issuer` holding
+    /// `balance`, or tops the trustline up. The account must exist.
+    pub fn fund_trustline(&self, account_id: &str, code: &str, issuer: &str, balance: Money) {
+        let mut state = self.state.lock().unwrap();
+        let account = state
+            .accounts
+            .get_mut(account_id)
+            .expect("account to exist");
+        match account
+            .trustlines
+            .iter_mut()
+            .find(|trustline| trustline.code == code && trustline.issuer == issuer)
+        {
+            Some(trustline) => {
+                trustline.balance = add(trustline.balance, balance).expect("balance to fit")
+            }
+            None => account.trustlines.push(Trustline {
+                code: code.to_string(),
+                issuer: issuer.to_string(),
+                balance,
+                authorized: true,
+            }),
+        }
+    }
+
+    pub fn account(&self, account_id: &str) -> Option<LedgerAccount> {
+        self.state.lock().unwrap().accounts.get(account_id).cloned()
+    }
+
+    /// Makes the next submission fail; queued failures apply in order.
+    pub fn fail_next_submission(&self, failure: SimulatedFailure) {
+        self.state.lock().unwrap().failures.push_back(failure);
+    }
+}
+
+impl LedgerClient for InMemoryLedger {
+    fn find_account(&self, account_id: &str) -> Result<Option<LedgerAccount>, EscrowError> {
+        Ok(self.account(account_id))
+    }
+
+    fn submit_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<LedgerTransaction, EscrowError> {
+        let hash = stellar::transaction_hash(&self.network, &transaction)?;
+        let mut state = self.state.lock().unwrap();
+
+        let failure = state.failures.pop_front();
+        if failure == Some(SimulatedFailure::Unreachable) {
+            return Err(EscrowError::Ledger("Ledger unreachable".to_string()));
+        }
+
+        let source = holder(transaction.source_account());
+        let expected_sequence = match state.accounts.get(&source) {
+            Some(account) => account.sequence + 1,
+            None => return Err(EscrowError::Ledger("tx_no_source_account".to_string())),
+        };
+        if *transaction.sequence() != expected_sequence {
+            return Err(EscrowError::Ledger("tx_bad_seq".to_string()));
+        }
+
+        let successful = if failure == Some(SimulatedFailure::Rejected) {
+            false
+        } else {
+            match apply_operations(&state.accounts, &transaction) {
+                Ok(accounts) => {
+                    state.accounts = accounts;
+                    for mut payment in payment_records(&hash, &transaction) {
+                        payment.paging_token = (state.payments.len() + 1).to_string();
+                        state.payments.push(payment);
+                    }
+                    true
+                }
+                Err(reason) => {
+                    tracing::info!(hash, reason, "In-memory ledger rejected transaction");
+                    false
+                }
+            }
+        };
+
+        if let Some(account) = state.accounts.get_mut(&source) {
+            account.sequence = expected_sequence;
+        }
+
+        let result = LedgerTransaction { hash, successful };
+        state
+            .transactions
+            .insert(result.hash.clone(), result.clone());
+        if failure == Some(SimulatedFailure::ResponseLost) {
+            return Err(EscrowError::Ledger("Response lost".to_string()));
+        }
+        Ok(result)
+    }
+
+    fn load_transaction(&self, hash: &str) -> Result<Option<LedgerTransaction>, EscrowError> {
+        Ok(self.state.lock().unwrap().transactions.get(hash).cloned())
+    }
+
+    fn payments(
+        &self,
+        account_id: &str,
+        cursor: Option<&str>,
+        limit: u8,
+    ) -> Result<PaymentPage, EscrowError> {
+        let after = match cursor {
+            Some(cursor) => cursor
+                .parse::<usize>()
+                .map_err(|_| EscrowError::Ledger(format!("Invalid cursor {}", cursor)))?,
+            None => 0,
+        };
+        let state = self.state.lock().unwrap();
+        let payments: Vec<LedgerPayment> = state
+            .payments
+            .iter()
+            .skip(after)
+            .filter(|payment| payment.from == account_id || payment.to == account_id)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+
+        Ok(PaymentPage {
+            cursor: payments.last().map(|payment| payment.paging_token.clone()),
+            payments,
+        })
+    }
+}
+
+/// Applies every operation of `transaction` to a copy of `accounts`, so a
+/// failing operation leaves the ledger untouched.
+fn apply_operations(
+    accounts: &HashMap<String, LedgerAccount>,
+    transaction: &Transaction,
+) -> Result<HashMap<String, LedgerAccount>, String> {
+    let mut accounts = accounts.clone();
+    let transaction_source = holder(transaction.source_account());
+
+    for operation in transaction.operations() {
+        let source = operation
+            .source_account()
+            .as_ref()
+            .map(holder)
+            .unwrap_or_else(|| transaction_source.clone());
+
+        match operation {
+            Operation::CreateAccount(create) => {
+                let destination = create.destination().account_id();
+                if accounts.contains_key(&destination) {
+                    return Err("op_already_exists".to_string());
+                }
+                let starting_balance = Money::from(*create.starting_balance());
+                debit(&mut accounts, &source, starting_balance)?;
+                accounts.insert(
+                    destination.clone(),
+                    LedgerAccount {
+                        account_id: destination,
+                        sequence: 0,
+                        balance: starting_balance,
+                        trustlines: Vec::new(),
+                    },
+                );
+            }
+            Operation::ChangeTrust(change) => {
+                let (code, issuer) = match change.asset() {
+                    Asset::Credit(credit) => {
+                        (credit.code().to_string(), credit.issuer().account_id())
+                    }
+                    Asset::Native => return Err("op_malformed".to_string()),
+                };
+                let account = accounts.get_mut(&source).ok_or("op_no_account")?;
+                let existing = account
+                    .trustlines
+                    .iter()
+                    .position(|trustline| trustline.code == code && trustline.issuer == issuer);
+                let limit = change.limit().as_ref().map_or(0, |limit| limit.to_i64());
+                match (existing, limit) {
+                    (Some(index), 0) => {
+                        if account.trustlines[index].balance != Money::ZERO {
+                            return Err("op_invalid_limit".to_string());
+                        }
+                        account.trustlines.remove(index);
+                    }
+                    (Some(_), _) => {}
+                    (None, 0) => return Err("op_no_trust".to_string()),
+                    (None, _) => account.trustlines.push(Trustline {
+                        code,
+                        issuer,
+                        balance: Money::ZERO,
+                        authorized: true,
+                    }),
+                }
+            }
+            Operation::SetOptions(_) => {}
+            Operation::Payment(payment) => {
+                let amount = Money::from(*payment.amount());
+                let destination = holder(payment.destination());
+                if !accounts.contains_key(&destination) {
+                    return Err("op_no_destination".to_string());
+                }
+                match payment.asset() {
+                    Asset::Native => {
+                        debit(&mut accounts, &source, amount)?;
+                        let destination = accounts.get_mut(&destination).unwrap();
+                        destination.balance = add(destination.balance, amount)?;
+                    }
+                    Asset::Credit(credit) => {
+                        let code = credit.code().to_string();
+                        let issuer = credit.issuer().account_id();
+                        if source != issuer {
+                            let held = trustline(&mut accounts, &source, &code, &issuer)
+                                .ok_or("op_src_no_trust")?;
+                            held.balance = subtract(held.balance, amount)?;
+                        }
+                        if destination != issuer {
+                            let held = trustline(&mut accounts, &destination, &code, &issuer)
+                                .ok_or("op_no_trust")?;
+                            held.balance = add(held.balance, amount)?;
+                        }
+                    }
+                }
+            }
+            Operation::AccountMerge(merge) => {
+                let merged = accounts.remove(&source).ok_or("op_no_account")?;
+                if !merged.trustlines.is_empty() {
+                    return Err("op_has_sub_entries".to_string());
+                }
+                let destination = accounts
+                    .get_mut(&holder(merge.destination()))
+                    .ok_or("op_no_destination")?;
+                destination.balance = add(destination.balance, merged.balance)?;
+            }
+            other => return Err(format!("unsupported operation {:?}", other)),
+        }
+    }
+
+    Ok(accounts)
+}
+
+/// The payments and account creations in `transaction`, once applied, without
+/// their paging tokens.
+fn payment_records(hash: &str, transaction: &Transaction) -> Vec<LedgerPayment> {
+    let memo = match transaction.memo() {
+        memo @ (Memo::Text(_) | Memo::Id(_)) => memo.clone(),
+        _ => Memo::None,
+    };
+    let transaction_source = holder(transaction.source_account());
+
+    transaction
+        .operations()
+        .iter()
+        .filter_map(|operation| {
+            let from = operation
+                .source_account()
+                .as_ref()
+                .map(holder)
+                .unwrap_or_else(|| transaction_source.clone());
+            let (to, asset, amount) = match operation {
+                Operation::CreateAccount(create) => (
+                    create.destination().account_id(),
+                    &Asset::Native,
+                    *create.starting_balance(),
+                ),
+                Operation::Payment(payment) => (
+                    holder(payment.destination()),
+                    payment.asset(),
+                    *payment.amount(),
+                ),
+                _ => return None,
+            };
+            let (asset_code, asset_issuer) = match asset {
+                Asset::Native => (stellar::NATIVE_ASSET_CODE.to_string(), None),
+                Asset::Credit(credit) => (
+                    credit.code().to_string(),
+                    Some(credit.issuer().account_id()),
+                ),
+            };
+            Some(LedgerPayment {
+                paging_token: String::new(),
+                transaction_hash: hash.to_string(),
+                memo: memo.clone(),
+                from,
+                to,
+                asset_code,
+                asset_issuer,
+                amount: Money::from(amount),
+            })
+        })
+        .collect()
+}
+
+/// The account behind a possibly muxed address, which holds its balances.
+fn holder(account: &MuxedAccount) -> String {
+    match account {
+        MuxedAccount::Ed25519(account) => account.account_id(),
+        MuxedAccount::MuxedEd25519(muxed) => muxed.public_key().account_id(),
+    }
+}
+
+fn trustline<'a>(
+    accounts: &'a mut HashMap<String, LedgerAccount>,
+    account_id: &str,
+    code: &str,
+    issuer: &str,
+) -> Option<&'a mut Trustline> {
+    accounts
+        .get_mut(account_id)?
+        .trustlines
+        .iter_mut()
+        .find(|trustline| trustline.code == code && trustline.issuer == issuer)
+}
+
+fn debit(
+    accounts: &mut HashMap<String, LedgerAccount>,
+    account_id: &str,
+    amount: Money,
+) -> Result<(), String> {
+    let account = accounts.get_mut(account_id).ok_or("op_no_account")?;
+    account.balance = subtract(account.balance, amount)?;
+    Ok(())
+}
+
+// Each balance slot, an account's XLM or one trustline, holds a single asset,
+// and operations only move an asset into the slot holding it, so the
+// arithmetic below is on plain units.
+
+fn add(balance: Money, amount: Money) -> Result<Money, String> {
+    balance
+        .units()
+        .checked_add(amount.units())
+        .map(Money::from_units)
+        .ok_or_else(|| "op_line_full".to_string())
+}
+
+fn subtract(balance: Money, amount: Money) -> Result<Money, String> {
+    balance
+        .units()
+        .checked_sub(amount.units())
+        .filter(|remaining| *remaining >= 0)
+        .map(Money::from_units)
+        .ok_or_else(|| "op_underfunded".to_string())
+}
@@ -1 +1,9 @@
-pub mod escrow;
\ No newline at end of file
+pub mod auth;
+pub mod authorization;
+pub mod escrow;
+pub mod idempotency;
+pub mod ledger;
+pub mod scheduler;
+pub mod signing;
+pub mod state_machine;
+pub mod stellar;
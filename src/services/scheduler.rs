@@ -0,0 +1,86 @@
+use crate::services::escrow::EscrowService;
+use crate::services::idempotency::IdempotencyService;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
+
+/// Enforces escrow deadlines every `interval` for as long as the server runs.
+///
+/// Several servers may run the scheduler at once: each escrow is changed
+/// under its row lock, so only one of them acts on it.
+pub fn spawn_deadline_scheduler(
+    escrow_service: Arc<EscrowService>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+            match escrow_service.enforce_deadlines(Utc::now()).await {
+                Ok(run) if run == Default::default() => {}
+                Ok(run) => tracing::info!(
+                    expired = run.expired,
+                    refunded = run.refunded,
+                    failed = run.failed,
+                    "Escrow deadlines enforced"
+                ),
+                Err(e) => tracing::error!(error = %e, "Escrow deadline check failed"),
+            }
+        }
+    })
+}
+
+/// Reconciles escrows with the ledger every `interval` for as long as the
+/// server runs; see `EscrowService::reconcile_ledger`.
+///
+/// Safe to run on several servers: escrows are changed under their row lock,
+/// and reading the same payments twice has no further effect.
+pub fn spawn_ledger_reconciler(
+    escrow_service: Arc<EscrowService>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+            match escrow_service.reconcile_ledger().await {
+                Ok(run) if run == Default::default() => {}
+                Ok(run) => tracing::info!(
+                    deposits = run.deposits,
+                    funded = run.funded,
+                    discrepancies = run.discrepancies,
+                    failed = run.failed,
+                    "Escrows reconciled with the ledger"
+                ),
+                Err(e) => tracing::error!(error = %e, "Ledger reconciliation failed"),
+            }
+        }
+    })
+}
+
+/// Deletes expired idempotency keys every `interval` for as long as the
+/// server runs. Harmless to run on several servers at once.
+pub fn spawn_idempotency_key_purge(
+    idempotency_service: Arc<IdempotencyService>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+            match idempotency_service.purge_expired(Utc::now()).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "Expired idempotency keys purged"),
+                Err(e) => tracing::error!(error = %e, "Idempotency key purge failed"),
+            }
+        }
+    })
+}
@@ -0,0 +1,242 @@
+use crate::errors::auth::AuthError;
+use crate::services::auth::{AuthUser, Role};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub const KEY_ID_HEADER: &str = "x-trustbridge-key-id";
+pub const TIMESTAMP_HEADER: &str = "x-trustbridge-timestamp";
+pub const NONCE_HEADER: &str = "x-trustbridge-nonce";
+pub const SIGNATURE_HEADER: &str = "x-trustbridge-signature";
+
+/// How far a request's timestamp may be from our clock, in seconds.
+pub const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// Key ID used when `API_SECRET_KEY` holds a single unnamed secret.
+pub const DEFAULT_KEY_ID: &str = "default";
+
+const MAX_NONCE_LENGTH: usize = 128;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The signed parts of a service request, as read off the wire.
+pub struct SignedRequest<'a> {
+    pub key_id: &'a str,
+    pub timestamp: &'a str,
+    pub nonce: &'a str,
+    pub signature: &'a str,
+    pub method: &'a str,
+    /// Path and query string, exactly as sent.
+    pub path: &'a str,
+    pub body: &'a [u8],
+}
+
+/// The string a service request's HMAC is computed over: method, path,
+/// timestamp, nonce and the hex SHA-256 of the body, one per line.
+pub fn canonical_request(
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &[u8],
+) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method.to_uppercase(),
+        path,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body))
+    )
+}
+
+/// Hex HMAC-SHA256 of the canonical request. What callers send in
+/// `X-TrustBridge-Signature`.
+pub fn sign(
+    secret: &[u8],
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &[u8],
+) -> String {
+    hex::encode(
+        mac(secret, method, path, timestamp, nonce, body)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+fn mac(
+    secret: &[u8],
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &[u8],
+) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(canonical_request(method, path, timestamp, nonce, body).as_bytes());
+    mac
+}
+
+/// A signing key and the role requests signed with it act with.
+pub struct ServiceKey {
+    pub secret: Vec<u8>,
+    pub role: Role,
+}
+
+/// Verifies HMAC-signed requests from internal workers and partners.
+///
+/// Several named keys may be active at once so a secret can be rotated by
+/// adding the new key, moving callers over, then dropping the old one. Each
+/// nonce is accepted once within the clock-skew window, after which the
+/// timestamp check alone rejects a replay.
+pub struct RequestVerifier {
+    keys: HashMap<String, ServiceKey>,
+    seen_nonces: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl RequestVerifier {
+    pub fn new(keys: HashMap<String, ServiceKey>) -> Self {
+        Self {
+            keys,
+            seen_nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Parses `API_SECRET_KEY`: either one secret, known as `default`, or a
+    /// comma-separated list of `key_id:secret` pairs.
+    ///
+    /// `API_KEY_ROLES` grants keys a role as comma-separated `key_id:role`
+    /// pairs, e.g. `worker:admin`. Keys it leaves out get the default role,
+    /// the same a Firebase user without a role claim gets.
+    pub fn from_config(api_secret_key: &str, api_key_roles: Option<&str>) -> Result<Self, String> {
+        let mut secrets = HashMap::new();
+        if !api_secret_key.contains(':') {
+            let secret = api_secret_key.trim();
+            if secret.is_empty() {
+                return Err("API_SECRET_KEY is empty".to_string());
+            }
+            secrets.insert(DEFAULT_KEY_ID.to_string(), secret.as_bytes().to_vec());
+        } else {
+            for (position, entry) in api_secret_key.split(',').map(str::trim).enumerate() {
+                // Never echo the entry itself: it may be a secret
+                let (key_id, secret) = entry
+                    .split_once(':')
+                    .filter(|(key_id, secret)| !key_id.is_empty() && !secret.is_empty())
+                    .ok_or_else(|| {
+                        format!("API_SECRET_KEY entry {} is not key_id:secret", position + 1)
+                    })?;
+                if secrets
+                    .insert(key_id.to_string(), secret.as_bytes().to_vec())
+                    .is_some()
+                {
+                    return Err(format!("API_SECRET_KEY names key '{}' twice", key_id));
+                }
+            }
+        }
+
+        let mut roles = HashMap::new();
+        let entries = api_key_roles
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty());
+        for entry in entries {
+            let (key_id, role) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("API_KEY_ROLES entry '{}' is not key_id:role", entry))?;
+            if !secrets.contains_key(key_id) {
+                return Err(format!(
+                    "API_KEY_ROLES names key '{}', which API_SECRET_KEY does not",
+                    key_id
+                ));
+            }
+            let role = Role::from_claim(role).ok_or_else(|| {
+                format!(
+                    "API_KEY_ROLES gives key '{}' unknown role '{}'",
+                    key_id, role
+                )
+            })?;
+            if roles.insert(key_id, role).is_some() {
+                return Err(format!("API_KEY_ROLES names key '{}' twice", key_id));
+            }
+        }
+
+        let keys = secrets
+            .into_iter()
+            .map(|(key_id, secret)| {
+                let role = roles.get(key_id.as_str()).copied().unwrap_or_default();
+                (key_id, ServiceKey { secret, role })
+            })
+            .collect();
+        Ok(Self::new(keys))
+    }
+
+    /// Checks a request signed at `timestamp` against our clock at `now`
+    /// (both Unix seconds) and returns the service it came from, under the
+    /// user ID `service:<key_id>` with its key's role.
+    pub fn verify(&self, request: &SignedRequest, now: i64) -> Result<AuthUser, AuthError> {
+        let key = self
+            .keys
+            .get(request.key_id)
+            .ok_or_else(|| AuthError::invalid_signature("Unknown signing key"))?;
+
+        let timestamp: i64 = request
+            .timestamp
+            .parse()
+            .map_err(|_| AuthError::invalid_signature("Timestamp must be Unix seconds"))?;
+        if (now - timestamp).abs() > MAX_CLOCK_SKEW_SECS {
+            return Err(AuthError::invalid_signature(
+                "Timestamp is outside the allowed clock skew",
+            ));
+        }
+
+        if request.nonce.is_empty() || request.nonce.len() > MAX_NONCE_LENGTH {
+            return Err(AuthError::invalid_signature(format!(
+                "Nonce must be 1 to {} characters",
+                MAX_NONCE_LENGTH
+            )));
+        }
+
+        let signature = hex::decode(request.signature)
+            .map_err(|_| AuthError::invalid_signature("Signature must be hex"))?;
+        mac(
+            &key.secret,
+            request.method,
+            request.path,
+            timestamp,
+            request.nonce,
+            request.body,
+        )
+        .verify_slice(&signature)
+        .map_err(|_| AuthError::invalid_signature("Signature does not match"))?;
+
+        // Only remember nonces of genuine requests, so forged ones cannot
+        // fill the cache
+        self.remember_nonce(request.key_id, request.nonce, now)?;
+
+        Ok(AuthUser {
+            uid: format!("service:{}", request.key_id),
+            email: None,
+            role: key.role,
+        })
+    }
+
+    fn remember_nonce(&self, key_id: &str, nonce: &str, now: i64) -> Result<(), AuthError> {
+        let mut seen = self.seen_nonces.lock().unwrap();
+        // A nonce only needs remembering while its timestamp could still pass
+        seen.retain(|_, expires_at| *expires_at > now);
+
+        let expires_at = now + 2 * MAX_CLOCK_SKEW_SECS;
+        if seen
+            .insert((key_id.to_string(), nonce.to_string()), expires_at)
+            .is_some()
+        {
+            return Err(AuthError::invalid_signature("Nonce has already been used"));
+        }
+        Ok(())
+    }
+}
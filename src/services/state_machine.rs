@@ -0,0 +1,121 @@
+use crate::errors::escrow::EscrowError;
+use crate::models::escrow::{Escrow, EscrowStatus};
+
+/// The events that can move an escrow from one status to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscrowTransition {
+    Fund,
+    Cancel,
+    /// The funding deadline passed before the escrow was funded.
+    Expire,
+    /// A party disputed the escrow, freezing its funds.
+    Dispute,
+    /// The arbiter resolved a dispute; the payout they decided on has been
+    /// submitted. The only way out of `Disputed`.
+    Resolve,
+    /// One milestone, but not the last, was paid out to the recipient.
+    ReleaseMilestone,
+    /// The ledger transaction paying out a funded escrow has been submitted.
+    Settle,
+    /// The ledger confirmed the payout to the recipient.
+    Release,
+    /// The ledger confirmed the payout back to the sender.
+    Refund,
+}
+
+/// Every legal move, as `(from, transition, to)`. This table is the only
+/// place that decides which status changes are allowed.
+#[rustfmt::skip]
+const TRANSITIONS: &[(EscrowStatus, EscrowTransition, EscrowStatus)] = &[
+    (EscrowStatus::Pending, EscrowTransition::Fund, EscrowStatus::Funded),
+    (EscrowStatus::Pending, EscrowTransition::Cancel, EscrowStatus::Cancelled),
+    (EscrowStatus::Pending, EscrowTransition::Expire, EscrowStatus::Expired),
+    (EscrowStatus::Funded, EscrowTransition::Settle, EscrowStatus::Settling),
+    (EscrowStatus::Funded, EscrowTransition::ReleaseMilestone, EscrowStatus::PartiallyReleased),
+    (EscrowStatus::PartiallyReleased, EscrowTransition::ReleaseMilestone, EscrowStatus::PartiallyReleased),
+    (EscrowStatus::PartiallyReleased, EscrowTransition::Settle, EscrowStatus::Settling),
+    (EscrowStatus::Funded, EscrowTransition::Dispute, EscrowStatus::Disputed),
+    (EscrowStatus::PartiallyReleased, EscrowTransition::Dispute, EscrowStatus::Disputed),
+    (EscrowStatus::Disputed, EscrowTransition::Resolve, EscrowStatus::Settling),
+    (EscrowStatus::Settling, EscrowTransition::Release, EscrowStatus::Released),
+    (EscrowStatus::Settling, EscrowTransition::Refund, EscrowStatus::Cancelled),
+];
+
+impl EscrowTransition {
+    /// Resolves the transition that takes an escrow from `from` to `to`.
+    pub fn between(from: EscrowStatus, to: EscrowStatus) -> Result<Self, EscrowError> {
+        TRANSITIONS
+            .iter()
+            .find(|(f, _, t)| *f == from && *t == to)
+            .map(|(_, transition, _)| *transition)
+            .ok_or(EscrowError::InvalidTransition { from, to })
+    }
+
+    /// Returns the status the escrow ends up in if this transition is applied
+    /// while it is in `from`.
+    pub fn target(self, from: EscrowStatus) -> Result<EscrowStatus, EscrowError> {
+        TRANSITIONS
+            .iter()
+            .find(|(f, transition, _)| *f == from && *transition == self)
+            .map(|(_, _, to)| *to)
+            .ok_or(EscrowError::InvalidTransition {
+                from,
+                to: self.intended_status(),
+            })
+    }
+
+    /// Checks the escrow's data, on top of its status, before the move is allowed.
+    pub fn guard(self, escrow: &Escrow) -> Result<(), EscrowError> {
+        match self {
+            EscrowTransition::Settle
+            | EscrowTransition::ReleaseMilestone
+            | EscrowTransition::Resolve
+                if !escrow.locked_funds.is_positive() =>
+            {
+                Err(EscrowError::validation(
+                    "locked_funds",
+                    "Escrow has no locked funds",
+                ))
+            }
+            EscrowTransition::Settle
+            | EscrowTransition::ReleaseMilestone
+            | EscrowTransition::Resolve
+                if escrow.escrow_account_id.is_none() =>
+            {
+                Err(EscrowError::validation(
+                    "escrow_account_id",
+                    "Escrow has no ledger account",
+                ))
+            }
+            EscrowTransition::Release | EscrowTransition::Refund
+                if escrow.settlement_target != Some(self.intended_status()) =>
+            {
+                Err(EscrowError::Conflict(format!(
+                    "Escrow {} is not settling towards {}",
+                    escrow.id,
+                    self.intended_status()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates the transition for `escrow` and returns its new status.
+    pub fn apply(self, escrow: &Escrow) -> Result<EscrowStatus, EscrowError> {
+        let to = self.target(escrow.status)?;
+        self.guard(escrow)?;
+        Ok(to)
+    }
+
+    fn intended_status(self) -> EscrowStatus {
+        match self {
+            EscrowTransition::Fund => EscrowStatus::Funded,
+            EscrowTransition::ReleaseMilestone => EscrowStatus::PartiallyReleased,
+            EscrowTransition::Dispute => EscrowStatus::Disputed,
+            EscrowTransition::Settle | EscrowTransition::Resolve => EscrowStatus::Settling,
+            EscrowTransition::Release => EscrowStatus::Released,
+            EscrowTransition::Expire => EscrowStatus::Expired,
+            EscrowTransition::Cancel | EscrowTransition::Refund => EscrowStatus::Cancelled,
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use crate::config::Config;
+use crate::errors::escrow::EscrowError;
+use crate::models::money::{AssetAmount, AssetId, Money};
+use crate::models::stellar_address::StellarAddress;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use stellar_base::amount::Stroops;
+use stellar_base::crypto::MuxedAccount;
+use stellar_base::signature::{Signer, SignerKey};
+use stellar_base::transaction::{Transaction, MIN_BASE_FEE};
+use stellar_base::{Asset, KeyPair, Memo, Network, Operation, PublicKey};
+
+/// Base reserve of the Stellar network, in stroops (0.5 XLM).
+pub const BASE_RESERVE_STROOPS: i64 = 5_000_000;
+
+/// XLM the treasury leaves in every escrow account on top of the locked funds:
+/// two base reserves for the account itself plus one for the treasury signer.
+/// It is returned to the treasury when the account is merged on settlement.
+pub const ESCROW_ACCOUNT_RESERVE_STROOPS: i64 = 3 * BASE_RESERVE_STROOPS;
+
+pub const NATIVE_ASSET_CODE: &str = "XLM";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// An asset escrows may be denominated in, from `STELLAR_SUPPORTED_ASSETS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupportedAsset {
+    pub code: String,
+    /// Issuing account; `None` for XLM.
+    pub issuer: Option<String>,
+    /// Decimal places amounts may use, at most `Money::DECIMALS`. USDC, for
+    /// one, only moves in cents.
+    pub decimals: u32,
+}
+
+impl SupportedAsset {
+    pub fn native() -> Self {
+        Self {
+            code: NATIVE_ASSET_CODE.to_string(),
+            issuer: None,
+            decimals: Money::DECIMALS,
+        }
+    }
+
+    /// Parses `XLM`, `CODE:ISSUER` or `CODE:ISSUER:DECIMALS`.
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let mut parts = entry.trim().split(':');
+        let code = parts.next().unwrap_or_default();
+        let issuer = parts.next();
+        let decimals = parts.next();
+        if parts.next().is_some() {
+            return Err(format!("'{}' is not CODE:ISSUER[:DECIMALS]", entry));
+        }
+
+        let Some(issuer) = issuer else {
+            return if code == NATIVE_ASSET_CODE {
+                Ok(Self::native())
+            } else {
+                Err(format!("Asset {} needs an issuer", code))
+            };
+        };
+        if code.is_empty() || code.len() > 12 || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("'{}' is not a Stellar asset code", code));
+        }
+        PublicKey::from_account_id(issuer)
+            .map_err(|_| format!("Issuer of {} is not a Stellar account", code))?;
+        let decimals = match decimals {
+            None => Money::DECIMALS,
+            Some(decimals) => decimals
+                .parse()
+                .ok()
+                .filter(|decimals| *decimals <= Money::DECIMALS)
+                .ok_or_else(|| format!("Decimals of {} must be 0 to {}", code, Money::DECIMALS))?,
+        };
+
+        Ok(Self {
+            code: code.to_string(),
+            issuer: Some(issuer.to_string()),
+            decimals,
+        })
+    }
+
+    /// Parses a comma-separated list of assets.
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        let assets = list
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(Self::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if assets.is_empty() {
+            return Err("At least one asset must be supported".to_string());
+        }
+        Ok(assets)
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.issuer.is_none()
+    }
+
+    pub fn to_stellar(&self) -> Result<Asset, EscrowError> {
+        escrow_asset(&self.code, self.issuer.as_deref())
+    }
+
+    /// Smallest amount this asset moves in, in ledger units.
+    pub fn unit(&self) -> i64 {
+        10_i64.pow(Money::DECIMALS - self.decimals)
+    }
+
+    /// Fails unless `amount` is a whole number of `unit`s.
+    pub fn check_precision(&self, field: &'static str, amount: Money) -> Result<(), EscrowError> {
+        if amount.units() % self.unit() != 0 {
+            return Err(EscrowError::validation(
+                field,
+                format!(
+                    "{} amounts have at most {} decimal places",
+                    self.code, self.decimals
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct StellarConfig {
+    pub network: Network,
+    pub horizon_url: String,
+    pub escrow_keypair: KeyPair,
+    pub supported_assets: Vec<SupportedAsset>,
+}
+
+impl StellarConfig {
+    pub fn from_config(config: &Config) -> Self {
+        let network = match config.stellar_network.as_str() {
+            "mainnet" => Network::new_public(),
+            _ => Network::new_test(),
+        };
+
+        Self {
+            network,
+            horizon_url: config.stellar_horizon_url.clone(),
+            // Checked by `Config::validate`
+            escrow_keypair: KeyPair::from_secret_seed(&config.stellar_escrow_secret_key)
+                .expect("Invalid Stellar secret key"),
+            supported_assets: SupportedAsset::parse_list(&config.stellar_supported_assets)
+                .expect("Invalid STELLAR_SUPPORTED_ASSETS"),
+        }
+    }
+
+    pub fn treasury(&self) -> &PublicKey {
+        self.escrow_keypair.public_key()
+    }
+
+    /// Key pair of the account holding an escrow's funds: an HMAC of the
+    /// escrow under the treasury seed. Every funding attempt for the escrow
+    /// gets the same account, so a retry can never create a second one.
+    pub fn escrow_account_keypair(
+        &self,
+        escrow_id: i32,
+        created_at: DateTime<Utc>,
+    ) -> Result<KeyPair, EscrowError> {
+        let mut mac =
+            HmacSha256::new_from_slice(self.escrow_keypair.secret_key().secret_seed().as_bytes())
+                .expect("HMAC accepts keys of any length");
+        mac.update(
+            format!(
+                "escrow-account\n{}\n{}",
+                escrow_id,
+                created_at.timestamp_micros()
+            )
+            .as_bytes(),
+        );
+        KeyPair::from_seed_bytes(&mac.finalize().into_bytes()).map_err(EscrowError::ledger)
+    }
+
+    /// Looks up an asset in the allowlist.
+    pub fn supported_asset(
+        &self,
+        code: &str,
+        issuer: Option<&str>,
+    ) -> Result<&SupportedAsset, EscrowError> {
+        self.supported_assets
+            .iter()
+            .find(|asset| asset.code == code && asset.issuer.as_deref() == issuer)
+            .ok_or_else(|| {
+                EscrowError::validation(
+                    "asset_code",
+                    match issuer {
+                        Some(issuer) => format!("{}:{} is not a supported asset", code, issuer),
+                        None => format!("{} is not a supported asset", code),
+                    },
+                )
+            })
+    }
+}
+
+/// The ledger asset an escrow is denominated in.
+pub fn escrow_asset(code: &str, issuer: Option<&str>) -> Result<Asset, EscrowError> {
+    match issuer {
+        None => Ok(Asset::new_native()),
+        Some(issuer) => Asset::new_credit(code, parse_account("asset_issuer", issuer)?)
+            .map_err(EscrowError::ledger),
+    }
+}
+
+/// XLM an escrow account needs on top of the locked funds. Holding an issued
+/// asset takes a trustline, which costs another base reserve.
+pub fn escrow_account_reserve(asset: &Asset) -> i64 {
+    match asset {
+        Asset::Native => ESCROW_ACCOUNT_RESERVE_STROOPS,
+        Asset::Credit(_) => ESCROW_ACCOUNT_RESERVE_STROOPS + BASE_RESERVE_STROOPS,
+    }
+}
+
+/// Funds a brand new account that holds one escrow's money.
+///
+/// The treasury creates the account with its reserve and, for XLM, `amount`
+/// on top. For an issued asset the account first trusts the asset and the
+/// treasury then pays `amount` of it in. Finally the account hands control to
+/// the treasury key and disables its own master key, so the funds can only
+/// leave through a transaction the backend signs. `escrow_account` only signs
+/// this transaction; see `StellarConfig::escrow_account_keypair`.
+pub fn build_escrow_account_transaction(
+    config: &StellarConfig,
+    treasury_sequence: i64,
+    escrow_id: i32,
+    escrow_account: &KeyPair,
+    asset: &Asset,
+    amount: Money,
+) -> Result<Transaction, EscrowError> {
+    let escrow_public_key = escrow_account.public_key().clone();
+    let reserve = escrow_account_reserve(asset);
+
+    let starting_balance = if asset.is_native() {
+        AssetAmount::new(amount, AssetId::native())
+            .checked_add(&AssetAmount::new(
+                Money::from_units(reserve),
+                AssetId::native(),
+            ))
+            .map_err(|_| EscrowError::validation("amount", "Amount is too large"))?
+            .amount
+    } else {
+        Money::from_units(reserve)
+    };
+
+    let create_account = Operation::new_create_account()
+        .with_destination(escrow_public_key.clone())
+        .with_starting_balance(Stroops::from(starting_balance))
+        .map_err(EscrowError::ledger)?
+        .build()
+        .map_err(EscrowError::ledger)?;
+
+    let mut builder =
+        Transaction::builder(config.treasury().clone(), treasury_sequence, MIN_BASE_FEE)
+            .with_memo(escrow_memo(escrow_id))
+            .add_operation(create_account);
+
+    if asset.is_credit() {
+        let trust_asset = Operation::new_change_trust()
+            .with_source_account(escrow_public_key.clone())
+            .with_asset(asset.clone())
+            .with_limit(Some(Stroops::from(Money::MAX)))
+            .map_err(EscrowError::ledger)?
+            .build()
+            .map_err(EscrowError::ledger)?;
+        let deposit = Operation::new_payment()
+            .with_destination(escrow_public_key.clone())
+            .with_amount(Stroops::from(amount))
+            .map_err(EscrowError::ledger)?
+            .with_asset(asset.clone())
+            .build()
+            .map_err(EscrowError::ledger)?;
+        builder = builder.add_operation(trust_asset).add_operation(deposit);
+    }
+
+    let hand_over_control = Operation::new_set_options()
+        .with_source_account(escrow_public_key)
+        .with_signer(Some(Signer::new(
+            SignerKey::new_from_public_key(config.treasury().clone()),
+            1,
+        )))
+        .with_master_weight(Some(0))
+        .with_low_threshold(Some(1))
+        .with_medium_threshold(Some(1))
+        .with_high_threshold(Some(1))
+        .build()
+        .map_err(EscrowError::ledger)?;
+
+    let mut transaction = builder
+        .add_operation(hand_over_control)
+        .into_transaction()
+        .map_err(EscrowError::ledger)?;
+
+    transaction
+        .sign(&config.escrow_keypair, &config.network)
+        .map_err(EscrowError::ledger)?;
+    transaction
+        .sign(escrow_account, &config.network)
+        .map_err(EscrowError::ledger)?;
+
+    Ok(transaction)
+}
+
+/// Pays an escrow's locked funds out and closes its account.
+///
+/// Each payout moves an amount of `asset` to the recipient (release), the
+/// sender (refund) or both (split dispute resolution). The account then drops
+/// its trustline, if any, and the merge returns the XLM reserve to the
+/// treasury. All operations are sourced from the escrow account, which only
+/// the treasury key can sign for, so the treasury signature is all that's
+/// needed.
+pub fn build_settlement_transaction(
+    config: &StellarConfig,
+    treasury_sequence: i64,
+    escrow_id: i32,
+    escrow_account: &PublicKey,
+    asset: &Asset,
+    payouts: &[(&MuxedAccount, Money)],
+) -> Result<Transaction, EscrowError> {
+    let mut builder =
+        Transaction::builder(config.treasury().clone(), treasury_sequence, MIN_BASE_FEE)
+            .with_memo(escrow_memo(escrow_id));
+
+    for (destination, amount) in payouts {
+        builder = builder.add_operation(payment(escrow_account, destination, asset, *amount)?);
+    }
+
+    if asset.is_credit() {
+        // No limit removes the trustline
+        let drop_trustline = Operation::new_change_trust()
+            .with_source_account(escrow_account.clone())
+            .with_asset(asset.clone())
+            .build()
+            .map_err(EscrowError::ledger)?;
+        builder = builder.add_operation(drop_trustline);
+    }
+
+    let close_account = Operation::new_account_merge()
+        .with_source_account(escrow_account.clone())
+        .with_destination(config.treasury().clone().into())
+        .build()
+        .map_err(EscrowError::ledger)?;
+
+    let mut transaction = builder
+        .add_operation(close_account)
+        .into_transaction()
+        .map_err(EscrowError::ledger)?;
+
+    transaction
+        .sign(&config.escrow_keypair, &config.network)
+        .map_err(EscrowError::ledger)?;
+
+    Ok(transaction)
+}
+
+/// Pays one milestone out of an escrow account to `destination`. The account
+/// stays open for the funds still locked in it.
+pub fn build_milestone_transaction(
+    config: &StellarConfig,
+    treasury_sequence: i64,
+    escrow_id: i32,
+    escrow_account: &PublicKey,
+    destination: &MuxedAccount,
+    asset: &Asset,
+    amount: Money,
+) -> Result<Transaction, EscrowError> {
+    let mut transaction =
+        Transaction::builder(config.treasury().clone(), treasury_sequence, MIN_BASE_FEE)
+            .with_memo(escrow_memo(escrow_id))
+            .add_operation(payment(escrow_account, destination, asset, amount)?)
+            .into_transaction()
+            .map_err(EscrowError::ledger)?;
+
+    transaction
+        .sign(&config.escrow_keypair, &config.network)
+        .map_err(EscrowError::ledger)?;
+
+    Ok(transaction)
+}
+
+/// Returns all or part of a deposit to the treasury to the account that
+/// paid it.
+pub fn build_deposit_refund_transaction(
+    config: &StellarConfig,
+    treasury_sequence: i64,
+    escrow_id: i32,
+    payer: &MuxedAccount,
+    asset: &Asset,
+    amount: Money,
+) -> Result<Transaction, EscrowError> {
+    let mut transaction =
+        Transaction::builder(config.treasury().clone(), treasury_sequence, MIN_BASE_FEE)
+            .with_memo(escrow_memo(escrow_id))
+            .add_operation(payment(config.treasury(), payer, asset, amount)?)
+            .into_transaction()
+            .map_err(EscrowError::ledger)?;
+
+    transaction
+        .sign(&config.escrow_keypair, &config.network)
+        .map_err(EscrowError::ledger)?;
+
+    Ok(transaction)
+}
+
+/// A payment of `amount` of `asset` out of `source`, an escrow account or
+/// the treasury.
+fn payment(
+    source: &PublicKey,
+    destination: &MuxedAccount,
+    asset: &Asset,
+    amount: Money,
+) -> Result<Operation, EscrowError> {
+    Operation::new_payment()
+        .with_source_account(source.clone())
+        .with_destination(destination.clone())
+        .with_amount(Stroops::from(amount))
+        .map_err(EscrowError::ledger)?
+        .with_asset(asset.clone())
+        .build()
+        .map_err(EscrowError::ledger)
+}
+
+/// Parses a Stellar account ID stored on an escrow.
+pub fn parse_account(field: &'static str, account_id: &str) -> Result<PublicKey, EscrowError> {
+    PublicKey::from_account_id(account_id)
+        .map_err(|_| EscrowError::validation(field, "Not a valid Stellar account"))
+}
+
+/// Turns a participant address stored on an escrow into a payment destination.
+pub fn parse_address(
+    field: &'static str,
+    address: &StellarAddress,
+) -> Result<MuxedAccount, EscrowError> {
+    address
+        .muxed_account()
+        .map_err(|err| EscrowError::validation(field, err.to_string()))
+}
+
+pub fn escrow_memo(escrow_id: i32) -> Memo {
+    Memo::Text(format!("escrow:{}", escrow_id))
+}
+
+/// Hex-encoded hash Horizon uses to identify `transaction`.
+pub fn transaction_hash(
+    network: &Network,
+    transaction: &Transaction,
+) -> Result<String, EscrowError> {
+    let hash = transaction.hash(network).map_err(EscrowError::ledger)?;
+    Ok(hash.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
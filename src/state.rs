@@ -0,0 +1,63 @@
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::routes::auth::Authenticators;
+use crate::services::auth::{FirebaseAuth, GoogleKeySource};
+use crate::services::escrow::EscrowService;
+use crate::services::idempotency::IdempotencyService;
+use crate::services::ledger::HorizonLedger;
+use crate::services::signing::RequestVerifier;
+use crate::services::stellar::StellarConfig;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Shared state handed to every route handler.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub stellar_config: Arc<StellarConfig>,
+    pub escrow_service: Arc<EscrowService>,
+    pub idempotency_service: Arc<IdempotencyService>,
+    pub auth: Authenticators,
+}
+
+impl AppState {
+    pub fn new(config: Config, pool: DbPool) -> Self {
+        let stellar_config = Arc::new(StellarConfig::from_config(&config));
+        let ledger =
+            HorizonLedger::new(&stellar_config.horizon_url).expect("Invalid Stellar Horizon URL");
+        // Both are checked by `Config::validate`
+        let firebase = config
+            .firebase_auth_enabled
+            .then_some(config.firebase_project_id.as_deref())
+            .flatten()
+            .map(|project_id| {
+                Arc::new(FirebaseAuth::new(
+                    project_id,
+                    Arc::new(GoogleKeySource::new()),
+                ))
+            });
+        let services = match &config.api_secret_key {
+            Some(keys) => RequestVerifier::from_config(keys, config.api_key_roles.as_deref())
+                .expect("Invalid API_SECRET_KEY"),
+            None => RequestVerifier::new(HashMap::new()),
+        };
+        let auth = Authenticators {
+            firebase,
+            services: Arc::new(services),
+        };
+        let idempotency_service = Arc::new(IdempotencyService::new(pool.clone()));
+        let escrow_service = Arc::new(EscrowService::new(
+            pool,
+            stellar_config.clone(),
+            Arc::new(ledger),
+        ));
+
+        Self {
+            config: Arc::new(config),
+            stellar_config,
+            escrow_service,
+            idempotency_service,
+            auth,
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::config::Config;
+use axum::{body::Body, http::Request, Router};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Values shorter than this are not treated as secrets, so a blank or
+/// placeholder setting cannot blank out ordinary log text.
+const MIN_SECRET_LENGTH: usize = 8;
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line, for log aggregation.
+    Json,
+    /// Human-readable lines, for local development.
+    Pretty,
+}
+
+/// Replaces every configured secret in log output with `[REDACTED]`.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    secrets: Arc<Vec<String>>,
+}
+
+impl Redactor {
+    pub fn new(secrets: impl IntoIterator<Item = String>) -> Self {
+        let mut secrets: Vec<String> = secrets
+            .into_iter()
+            .filter(|secret| secret.len() >= MIN_SECRET_LENGTH)
+            .collect();
+        // Longest first, so a secret containing another is redacted whole
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        secrets.dedup();
+        Self {
+            secrets: Arc::new(secrets),
+        }
+    }
+
+    /// Every secret-bearing setting, including each key in `API_SECRET_KEY`
+    /// and the password in `DATABASE_URL`.
+    pub fn from_config(config: &Config) -> Self {
+        let mut secrets = vec![config.stellar_escrow_secret_key.clone()];
+        if let Some(private_key) = &config.firebase_private_key {
+            secrets.push(private_key.clone());
+            // Private keys are often set with escaped newlines
+            secrets.push(private_key.replace("\\n", "\n"));
+        }
+        if let Some(api_secret_key) = &config.api_secret_key {
+            secrets.push(api_secret_key.clone());
+            secrets.extend(
+                api_secret_key
+                    .split(',')
+                    .filter_map(|entry| entry.split_once(':'))
+                    .map(|(_, secret)| secret.trim().to_string()),
+            );
+        }
+        secrets.extend(database_password(&config.database_url));
+        Self::new(secrets)
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(text);
+        for secret in self.secrets.iter() {
+            if redacted.contains(secret.as_str()) {
+                redacted = Cow::Owned(redacted.replace(secret.as_str(), REDACTED));
+            }
+        }
+        redacted
+    }
+}
+
+fn database_password(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let (credentials, _) = rest.rsplit_once('@')?;
+    let (_, password) = credentials.split_once(':')?;
+    Some(password.to_string())
+}
+
+/// Wraps a writer so each log line passes through a `Redactor` first.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    redactor: Redactor,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, redactor: Redactor) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: self.redactor.clone(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// Buffers one event and writes it redacted when dropped, so a secret is
+/// caught even if the formatter writes it in pieces.
+pub struct RedactingWriter<W: Write> {
+    inner: W,
+    redactor: Redactor,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let text = String::from_utf8_lossy(&self.buffer);
+            self.inner
+                .write_all(self.redactor.redact(&text).as_bytes())?;
+            self.buffer.clear();
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for RedactingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Installs the global subscriber. Levels come from `RUST_LOG` and default
+/// to `info`.
+pub fn init(format: LogFormat, redactor: Redactor) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let writer = RedactingMakeWriter::new(io::stdout, redactor);
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+
+    match format {
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+        LogFormat::Pretty => builder.init(),
+    }
+}
+
+/// Gives every request an `X-Request-Id`, keeping one the caller sent, and
+/// runs it inside a `request` span carrying that ID. Everything logged while
+/// handling the request, down to `EscrowService`, inherits the span.
+pub fn with_request_tracing(router: Router) -> Router {
+    router
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        path = request.uri().path(),
+    )
+}
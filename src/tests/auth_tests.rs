@@ -0,0 +1,197 @@
+use crate::errors::auth::AuthError;
+use crate::routes::auth::{authenticate, Authenticators};
+use crate::services::auth::{AuthUser, FirebaseAuth, KeySource, PublicKeys, Role};
+use crate::services::signing::RequestVerifier;
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    middleware,
+    routing::get,
+    Router,
+};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+const PROJECT_ID: &str = "trustbridge-test";
+const TEST_KEY_ID: &str = "test-key";
+const TEST_PRIVATE_KEY: &[u8] = include_bytes!("fixtures/firebase_test_key.pem");
+const TEST_JWKS: &str = include_str!("fixtures/firebase_test_jwks.json");
+
+/// Serves the fixture JWKS and counts how often it is fetched.
+#[derive(Default)]
+struct LocalKeySource {
+    fetches: AtomicUsize,
+}
+
+impl KeySource for LocalKeySource {
+    fn fetch(&self) -> Result<PublicKeys, AuthError> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        Ok(PublicKeys {
+            keys: serde_json::from_str(TEST_JWKS).unwrap(),
+            max_age: Duration::from_secs(60),
+        })
+    }
+}
+
+fn setup() -> (FirebaseAuth, Arc<LocalKeySource>) {
+    let keys = Arc::new(LocalKeySource::default());
+    (FirebaseAuth::new(PROJECT_ID, keys.clone()), keys)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn valid_claims() -> serde_json::Value {
+    json!({
+        "sub": "user-1",
+        "email": "user@example.com",
+        "aud": PROJECT_ID,
+        "iss": format!("https://securetoken.google.com/{}", PROJECT_ID),
+        "iat": now() - 10,
+        "exp": now() + 3600,
+    })
+}
+
+fn sign(claims: &serde_json::Value, kid: &str) -> String {
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(kid.to_string());
+    encode(
+        &header,
+        claims,
+        &EncodingKey::from_rsa_pem(TEST_PRIVATE_KEY).unwrap(),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_valid_token_yields_user() {
+    let (auth, _) = setup();
+
+    let user = auth
+        .verify(&sign(&valid_claims(), TEST_KEY_ID))
+        .await
+        .unwrap();
+    assert_eq!(
+        user,
+        AuthUser {
+            uid: "user-1".to_string(),
+            email: Some("user@example.com".to_string()),
+            role: Role::Borrower,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_token_claims_are_checked() {
+    let (auth, _) = setup();
+
+    let mut other_project = valid_claims();
+    other_project["aud"] = json!("someone-else");
+    let mut other_issuer = valid_claims();
+    other_issuer["iss"] = json!("https://accounts.google.com");
+    let mut expired = valid_claims();
+    expired["exp"] = json!(now() - 3600);
+
+    for claims in [other_project, other_issuer, expired] {
+        assert!(matches!(
+            auth.verify(&sign(&claims, TEST_KEY_ID)).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_role_claim_is_read() {
+    let (auth, _) = setup();
+
+    let mut arbiter = valid_claims();
+    arbiter["role"] = json!("arbiter");
+    let user = auth.verify(&sign(&arbiter, TEST_KEY_ID)).await.unwrap();
+    assert_eq!(user.role, Role::Arbiter);
+
+    let mut unknown = valid_claims();
+    unknown["role"] = json!("superuser");
+    assert!(matches!(
+        auth.verify(&sign(&unknown, TEST_KEY_ID)).await,
+        Err(AuthError::InvalidToken(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_token_must_use_a_known_rs256_key() {
+    let (auth, _) = setup();
+
+    assert!(matches!(
+        auth.verify(&sign(&valid_claims(), "rotated-away")).await,
+        Err(AuthError::InvalidToken(_))
+    ));
+
+    let hs256 = encode(
+        &Header::new(Algorithm::HS256),
+        &valid_claims(),
+        &EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap();
+    assert!(matches!(
+        auth.verify(&hs256).await,
+        Err(AuthError::InvalidToken(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_keys_are_cached() {
+    let (auth, keys) = setup();
+    let token = sign(&valid_claims(), TEST_KEY_ID);
+
+    auth.verify(&token).await.unwrap();
+    auth.verify(&token).await.unwrap();
+    // Unknown key IDs do not refetch while the keys are fresh
+    auth.verify(&sign(&valid_claims(), "unknown")).await.ok();
+
+    assert_eq!(keys.fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_layer_requires_bearer_token() {
+    let (auth, _) = setup();
+    let app = Router::new()
+        .route("/me", get(|user: AuthUser| async move { user.uid }))
+        .route_layer(middleware::from_fn_with_state(
+            Authenticators {
+                firebase: Some(Arc::new(auth)),
+                services: Arc::new(RequestVerifier::from_config("secret", None).unwrap()),
+            },
+            authenticate,
+        ));
+
+    let anonymous = app
+        .clone()
+        .oneshot(Request::get("/me").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    assert!(anonymous.headers().contains_key(header::WWW_AUTHENTICATE));
+
+    let authenticated = app
+        .oneshot(
+            Request::get("/me")
+                .header(
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", sign(&valid_claims(), TEST_KEY_ID)),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(authenticated.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(authenticated.into_body())
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"user-1");
+}
@@ -1,99 +1,1695 @@
-use crate::models::escrow::{self, Escrow, EscrowStatus};
+use crate::db::DbPool;
+use crate::errors::escrow::EscrowError;
+use crate::models::escrow::{Escrow, EscrowQuery, EscrowSort, EscrowStatus, LockFunds, NewEscrow};
+use crate::models::escrow_deposit::DepositInstructions;
+use crate::models::escrow_dispute::{DisputeOutcome, DisputeResolution, NewEvidence, OpenDispute};
+use crate::models::escrow_milestone::NewMilestone;
+use crate::models::escrow_participant::Participants;
+use crate::models::ledger_discrepancy::DiscrepancyKind;
+use crate::models::money::Money;
+use crate::models::stellar_address::StellarAddress;
+use crate::services::auth::{AuthUser, Role, SYSTEM_ACTOR};
 use crate::services::escrow::EscrowService;
-use diesel::r2d2::{self, ConnectionManager};
-use diesel::PgConnection;
-use dotenvy::dotenv;
+use crate::services::ledger::{InMemoryLedger, LedgerClient, SimulatedFailure};
+use crate::services::signing::{sign, RequestVerifier, SignedRequest};
+use crate::services::stellar::{
+    StellarConfig, SupportedAsset, BASE_RESERVE_STROOPS, ESCROW_ACCOUNT_RESERVE_STROOPS,
+};
+use crate::tests::{test_pool, test_pool_with};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use std::sync::Arc;
+use stellar_base::amount::Stroops;
+use stellar_base::crypto::MuxedEd25519PublicKey;
+use stellar_base::transaction::{Transaction, MIN_BASE_FEE};
+use stellar_base::{Asset, KeyPair, Memo, Network, Operation, PublicKey};
+
+const TREASURY_BALANCE: i64 = 10_000_000_000;
+const USDC_ISSUER: &str = "GDHMW6QZOL73SHKG2JA3YHXFDHM46SS5ZRWEYF5BCYHX2C5TVO6KZBYL";
 
 fn setup_test_db() -> EscrowService {
-    dotenv().expect(".env file not found");
+    setup_with_ledger().0
+}
+
+/// Service backed by an in-memory ledger whose treasury holds `TREASURY_BALANCE`.
+fn setup_with_ledger() -> (EscrowService, Arc<InMemoryLedger>, String) {
+    setup_with_pool(test_pool())
+}
+
+fn setup_with_pool(pool: DbPool) -> (EscrowService, Arc<InMemoryLedger>, String) {
+    let stellar_config = StellarConfig {
+        network: Network::new_test(),
+        horizon_url: "https://horizon-testnet.stellar.org".to_string(),
+        escrow_keypair: KeyPair::random().expect("Failed to generate keypair"),
+        supported_assets: vec![
+            SupportedAsset::native(),
+            SupportedAsset::parse(&format!("USDC:{}:2", USDC_ISSUER)).unwrap(),
+        ],
+    };
+
+    let ledger = Arc::new(InMemoryLedger::new(Network::new_test()));
+    let treasury = stellar_config.treasury().account_id();
+    ledger.fund_account(&treasury, Money::from_units(TREASURY_BALANCE));
+    ledger.fund_trustline(
+        &treasury,
+        "USDC",
+        USDC_ISSUER,
+        Money::from_units(TREASURY_BALANCE),
+    );
+
+    let service = EscrowService::new(pool, Arc::new(stellar_config), ledger.clone());
+    (service, ledger, treasury)
+}
+
+fn test_escrow() -> NewEscrow {
+    NewEscrow {
+        loan_amount: Money::from_units(1000),
+        loan_term: "12 months".to_string(),
+        purpose_of_loan: "Test loan".to_string(),
+        monthly_income: Money::from_units(5000),
+        sender_address: random_address(),
+        recipient_address: random_address(),
+        funding_deadline: None,
+        release_deadline: None,
+        asset_code: "XLM".to_string(),
+        asset_issuer: None,
+    }
+}
+
+fn user(uid: &str, role: Role) -> AuthUser {
+    AuthUser {
+        uid: uid.to_string(),
+        email: None,
+        role,
+    }
+}
+
+fn sender() -> AuthUser {
+    user("sender", Role::Borrower)
+}
+
+fn recipient() -> AuthUser {
+    user("recipient", Role::Lender)
+}
+
+fn arbiter() -> AuthUser {
+    user("arbiter", Role::Arbiter)
+}
+
+fn unique_suffix() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+}
+
+fn participants() -> Participants {
+    Participants {
+        sender: "sender".to_string(),
+        recipient: "recipient".to_string(),
+        arbiter: Some("arbiter".to_string()),
+    }
+}
+
+fn random_address() -> StellarAddress {
+    StellarAddress::parse(&KeyPair::random().unwrap().public_key().account_id()).unwrap()
+}
 
-    let database_url =
-        std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file");
+/// An escrow between two fresh accounts that exist on `ledger`.
+fn ledger_escrow(ledger: &InMemoryLedger) -> NewEscrow {
+    let escrow = test_escrow();
+    ledger.fund_account(escrow.sender_address.as_str(), Money::ZERO);
+    ledger.fund_account(escrow.recipient_address.as_str(), Money::ZERO);
+    escrow
+}
 
-    println!("Using database URL: {}", database_url); // Debug print
-    EscrowService::new(&database_url)
+fn xlm(units: i64) -> LockFunds {
+    LockFunds {
+        amount: Money::from_units(units),
+        asset_code: "XLM".to_string(),
+        asset_issuer: None,
+    }
 }
 
 #[tokio::test]
 async fn test_create_escrow() {
     let service = setup_test_db();
 
-    let test_escrow = Escrow {
-        id: 0, // Will be set by database
-        loan_amount: 1000,
-        loan_term: "12 months".to_string(),
-        purpose_of_loan: "Test loan".to_string(),
-        monthly_income: 5000,
-        status: "".to_string(),
-        sender_address: "sender123".to_string(),
-        recipient_address: "recipient456".to_string(),
-        locked_funds: 0,
-    };
-
-    let result = service.create_escrow(test_escrow).await;
+    let result = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await;
     assert!(result.is_ok());
 
     let created = result.unwrap();
     assert!(created.id > 0);
-    assert_eq!(created.loan_amount, 1000);
-    assert_eq!(created.status, EscrowStatus::Pending.to_string());
+    assert_eq!(created.loan_amount, Money::from_units(1000));
+    assert_eq!(created.status, EscrowStatus::Pending);
+}
+
+#[tokio::test]
+async fn test_memo_id_collision_draws_again() {
+    // With one connection, reseeding it makes the next escrow draw the memo
+    // ID the previous one got
+    let pool = test_pool_with(1);
+    let (service, _, _) = setup_with_pool(pool.clone());
+    let seed = (unique_suffix() % 1_000_000) as f64 / 1_000_000.0;
+    let reseed = || {
+        diesel::sql_query(format!("SELECT setseed({})", seed))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+    };
+
+    reseed();
+    let first = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+    reseed();
+    let second = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    assert_ne!(second.deposit_memo_id, first.deposit_memo_id);
+}
+
+#[tokio::test]
+async fn test_create_escrow_rejects_invalid_amount() {
+    let service = setup_test_db();
+
+    let mut escrow = test_escrow();
+    escrow.loan_amount = Money::ZERO;
+
+    let result = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "loan_amount",
+            ..
+        })
+    ));
 }
 
 #[tokio::test]
 async fn test_get_escrow() {
     let service = setup_test_db();
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
 
-    let result = service.get_escrow(1).await;
+    let result = service.get_escrow(created.id, &sender()).await;
     assert!(result.is_ok());
     let escrow = result.unwrap();
-    assert!(escrow.id == 1);
+    assert!(escrow.id == created.id);
 }
 
 #[tokio::test]
-async fn test_lock_funds() {
+async fn test_get_missing_escrow() {
     let service = setup_test_db();
 
-    let result = service.lock_funds(1, 1000).await;
+    let result = service.get_escrow(i32::MAX, &sender()).await;
+    assert!(matches!(result, Err(EscrowError::NotFound(i32::MAX))));
+}
+
+#[tokio::test]
+async fn test_lock_funds() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+
+    // The treasury funds the loan amount and nothing else
+    for units in [999, 1001, 1_000_000] {
+        let result = service
+            .lock_funds(created.id, xlm(units), &sender(), None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(EscrowError::Validation {
+                field: "amount",
+                ..
+            })
+        ));
+    }
+
+    let result = service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await;
     assert!(result.is_ok());
 
     let escrow = result.unwrap();
-    assert_eq!(escrow.locked_funds, 1000);
-    assert_eq!(escrow.status, EscrowStatus::Funded.to_string());
+    assert_eq!(escrow.locked_funds, Money::from_units(1000));
+    assert_eq!(escrow.status, EscrowStatus::Funded);
+    assert!(escrow.funded_at.unwrap() >= created.created_at);
+    assert!(escrow.updated_at > created.updated_at);
+
+    let escrow_account = ledger
+        .account(escrow.escrow_account_id.as_deref().unwrap())
+        .unwrap();
+    assert_eq!(
+        escrow_account.balance,
+        Money::from_units(1000 + ESCROW_ACCOUNT_RESERVE_STROOPS)
+    );
+    assert_eq!(
+        ledger.account(&treasury).unwrap().balance,
+        Money::from_units(TREASURY_BALANCE - 1000 - ESCROW_ACCOUNT_RESERVE_STROOPS)
+    );
+}
+
+#[tokio::test]
+async fn test_funding_is_not_repeated_after_lost_response() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+
+    // The escrow account is created, but the service never hears back
+    ledger.fail_next_submission(SimulatedFailure::ResponseLost);
+    let result = service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::Ledger(_))));
+
+    let escrow = service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Funded);
+    assert_eq!(
+        ledger.account(&treasury).unwrap().balance,
+        Money::from_units(TREASURY_BALANCE - 1000 - ESCROW_ACCOUNT_RESERVE_STROOPS)
+    );
+    assert!(ledger
+        .account(escrow.escrow_account_id.as_deref().unwrap())
+        .is_some());
 }
 
 #[tokio::test]
 async fn test_release_funds() {
-    let service = setup_test_db();
+    let (service, ledger, treasury) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    let funded = service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
 
-    let result = service.release_funds(1).await;
+    let result = service
+        .release_funds(created.id, &arbiter(), None, None)
+        .await;
     assert!(result.is_ok());
 
     let escrow = result.unwrap();
-    assert_eq!(escrow.status, EscrowStatus::Released.to_string());
+    assert_eq!(escrow.status, EscrowStatus::Released);
+    assert_eq!(escrow.locked_funds, Money::ZERO);
+    assert!(escrow.released_at.unwrap() >= funded.funded_at.unwrap());
+    assert!(escrow.cancelled_at.is_none());
+
+    // Recipient is paid, the escrow account is merged back into the treasury
+    assert_eq!(
+        ledger
+            .account(escrow.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+    assert!(ledger
+        .account(funded.escrow_account_id.as_deref().unwrap())
+        .is_none());
+    assert_eq!(
+        ledger.account(&treasury).unwrap().balance,
+        Money::from_units(TREASURY_BALANCE - 1000)
+    );
 }
 
 #[tokio::test]
-async fn test_cancel_and_refund() {
+async fn test_refund_funded_escrow() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    let escrow = service
+        .cancel_and_refund(created.id, &arbiter(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Cancelled);
+    assert_eq!(
+        ledger
+            .account(escrow.sender_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+}
+
+#[tokio::test]
+async fn test_failed_settlement_can_be_resumed() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    ledger.fail_next_submission(SimulatedFailure::Unreachable);
+    let result = service
+        .release_funds(created.id, &arbiter(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::Ledger(_))));
+
+    let escrow = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Settling);
+    assert_eq!(escrow.locked_funds, Money::from_units(1000));
+
+    // A settling release cannot turn into a refund
+    let result = service
+        .cancel_and_refund(created.id, &arbiter(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::Conflict(_))));
+
+    let escrow = service
+        .release_funds(created.id, &arbiter(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Released);
+    assert_eq!(
+        ledger
+            .account(escrow.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+    assert!(
+        ledger
+            .load_transaction(escrow.settlement_tx_hash.as_deref().unwrap())
+            .unwrap()
+            .unwrap()
+            .successful
+    );
+}
+
+#[tokio::test]
+async fn test_stale_version_is_rejected() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    let funded = service
+        .lock_funds(created.id, xlm(1000), &sender(), Some(created.version))
+        .await
+        .unwrap();
+    assert_eq!(funded.version, created.version + 1);
+
+    let result = service
+        .release_funds(created.id, &arbiter(), None, Some(created.version))
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::VersionMismatch { expected, actual })
+            if expected == created.version && actual == funded.version
+    ));
+    assert_eq!(
+        service
+            .get_escrow(created.id, &sender())
+            .await
+            .unwrap()
+            .status,
+        EscrowStatus::Funded
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_concurrent_releases_settle_once() {
+    let (service, ledger, _) = setup_with_ledger();
+    let service = Arc::new(service);
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    let releases: Vec<_> = (0..2)
+        .map(|_| {
+            let service = service.clone();
+            tokio::spawn(async move {
+                service
+                    .release_funds(created.id, &arbiter(), None, Some(created.version + 1))
+                    .await
+            })
+        })
+        .collect();
+    let mut results = Vec::new();
+    for release in releases {
+        results.push(release.await.unwrap());
+    }
+
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    assert!(results
+        .iter()
+        .any(|result| matches!(result, Err(EscrowError::VersionMismatch { .. }))));
+    assert_eq!(
+        ledger
+            .account(created.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn test_concurrent_fundings_take_their_own_sequence_numbers() {
+    // Each funding holds one connection and briefly takes a second
+    let (service, ledger, _) = setup_with_pool(test_pool_with(6));
+    let service = Arc::new(service);
+    let mut escrows = Vec::new();
+    for _ in 0..3 {
+        let created = service
+            .create_escrow(
+                ledger_escrow(&ledger),
+                participants(),
+                Vec::new(),
+                &sender(),
+            )
+            .await
+            .unwrap();
+        escrows.push(created.id);
+    }
+
+    let fundings: Vec<_> = escrows
+        .into_iter()
+        .map(|escrow_id| {
+            let service = service.clone();
+            tokio::spawn(async move {
+                service
+                    .lock_funds(escrow_id, xlm(1000), &sender(), None)
+                    .await
+            })
+        })
+        .collect();
+    for funding in fundings {
+        assert_eq!(funding.await.unwrap().unwrap().status, EscrowStatus::Funded);
+    }
+}
+
+#[tokio::test]
+async fn test_update_status_cannot_skip_funding() {
     let service = setup_test_db();
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
 
-    let test_escrow = Escrow {
-        id: 0,
-        loan_amount: 1000,
-        loan_term: "12 months".to_string(),
-        purpose_of_loan: "Test loan".to_string(),
-        monthly_income: 5000,
-        status: "".to_string(),
-        sender_address: "sender123".to_string(),
-        recipient_address: "recipient456".to_string(),
-        locked_funds: 0,
-    };
+    let result = service
+        .update_status(created.id, EscrowStatus::Released, &sender(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::InvalidTransition { .. })));
+}
 
-    let created = service.create_escrow(test_escrow).await.unwrap();
+#[tokio::test]
+async fn test_cancel_and_refund() {
+    let service = setup_test_db();
 
-    let result = service.cancel_and_refund(created.id).await;
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    let result = service
+        .cancel_and_refund(created.id, &sender(), Some("Borrower withdrew"), None)
+        .await;
     assert!(result.is_ok());
 
     let escrow = result.unwrap();
-    assert_eq!(escrow.status, EscrowStatus::Cancelled.to_string());
-    assert_eq!(escrow.locked_funds, 0);
+    assert_eq!(escrow.status, EscrowStatus::Cancelled);
+    assert_eq!(escrow.locked_funds, Money::ZERO);
+    assert!(escrow.cancelled_at.is_some());
+    assert!(escrow.funded_at.is_none());
+
+    let history = service.status_history(created.id, &sender()).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].from_status, None);
+    assert_eq!(history[1].from_status, Some(EscrowStatus::Pending));
+    assert_eq!(history[1].to_status, EscrowStatus::Cancelled);
+    assert_eq!(history[1].reason.as_deref(), Some("Borrower withdrew"));
+}
+
+#[tokio::test]
+async fn test_non_participants_cannot_see_escrow() {
+    let service = setup_test_db();
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    let outsider = user("outsider", Role::Lender);
+    assert!(matches!(
+        service.get_escrow(created.id, &outsider).await,
+        Err(EscrowError::Forbidden(_))
+    ));
+    assert!(service
+        .get_escrow(created.id, &user("support", Role::Admin))
+        .await
+        .is_ok());
+
+    // Nor can they open escrows on behalf of others
+    assert!(matches!(
+        service
+            .create_escrow(test_escrow(), participants(), Vec::new(), &outsider)
+            .await,
+        Err(EscrowError::Forbidden(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_service_keys_act_with_their_configured_role() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    let verifier =
+        RequestVerifier::from_config("worker:w-secret,partner:p-secret", Some("worker:admin"))
+            .unwrap();
+    let now = Utc::now().timestamp();
+    let signed_by = |key_id: &str, secret: &[u8]| {
+        let path = format!("/escrows/{}/release", created.id);
+        let nonce = format!("{}-{}", key_id, unique_suffix());
+        let signature = sign(secret, "POST", &path, now, &nonce, b"");
+        verifier
+            .verify(
+                &SignedRequest {
+                    key_id,
+                    timestamp: &now.to_string(),
+                    nonce: &nonce,
+                    signature: &signature,
+                    method: "POST",
+                    path: &path,
+                    body: b"",
+                },
+                now,
+            )
+            .unwrap()
+    };
+
+    // A partner key without a role is no admin
+    let partner = signed_by("partner", b"p-secret");
+    assert!(matches!(
+        service
+            .release_funds(created.id, &partner, None, None)
+            .await,
+        Err(EscrowError::Forbidden(_))
+    ));
+
+    let worker = signed_by("worker", b"w-secret");
+    let released = service
+        .release_funds(created.id, &worker, None, None)
+        .await
+        .unwrap();
+    assert_eq!(released.status, EscrowStatus::Released);
+}
+
+#[tokio::test]
+async fn test_only_sender_cancels_pending_escrow() {
+    let service = setup_test_db();
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    for other in [recipient(), arbiter()] {
+        assert!(matches!(
+            service
+                .cancel_and_refund(created.id, &other, None, None)
+                .await,
+            Err(EscrowError::Forbidden(_))
+        ));
+    }
+    assert!(service
+        .cancel_and_refund(created.id, &sender(), None, None)
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn test_release_needs_both_parties() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    // The parties cannot refund; only the arbiter can
+    assert!(matches!(
+        service
+            .cancel_and_refund(created.id, &sender(), None, None)
+            .await,
+        Err(EscrowError::Forbidden(_))
+    ));
+
+    let escrow = service
+        .release_funds(created.id, &sender(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Funded);
+
+    let escrow = service
+        .release_funds(created.id, &recipient(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Released);
+    assert_eq!(
+        ledger
+            .account(escrow.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+
+    let participants = service.participants(created.id, &sender()).await.unwrap();
+    assert_eq!(participants.len(), 3);
+}
+
+#[tokio::test]
+async fn test_list_escrows_pages_through_own_escrows() {
+    let service = setup_test_db();
+    // Fresh users, so escrows from other tests stay out of the listing
+    let owner = user(&format!("lister-{}", unique_suffix()), Role::Borrower);
+    let counterparty = user(&format!("counterparty-{}", unique_suffix()), Role::Lender);
+    let parties = Participants {
+        sender: owner.uid.clone(),
+        recipient: counterparty.uid.clone(),
+        arbiter: None,
+    };
+
+    let mut created = Vec::new();
+    for amount in [300, 100, 200] {
+        let mut escrow = test_escrow();
+        escrow.loan_amount = Money::from_units(amount);
+        created.push(
+            service
+                .create_escrow(escrow, parties.clone(), Vec::new(), &owner)
+                .await
+                .unwrap(),
+        );
+    }
+    service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    let query = || EscrowQuery {
+        sort: EscrowSort::LoanAmountAsc,
+        limit: Some(2),
+        ..Default::default()
+    };
+    let first = service.list_escrows(query(), &owner).await.unwrap();
+    let amounts: Vec<_> = first.items.iter().map(|e| e.loan_amount.units()).collect();
+    assert_eq!(amounts, [100, 200]);
+
+    let second = service
+        .list_escrows(
+            EscrowQuery {
+                cursor: first.next_cursor.clone(),
+                ..query()
+            },
+            &owner,
+        )
+        .await
+        .unwrap();
+    let amounts: Vec<_> = second.items.iter().map(|e| e.loan_amount.units()).collect();
+    assert_eq!(amounts, [300]);
+    assert!(second.next_cursor.is_none());
+
+    // Filters narrow the listing; an unrelated user sees nothing
+    let amount_query = || EscrowQuery {
+        min_amount: Some(Money::from_units(150)),
+        max_amount: Some(Money::from_units(250)),
+        status: Some("PENDING".to_string()),
+        ..Default::default()
+    };
+    let filtered = service
+        .list_escrows(
+            EscrowQuery {
+                asset_code: Some("XLM".to_string()),
+                ..amount_query()
+            },
+            &counterparty,
+        )
+        .await
+        .unwrap();
+    assert_eq!(filtered.items.len(), 1);
+    assert_eq!(filtered.items[0].id, created[2].id);
+    // Amounts in different assets do not compare
+    let result = service.list_escrows(amount_query(), &counterparty).await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "asset_code",
+            ..
+        })
+    ));
+
+    let outsider = user(&format!("outsider-{}", unique_suffix()), Role::Lender);
+    let listing = service
+        .list_escrows(EscrowQuery::default(), &outsider)
+        .await
+        .unwrap();
+    assert!(listing.items.is_empty());
+
+    // A cursor only works with the sort it came from
+    let result = service
+        .list_escrows(
+            EscrowQuery {
+                cursor: first.next_cursor,
+                ..Default::default()
+            },
+            &owner,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "cursor",
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_admins_list_every_escrow() {
+    let service = setup_test_db();
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    let listing = service
+        .list_escrows(
+            EscrowQuery {
+                created_after: Some(created.created_at),
+                sort: EscrowSort::CreatedAtAsc,
+                limit: Some(100),
+                ..Default::default()
+            },
+            &user("support", Role::Admin),
+        )
+        .await
+        .unwrap();
+    assert!(listing.items.iter().any(|escrow| escrow.id == created.id));
+}
+
+#[tokio::test]
+async fn test_deadlines_must_be_in_the_future() {
+    let service = setup_test_db();
+
+    let mut escrow = test_escrow();
+    escrow.funding_deadline = Some(Utc::now() - Duration::minutes(1));
+    let result = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "funding_deadline",
+            ..
+        })
+    ));
+
+    let mut escrow = test_escrow();
+    escrow.funding_deadline = Some(Utc::now() + Duration::days(2));
+    escrow.release_deadline = Some(Utc::now() + Duration::days(1));
+    let result = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "release_deadline",
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_unfunded_escrow_expires_after_funding_deadline() {
+    let service = setup_test_db();
+    let deadline = Utc::now() + Duration::hours(1);
+    let mut escrow = test_escrow();
+    escrow.funding_deadline = Some(deadline);
+    let created = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    service
+        .enforce_deadlines(deadline + Duration::seconds(1))
+        .await
+        .unwrap();
+    let escrow = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Expired);
+    assert!(escrow.expired_at.is_some());
+
+    let history = service.status_history(created.id, &sender()).await.unwrap();
+    let expiry = history.last().unwrap();
+    assert_eq!(expiry.to_status, EscrowStatus::Expired);
+    assert_eq!(expiry.actor, SYSTEM_ACTOR);
+}
+
+#[tokio::test]
+async fn test_unreleased_escrow_is_refunded_after_release_deadline() {
+    let (service, ledger, _) = setup_with_ledger();
+    // Far enough out that other tests' deadline runs leave it alone
+    let deadline = Utc::now() + Duration::days(3650);
+    let mut escrow = ledger_escrow(&ledger);
+    escrow.release_deadline = Some(deadline);
+    let created = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    let run = service
+        .enforce_deadlines(deadline + Duration::seconds(1))
+        .await
+        .unwrap();
+    assert!(run.refunded >= 1);
+
+    let escrow = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Cancelled);
+    assert!(escrow.cancelled_at.is_some());
+    assert_eq!(
+        ledger
+            .account(escrow.sender_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+
+    let history = service.status_history(created.id, &sender()).await.unwrap();
+    assert!(history
+        .iter()
+        .filter(|entry| entry.from_status == Some(EscrowStatus::Funded))
+        .all(|entry| entry.actor == SYSTEM_ACTOR));
+}
+
+fn milestones() -> Vec<NewMilestone> {
+    vec![
+        NewMilestone {
+            description: "Deposit".to_string(),
+            amount: Money::from_units(400),
+        },
+        NewMilestone {
+            description: "Completion".to_string(),
+            amount: Money::from_units(600),
+        },
+    ]
+}
+
+#[tokio::test]
+async fn test_milestones_must_add_up_to_loan_amount() {
+    let service = setup_test_db();
+    let mut milestones = milestones();
+    milestones[1].amount = Money::from_units(500);
+
+    let result = service
+        .create_escrow(test_escrow(), participants(), milestones, &sender())
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "milestones",
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_milestones_release_in_tranches() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            milestones(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    let result = service
+        .lock_funds(created.id, xlm(900), &sender(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "amount",
+            ..
+        })
+    ));
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    let escrow = service
+        .release_milestone(created.id, 1, &arbiter(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::PartiallyReleased);
+    assert_eq!(escrow.locked_funds, Money::from_units(600));
+    assert_eq!(
+        ledger
+            .account(escrow.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(400)
+    );
+
+    // The last milestone needs both parties, then closes the escrow
+    let escrow = service
+        .release_milestone(created.id, 2, &sender(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::PartiallyReleased);
+    let escrow = service
+        .release_milestone(created.id, 2, &recipient(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Released);
+    assert_eq!(escrow.locked_funds, Money::ZERO);
+    assert_eq!(
+        ledger
+            .account(escrow.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(1000)
+    );
+
+    let milestones = service.milestones(created.id, &sender()).await.unwrap();
+    assert!(milestones
+        .iter()
+        .all(|milestone| milestone.released_at.is_some() && milestone.release_tx_hash.is_some()));
+
+    let result = service
+        .release_milestone(created.id, 1, &arbiter(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::Conflict(_))));
+    let result = service
+        .release_milestone(created.id, 3, &arbiter(), None, None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::MilestoneNotFound { position: 3, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_milestone_payout_is_not_repeated_after_lost_response() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            milestones(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+
+    // Paid on the ledger, but the service never hears back
+    ledger.fail_next_submission(SimulatedFailure::ResponseLost);
+    let result = service
+        .release_milestone(created.id, 1, &arbiter(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::Ledger(_))));
+    let recipient_address = created.recipient_address.as_str();
+    assert_eq!(
+        ledger.account(recipient_address).unwrap().balance,
+        Money::from_units(400)
+    );
+
+    let escrow = service
+        .release_milestone(created.id, 1, &arbiter(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.locked_funds, Money::from_units(600));
+    assert_eq!(
+        ledger.account(recipient_address).unwrap().balance,
+        Money::from_units(400)
+    );
+}
+
+#[tokio::test]
+async fn test_ledger_operations_need_a_single_connection() {
+    // Holding the row lock's connection while waiting for a second one
+    // would time out here
+    let (service, ledger, _) = setup_with_pool(test_pool_with(1));
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            milestones(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+    let escrow = service
+        .release_milestone(created.id, 1, &arbiter(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.locked_funds, Money::from_units(600));
+}
+
+fn dispute() -> OpenDispute {
+    OpenDispute {
+        reason: "Goods never arrived".to_string(),
+        evidence: vec![NewEvidence {
+            name: "Shipping receipt".to_string(),
+            url: "https://files.example.com/receipt.pdf".to_string(),
+        }],
+    }
+}
+
+#[tokio::test]
+async fn test_dispute_freezes_funds_until_split() {
+    let (service, ledger, _) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+
+    // Nothing to dispute before funding
+    let result = service
+        .open_dispute(created.id, dispute(), &recipient(), None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::InvalidTransition { .. })));
+
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+    let opened = service
+        .open_dispute(created.id, dispute(), &recipient(), None)
+        .await
+        .unwrap();
+    assert_eq!(opened.evidence.len(), 1);
+
+    let result = service
+        .release_funds(created.id, &arbiter(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::InvalidTransition { .. })));
+    let result = service
+        .cancel_and_refund(created.id, &arbiter(), None, None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::InvalidTransition { .. })));
+
+    let split = |recipient_amount| DisputeResolution {
+        outcome: DisputeOutcome::Split,
+        recipient_amount: Some(recipient_amount),
+        justification: "Partial delivery confirmed".to_string(),
+    };
+    let result = service
+        .resolve_dispute(created.id, split(Money::from_units(300)), &sender(), None)
+        .await;
+    assert!(matches!(result, Err(EscrowError::Forbidden(_))));
+    let result = service
+        .resolve_dispute(created.id, split(Money::from_units(1000)), &arbiter(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "recipient_amount",
+            ..
+        })
+    ));
+
+    let escrow = service
+        .resolve_dispute(created.id, split(Money::from_units(300)), &arbiter(), None)
+        .await
+        .unwrap();
+    assert_eq!(escrow.status, EscrowStatus::Released);
+    assert_eq!(escrow.locked_funds, Money::ZERO);
+    assert!(escrow.settlement_recipient_amount.is_none());
+    assert_eq!(
+        ledger
+            .account(escrow.recipient_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(300)
+    );
+    assert_eq!(
+        ledger
+            .account(escrow.sender_address.as_str())
+            .unwrap()
+            .balance,
+        Money::from_units(700)
+    );
+
+    let disputes = service.disputes(created.id, &sender()).await.unwrap();
+    let resolved = &disputes[0].dispute;
+    assert_eq!(resolved.outcome, Some(DisputeOutcome::Split));
+    assert_eq!(resolved.sender_amount, Some(Money::from_units(700)));
+    assert_eq!(resolved.resolved_by.as_deref(), Some("arbiter"));
+}
+
+fn usdc_escrow(ledger: &InMemoryLedger) -> NewEscrow {
+    let mut escrow = ledger_escrow(ledger);
+    escrow.asset_code = "USDC".to_string();
+    escrow.asset_issuer = Some(USDC_ISSUER.to_string());
+    escrow.loan_amount = Money::from_units(25_000_000);
+    escrow.monthly_income = Money::from_units(500_000_000);
+    escrow
+}
+
+fn usdc(units: i64) -> LockFunds {
+    LockFunds {
+        amount: Money::from_units(units),
+        asset_code: "USDC".to_string(),
+        asset_issuer: Some(USDC_ISSUER.to_string()),
+    }
+}
+
+#[tokio::test]
+async fn test_create_escrow_checks_asset() {
+    let (service, ledger, _) = setup_with_ledger();
+
+    let mut escrow = usdc_escrow(&ledger);
+    escrow.asset_code = "EURC".to_string();
+    let result = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "asset_code",
+            ..
+        })
+    ));
+
+    // USDC has two decimals, so 2.5000001 USDC cannot be moved
+    let mut escrow = usdc_escrow(&ledger);
+    escrow.loan_amount = Money::from_units(25_000_001);
+    let result = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "loan_amount",
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_issued_asset_escrow_needs_trustlines() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let escrow = usdc_escrow(&ledger);
+    let created = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+    assert_eq!(created.asset_code, "USDC");
+
+    let result = service
+        .lock_funds(created.id, usdc(25_000_000), &sender(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "recipient_address",
+            ..
+        })
+    ));
+
+    ledger.fund_trustline(
+        created.recipient_address.as_str(),
+        "USDC",
+        USDC_ISSUER,
+        Money::ZERO,
+    );
+    ledger.fund_trustline(
+        created.sender_address.as_str(),
+        "USDC",
+        USDC_ISSUER,
+        Money::ZERO,
+    );
+    // The amount must be in the escrow's asset
+    let result = service
+        .lock_funds(created.id, xlm(25_000_000), &sender(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "asset_code",
+            ..
+        })
+    ));
+    let funded = service
+        .lock_funds(created.id, usdc(25_000_000), &sender(), None)
+        .await
+        .unwrap();
+    let escrow_account = ledger
+        .account(funded.escrow_account_id.as_deref().unwrap())
+        .unwrap();
+    // Only the reserve is in XLM, plus one base reserve for the trustline
+    assert_eq!(
+        escrow_account.balance,
+        Money::from_units(ESCROW_ACCOUNT_RESERVE_STROOPS + BASE_RESERVE_STROOPS)
+    );
+    assert_eq!(
+        escrow_account.trustlines[0].balance,
+        Money::from_units(25_000_000)
+    );
+
+    service
+        .release_funds(created.id, &arbiter(), None, None)
+        .await
+        .unwrap();
+    let recipient = ledger.account(created.recipient_address.as_str()).unwrap();
+    assert_eq!(
+        recipient.trustlines[0].balance,
+        Money::from_units(25_000_000)
+    );
+    assert!(ledger
+        .account(funded.escrow_account_id.as_deref().unwrap())
+        .is_none());
+    assert_eq!(
+        ledger.account(&treasury).unwrap().balance,
+        Money::from_units(TREASURY_BALANCE)
+    );
+}
+
+#[tokio::test]
+async fn test_funding_requires_existing_accounts() {
+    let (service, ledger, _) = setup_with_ledger();
+    let escrow = test_escrow();
+    ledger.fund_account(escrow.sender_address.as_str(), Money::ZERO);
+    let created = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    let result = service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(EscrowError::Validation {
+            field: "recipient_address",
+            ..
+        })
+    ));
+    assert_eq!(
+        service
+            .get_escrow(created.id, &sender())
+            .await
+            .unwrap()
+            .status,
+        EscrowStatus::Pending
+    );
+}
+
+#[tokio::test]
+async fn test_release_to_muxed_address_pays_underlying_account() {
+    let (service, ledger, _) = setup_with_ledger();
+    let mut escrow = ledger_escrow(&ledger);
+    let exchange = KeyPair::random().unwrap().public_key().clone();
+    ledger.fund_account(&exchange.account_id(), Money::ZERO);
+    escrow.recipient_address =
+        StellarAddress::parse(&MuxedEd25519PublicKey::new(exchange.clone(), 7).account_id())
+            .unwrap();
+
+    let created = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+    service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+    service
+        .release_funds(created.id, &arbiter(), None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        ledger.account(&exchange.account_id()).unwrap().balance,
+        Money::from_units(1000)
+    );
+}
+
+/// Pays `amount` XLM from `from` to `to`, as a wallet outside the API would.
+fn pay(ledger: &InMemoryLedger, from: &PublicKey, to: &str, memo: Memo, amount: Money) -> String {
+    let sequence = ledger.account(&from.account_id()).unwrap().sequence + 1;
+    let payment = Operation::new_payment()
+        .with_destination(PublicKey::from_account_id(to).unwrap())
+        .with_amount(Stroops::from(amount))
+        .unwrap()
+        .with_asset(Asset::new_native())
+        .build()
+        .unwrap();
+    let transaction = Transaction::builder(from.clone(), sequence, MIN_BASE_FEE)
+        .with_memo(memo)
+        .add_operation(payment)
+        .into_transaction()
+        .unwrap();
+
+    let submitted = ledger.submit_transaction(transaction).unwrap();
+    assert!(submitted.successful);
+    submitted.hash
+}
+
+/// A pending escrow whose sender's wallet holds 5000 units of XLM, and how
+/// to deposit into it.
+async fn deposit_escrow(
+    service: &EscrowService,
+    ledger: &InMemoryLedger,
+) -> (Escrow, PublicKey, DepositInstructions) {
+    let escrow = ledger_escrow(ledger);
+    let wallet = PublicKey::from_account_id(escrow.sender_address.as_str()).unwrap();
+    ledger.fund_account(&wallet.account_id(), Money::from_units(5000));
+    let created = service
+        .create_escrow(escrow, participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+    let instructions = service
+        .deposit_instructions(created.id, &recipient())
+        .await
+        .unwrap();
+    (created, wallet, instructions)
+}
+
+fn memo_id(instructions: &DepositInstructions) -> Memo {
+    assert_eq!(instructions.memo_type, "id");
+    Memo::Id(instructions.memo.parse().unwrap())
+}
+
+#[tokio::test]
+async fn test_deposit_to_treasury_funds_pending_escrow() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let (created, wallet, instructions) = deposit_escrow(&service, &ledger).await;
+    assert_eq!(instructions.destination, treasury);
+    assert_eq!(instructions.asset_code, "XLM");
+    assert_eq!(instructions.amount, Money::from_units(1000));
+    assert_eq!(instructions.memo, created.deposit_memo_id.to_string());
+
+    let memo = memo_id(&instructions);
+    let deposit = pay(
+        &ledger,
+        &wallet,
+        &treasury,
+        memo.clone(),
+        instructions.amount,
+    );
+    let run = service.reconcile_ledger().await.unwrap();
+    assert_eq!((run.deposits, run.funded, run.refunded), (1, 1, 0));
+
+    let funded = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(funded.status, EscrowStatus::Funded);
+    assert_eq!(funded.locked_funds, Money::from_units(1000));
+    assert_eq!(funded.deposit_tx_hash.as_deref(), Some(deposit.as_str()));
+    let history = service.status_history(created.id, &sender()).await.unwrap();
+    assert_eq!(history.last().unwrap().actor, SYSTEM_ACTOR);
+    assert!(matches!(
+        service.deposit_instructions(created.id, &sender()).await,
+        Err(EscrowError::InvalidTransition { .. })
+    ));
+
+    // The cursor is stored, so the deposit is not read again
+    let run = service.reconcile_ledger().await.unwrap();
+    assert_eq!(run.deposits, 0);
+
+    // Paying again cannot fund it twice: the payment goes back
+    pay(&ledger, &wallet, &treasury, memo, instructions.amount);
+    let run = service.reconcile_ledger().await.unwrap();
+    assert_eq!((run.deposits, run.funded, run.refunded), (1, 0, 1));
+    assert_eq!(
+        ledger.account(&wallet.account_id()).unwrap().balance,
+        Money::from_units(4000)
+    );
+    let deposits = service.deposits(created.id, &sender()).await.unwrap();
+    assert_eq!(deposits.len(), 2);
+    assert_eq!(deposits[1].credited_amount, Money::ZERO);
+    assert_eq!(
+        deposits[1].refund_reason.as_deref(),
+        Some("Escrow is FUNDED")
+    );
+    assert!(deposits[1].refunded_at.is_some());
+}
+
+#[tokio::test]
+async fn test_short_and_excess_deposits_are_refunded() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let (created, wallet, instructions) = deposit_escrow(&service, &ledger).await;
+    let memo = memo_id(&instructions);
+
+    pay(
+        &ledger,
+        &wallet,
+        &treasury,
+        memo.clone(),
+        Money::from_units(600),
+    );
+    let run = service.reconcile_ledger().await.unwrap();
+    assert_eq!((run.funded, run.refunded), (0, 1));
+    let pending = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(pending.status, EscrowStatus::Pending);
+    assert_eq!(
+        ledger.account(&wallet.account_id()).unwrap().balance,
+        Money::from_units(5000)
+    );
+
+    pay(&ledger, &wallet, &treasury, memo, Money::from_units(1500));
+    let run = service.reconcile_ledger().await.unwrap();
+    assert_eq!((run.funded, run.refunded), (1, 1));
+    let funded = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(funded.locked_funds, Money::from_units(1000));
+    assert_eq!(
+        ledger.account(&wallet.account_id()).unwrap().balance,
+        Money::from_units(4000)
+    );
+
+    let deposits = service.deposits(created.id, &sender()).await.unwrap();
+    let reasons: Vec<_> = deposits
+        .iter()
+        .map(|deposit| (deposit.credited_amount, deposit.refund_amount))
+        .collect();
+    assert_eq!(
+        reasons,
+        [
+            (Money::ZERO, Money::from_units(600)),
+            (Money::from_units(1000), Money::from_units(500)),
+        ]
+    );
+    assert_eq!(
+        deposits[0].refund_reason.as_deref(),
+        Some("Underpaid: 0.0000600 of 0.0001000 XLM due")
+    );
+}
+
+#[tokio::test]
+async fn test_deposit_is_retried_after_transient_failure() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let (created, wallet, instructions) = deposit_escrow(&service, &ledger).await;
+    let deposit = pay(
+        &ledger,
+        &wallet,
+        &treasury,
+        memo_id(&instructions),
+        instructions.amount,
+    );
+
+    // Funding the escrow from the deposit cannot reach the ledger, so the
+    // run stops without moving past the deposit
+    ledger.fail_next_submission(SimulatedFailure::Unreachable);
+    assert!(matches!(
+        service.reconcile_ledger().await,
+        Err(EscrowError::Ledger(_))
+    ));
+    let pending = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(pending.status, EscrowStatus::Pending);
+    assert!(service
+        .discrepancies(created.id, &sender())
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Once the ledger is back, the next run funds the escrow from it
+    let run = service.reconcile_ledger().await.unwrap();
+    assert_eq!((run.deposits, run.funded, run.failed), (1, 1, 0));
+    let funded = service.get_escrow(created.id, &sender()).await.unwrap();
+    assert_eq!(funded.status, EscrowStatus::Funded);
+    assert_eq!(funded.deposit_tx_hash.as_deref(), Some(deposit.as_str()));
+}
+
+#[tokio::test]
+async fn test_escrow_account_balance_is_checked_against_locked_funds() {
+    let (service, ledger, treasury) = setup_with_ledger();
+    let created = service
+        .create_escrow(
+            ledger_escrow(&ledger),
+            participants(),
+            Vec::new(),
+            &sender(),
+        )
+        .await
+        .unwrap();
+    let funded = service
+        .lock_funds(created.id, xlm(1000), &sender(), None)
+        .await
+        .unwrap();
+    let escrow_account = funded.escrow_account_id.unwrap();
+    assert_eq!(service.reconcile_ledger().await.unwrap().discrepancies, 0);
+
+    // Funds sent to the escrow account behind the API's back
+    let treasury_key = PublicKey::from_account_id(&treasury).unwrap();
+    pay(
+        &ledger,
+        &treasury_key,
+        &escrow_account,
+        Memo::None,
+        Money::from_units(500),
+    );
+    assert_eq!(service.reconcile_ledger().await.unwrap().discrepancies, 1);
+
+    let discrepancies = service.discrepancies(created.id, &sender()).await.unwrap();
+    assert_eq!(discrepancies.len(), 1);
+    assert_eq!(discrepancies[0].kind, DiscrepancyKind::BalanceMismatch);
+    assert_eq!(discrepancies[0].expected_amount, Money::from_units(1000));
+    assert_eq!(discrepancies[0].actual_amount, Money::from_units(1500));
+    assert!(discrepancies[0].resolved_at.is_none());
+
+    // Once they are sent back the balances agree again
+    let escrow_key = PublicKey::from_account_id(&escrow_account).unwrap();
+    pay(
+        &ledger,
+        &escrow_key,
+        &treasury,
+        Memo::None,
+        Money::from_units(500),
+    );
+    assert_eq!(service.reconcile_ledger().await.unwrap().discrepancies, 0);
+    let discrepancies = service.discrepancies(created.id, &sender()).await.unwrap();
+    assert!(discrepancies[0].resolved_at.is_some());
+}
+
+#[tokio::test]
+async fn test_timestamps_serialize_as_rfc3339() {
+    let service = setup_test_db();
+    let created = service
+        .create_escrow(test_escrow(), participants(), Vec::new(), &sender())
+        .await
+        .unwrap();
+
+    let json = serde_json::to_value(&created).unwrap();
+    let created_at = json["created_at"].as_str().unwrap();
+    assert_eq!(
+        chrono::DateTime::parse_from_rfc3339(created_at).unwrap(),
+        created.created_at
+    );
+    assert!(json["funded_at"].is_null());
+}
+
+#[test]
+fn test_status_uses_database_labels() {
+    assert_eq!(
+        serde_json::to_string(&EscrowStatus::Funded).unwrap(),
+        "\"FUNDED\""
+    );
+    assert_eq!(
+        serde_json::from_str::<EscrowStatus>("\"CANCELLED\"").unwrap(),
+        EscrowStatus::Cancelled
+    );
+    assert!(serde_json::from_str::<EscrowStatus>("\"funded\"").is_err());
 }
@@ -0,0 +1,194 @@
+use crate::errors::escrow::EscrowError;
+use crate::routes::body::MAX_BODY_BYTES;
+use crate::routes::idempotency::{idempotency, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAY_HEADER};
+use crate::services::idempotency::{IdempotencyOutcome, IdempotencyService, KEY_TTL_HOURS};
+use crate::tests::test_pool;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware,
+    routing::post,
+    Json, Router,
+};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower::ServiceExt;
+
+const CALLER: &str = "idempotency-tester";
+
+/// Keys live in the shared test database, so every test needs fresh ones.
+fn unique_key(name: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("{}-{}", name, nanos)
+}
+
+#[tokio::test]
+async fn test_completed_key_replays_response() {
+    let service = IdempotencyService::new(test_pool());
+    let key = unique_key("replay");
+
+    assert_eq!(
+        service.begin(CALLER, &key, "hash").await.unwrap(),
+        IdempotencyOutcome::Started
+    );
+    let headers = vec![("etag".to_string(), "\"2\"".to_string())];
+    service
+        .complete(CALLER, &key, 200, &headers, "{\"id\":1}")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        service.begin(CALLER, &key, "hash").await.unwrap(),
+        IdempotencyOutcome::Replay {
+            status: 200,
+            headers,
+            body: "{\"id\":1}".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_key_reused_for_other_request_conflicts() {
+    let service = IdempotencyService::new(test_pool());
+    let key = unique_key("reuse");
+
+    service.begin(CALLER, &key, "hash").await.unwrap();
+    service
+        .complete(CALLER, &key, 200, &[], "{}")
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        service.begin(CALLER, &key, "other-hash").await,
+        Err(EscrowError::Conflict(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_key_in_progress_conflicts_until_abandoned() {
+    let service = IdempotencyService::new(test_pool());
+    let key = unique_key("in-progress");
+
+    service.begin(CALLER, &key, "hash").await.unwrap();
+    assert!(matches!(
+        service.begin(CALLER, &key, "hash").await,
+        Err(EscrowError::Conflict(_))
+    ));
+
+    service.abandon(CALLER, &key).await.unwrap();
+    assert_eq!(
+        service.begin(CALLER, &key, "hash").await.unwrap(),
+        IdempotencyOutcome::Started
+    );
+}
+
+#[tokio::test]
+async fn test_keys_are_scoped_to_the_caller() {
+    let service = IdempotencyService::new(test_pool());
+    let key = unique_key("scoped");
+
+    service.begin(CALLER, &key, "hash").await.unwrap();
+    service
+        .complete(CALLER, &key, 200, &[], "{}")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        service
+            .begin("someone-else", &key, "other-hash")
+            .await
+            .unwrap(),
+        IdempotencyOutcome::Started
+    );
+}
+
+#[tokio::test]
+async fn test_expired_key_is_claimed_again_and_purged() {
+    use crate::schema::idempotency_keys::dsl::*;
+
+    let pool = test_pool();
+    let service = IdempotencyService::new(pool.clone());
+    let key = unique_key("expired");
+
+    service.begin(CALLER, &key, "hash").await.unwrap();
+    service
+        .complete(CALLER, &key, 200, &[], "{}")
+        .await
+        .unwrap();
+    let long_ago = Utc::now() - Duration::hours(KEY_TTL_HOURS + 1);
+    diesel::update(idempotency_keys.find((CALLER, &key)))
+        .set(created_at.eq(long_ago))
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+
+    // Forgotten, so another request may use it
+    assert_eq!(
+        service.begin(CALLER, &key, "other-hash").await.unwrap(),
+        IdempotencyOutcome::Started
+    );
+
+    diesel::update(idempotency_keys.find((CALLER, &key)))
+        .set(created_at.eq(long_ago))
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    assert!(service.purge_expired(Utc::now()).await.unwrap() >= 1);
+    let remaining: i64 = idempotency_keys
+        .filter(idempotency_key.eq(&key))
+        .count()
+        .get_result(&mut pool.get().unwrap())
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[tokio::test]
+async fn test_middleware_runs_handler_once_per_key() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route(
+            "/counter",
+            post(|State(calls): State<Arc<AtomicUsize>>| async move {
+                let count = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                ([(header::ETAG, format!("\"{}\"", count))], Json(count))
+            }),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(IdempotencyService::new(test_pool())),
+            idempotency,
+        ))
+        .with_state(calls.clone());
+
+    let key = unique_key("middleware");
+    let request = |body: &'static str| {
+        Request::post("/counter")
+            .header(IDEMPOTENCY_KEY_HEADER, key.as_str())
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let first = app.clone().oneshot(request("{}")).await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+
+    let retry = app.clone().oneshot(request("{}")).await.unwrap();
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert!(retry.headers().contains_key(IDEMPOTENT_REPLAY_HEADER));
+    assert_eq!(retry.headers()[header::ETAG], "\"1\"");
+    let body = hyper::body::to_bytes(retry.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"1");
+
+    let reused = app.clone().oneshot(request("{\"x\":1}")).await.unwrap();
+    assert_eq!(reused.status(), StatusCode::CONFLICT);
+
+    let mut oversized = request("");
+    *oversized.body_mut() = Body::from(vec![b' '; MAX_BODY_BYTES + 1]);
+    let oversized = app.clone().oneshot(oversized).await.unwrap();
+    assert_eq!(oversized.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,152 @@
+use crate::errors::escrow::EscrowError;
+use crate::models::money::Money;
+use crate::services::ledger::{InMemoryLedger, LedgerClient, SimulatedFailure};
+use crate::services::stellar::{
+    build_escrow_account_transaction, StellarConfig, SupportedAsset, ESCROW_ACCOUNT_RESERVE_STROOPS,
+};
+use stellar_base::{Asset, KeyPair, Memo, Network};
+
+fn setup() -> (StellarConfig, InMemoryLedger) {
+    let config = StellarConfig {
+        network: Network::new_test(),
+        horizon_url: "https://horizon-testnet.stellar.org".to_string(),
+        escrow_keypair: KeyPair::random().unwrap(),
+        supported_assets: vec![SupportedAsset::native()],
+    };
+    let ledger = InMemoryLedger::new(Network::new_test());
+    ledger.fund_account(
+        &config.treasury().account_id(),
+        Money::from_units(100_000_000),
+    );
+    (config, ledger)
+}
+
+#[test]
+fn test_submission_requires_next_sequence() {
+    let (config, ledger) = setup();
+    let escrow_account = KeyPair::random().unwrap();
+
+    let transaction = build_escrow_account_transaction(
+        &config,
+        5,
+        1,
+        &escrow_account,
+        &Asset::new_native(),
+        Money::from_units(1000),
+    )
+    .unwrap();
+    assert!(matches!(
+        ledger.submit_transaction(transaction),
+        Err(EscrowError::Ledger(_))
+    ));
+
+    let transaction = build_escrow_account_transaction(
+        &config,
+        1,
+        1,
+        &escrow_account,
+        &Asset::new_native(),
+        Money::from_units(1000),
+    )
+    .unwrap();
+    assert!(ledger.submit_transaction(transaction).unwrap().successful);
+    assert_eq!(
+        ledger
+            .load_account(&config.treasury().account_id())
+            .unwrap()
+            .sequence,
+        1
+    );
+}
+
+#[test]
+fn test_rejected_transaction_consumes_sequence_only() {
+    let (config, ledger) = setup();
+    let escrow_account = KeyPair::random().unwrap();
+    let treasury = config.treasury().account_id();
+
+    ledger.fail_next_submission(SimulatedFailure::Rejected);
+    let transaction = build_escrow_account_transaction(
+        &config,
+        1,
+        1,
+        &escrow_account,
+        &Asset::new_native(),
+        Money::from_units(1000),
+    )
+    .unwrap();
+    let result = ledger.submit_transaction(transaction).unwrap();
+
+    assert!(!result.successful);
+    assert_eq!(ledger.load_transaction(&result.hash).unwrap(), Some(result));
+    let treasury = ledger.account(&treasury).unwrap();
+    assert_eq!(treasury.sequence, 1);
+    assert_eq!(treasury.balance, Money::from_units(100_000_000));
+    assert!(ledger
+        .account(&escrow_account.public_key().account_id())
+        .is_none());
+}
+
+#[test]
+fn test_underfunded_transaction_fails() {
+    let (config, ledger) = setup();
+    let escrow_account = KeyPair::random().unwrap();
+
+    let transaction = build_escrow_account_transaction(
+        &config,
+        1,
+        1,
+        &escrow_account,
+        &Asset::new_native(),
+        Money::from_units(100_000_000),
+    )
+    .unwrap();
+    assert!(!ledger.submit_transaction(transaction).unwrap().successful);
+}
+
+#[test]
+fn test_payments_page_from_cursor() {
+    let (config, ledger) = setup();
+    let treasury = config.treasury().account_id();
+
+    let mut hashes = Vec::new();
+    for (sequence, escrow_id) in [(1, 7), (2, 8)] {
+        let transaction = build_escrow_account_transaction(
+            &config,
+            sequence,
+            escrow_id,
+            &KeyPair::random().unwrap(),
+            &Asset::new_native(),
+            Money::from_units(1000),
+        )
+        .unwrap();
+        hashes.push(ledger.submit_transaction(transaction).unwrap().hash);
+    }
+
+    let first = ledger.payments(&treasury, None, 1).unwrap();
+    assert_eq!(first.payments.len(), 1);
+    let created = &first.payments[0];
+    assert_eq!(created.from, treasury);
+    assert_eq!(created.transaction_hash, hashes[0]);
+    assert_eq!(created.memo, Memo::Text("escrow:7".to_string()));
+    assert_eq!(created.asset_code, "XLM");
+    assert_eq!(
+        created.amount,
+        Money::from_units(1000 + ESCROW_ACCOUNT_RESERVE_STROOPS)
+    );
+
+    let rest = ledger
+        .payments(&treasury, first.cursor.as_deref(), 200)
+        .unwrap();
+    assert_eq!(rest.payments.len(), 1);
+    assert_eq!(rest.payments[0].transaction_hash, hashes[1]);
+    let end = ledger
+        .payments(&treasury, rest.cursor.as_deref(), 200)
+        .unwrap();
+    assert!(end.payments.is_empty() && end.cursor.is_none());
+
+    // Only the account's own history is listed
+    let escrow_account = &rest.payments[0].to;
+    let page = ledger.payments(escrow_account, None, 200).unwrap();
+    assert_eq!(page.payments, rest.payments);
+}
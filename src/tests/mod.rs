@@ -1 +1,39 @@
-pub mod escrow_tests;
\ No newline at end of file
+pub mod auth_tests;
+pub mod config_tests;
+pub mod escrow_tests;
+pub mod idempotency_tests;
+pub mod ledger_tests;
+pub mod money_tests;
+pub mod route_tests;
+pub mod signing_tests;
+pub mod state_machine_tests;
+pub mod stellar_address_tests;
+pub mod stellar_tests;
+pub mod telemetry_tests;
+
+use crate::db::{self, DbPool};
+use dotenvy::dotenv;
+use std::sync::Once;
+
+static MIGRATE: Once = Once::new();
+
+/// Pool on the `DATABASE_URL` test database, migrated once per test run.
+pub fn test_pool() -> DbPool {
+    test_pool_with(2)
+}
+
+/// `test_pool` with room for `max_connections`, for tests running several
+/// requests at once.
+pub fn test_pool_with(max_connections: u32) -> DbPool {
+    dotenv().expect(".env file not found");
+
+    let database_url =
+        std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file");
+
+    let pool = db::init_pool(&database_url, max_connections);
+    MIGRATE.call_once(|| {
+        let mut conn = pool.get().expect("Failed to get database connection");
+        db::run_pending_migrations(&mut conn).expect("Failed to run migrations");
+    });
+    pool
+}
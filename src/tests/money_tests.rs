@@ -0,0 +1,89 @@
+use std::cmp::Ordering;
+
+use crate::models::money::{AmountError, AssetAmount, AssetId, Money};
+
+#[test]
+fn test_stellar_amount_strings_round_trip() {
+    for (text, units) in [
+        ("0.0000000", 0),
+        ("0.0000001", 1),
+        ("12.5000000", 125_000_000),
+        ("-3.0000020", -30_000_020),
+        ("922337203685.4775807", i64::MAX),
+    ] {
+        let money = Money::from_units(units);
+        assert_eq!(money.to_string(), text);
+        assert_eq!(text.parse::<Money>().unwrap(), money);
+    }
+
+    assert_eq!(
+        "12.5".parse::<Money>().unwrap(),
+        Money::from_units(125_000_000)
+    );
+    assert_eq!("7".parse::<Money>().unwrap(), Money::from_units(70_000_000));
+}
+
+#[test]
+fn test_invalid_amount_strings_are_rejected() {
+    for text in [
+        "",
+        ".5",
+        "1.00000001",
+        "1e7",
+        "+1",
+        "1,5",
+        "922337203685.4775808",
+    ] {
+        assert!(text.parse::<Money>().is_err(), "{}", text);
+    }
+}
+
+fn usdc(units: i64) -> AssetAmount {
+    AssetAmount::new(
+        Money::from_units(units),
+        AssetId::new("USDC", Some("GISSUER")),
+    )
+}
+
+#[test]
+fn test_arithmetic_is_checked() {
+    let one = usdc(1);
+    assert_eq!(usdc(i64::MAX).checked_add(&one), Err(AmountError::Overflow));
+    assert_eq!(usdc(5).checked_sub(&one), Ok(usdc(4)));
+    assert_eq!(usdc(5).compare(&one), Ok(Ordering::Greater));
+    assert_eq!(
+        AssetAmount::checked_sum(&one.asset, [&usdc(400), &usdc(600)]),
+        Ok(usdc(1000))
+    );
+    assert_eq!(
+        AssetAmount::checked_sum(&one.asset, [&usdc(i64::MAX), &one]),
+        Err(AmountError::Overflow)
+    );
+}
+
+#[test]
+fn test_amounts_in_different_assets_do_not_mix() {
+    let xlm = AssetAmount::new(Money::from_units(5), AssetId::native());
+    let other_usdc = AssetAmount::new(Money::from_units(5), AssetId::new("USDC", Some("GOTHER")));
+    for other in [&xlm, &other_usdc] {
+        let mismatch = AmountError::AssetMismatch {
+            expected: usdc(5).asset,
+            actual: other.asset.clone(),
+        };
+        assert_eq!(usdc(5).compare(other).unwrap_err(), mismatch);
+        assert_eq!(usdc(5).checked_add(other).unwrap_err(), mismatch);
+        assert_eq!(usdc(5).checked_sub(other).unwrap_err(), mismatch);
+        assert_eq!(
+            AssetAmount::checked_sum(&usdc(0).asset, [&usdc(5), other]).unwrap_err(),
+            mismatch
+        );
+    }
+}
+
+#[test]
+fn test_serializes_as_units() {
+    let money = Money::from_units(125_000_000);
+    assert_eq!(serde_json::to_string(&money).unwrap(), "125000000");
+    assert_eq!(serde_json::from_str::<Money>("125000000").unwrap(), money);
+    assert!(serde_json::from_str::<Money>("12.5").is_err());
+}
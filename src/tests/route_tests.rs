@@ -0,0 +1,94 @@
+use crate::models::escrow::LockFunds;
+use crate::routes::escrow::{OptionalJson, TransitionRequest};
+use crate::routes::extract::{Json, Path};
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::Response,
+    routing::post,
+    Router,
+};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_transition_body_is_optional_but_must_parse() {
+    let app = Router::new().route(
+        "/release",
+        post(
+            |OptionalJson(request): OptionalJson<TransitionRequest>| async move {
+                request.reason.unwrap_or_default()
+            },
+        ),
+    );
+    let request = |body: &'static str| {
+        Request::post("/release")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let missing = app
+        .clone()
+        .oneshot(Request::post("/release").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), StatusCode::OK);
+
+    let given = app
+        .clone()
+        .oneshot(request(r#"{"reason":"Delivered"}"#))
+        .await
+        .unwrap();
+    assert_eq!(given.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(given.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"Delivered");
+
+    let malformed = app.oneshot(request(r#"{"reason":"#)).await.unwrap();
+    assert_eq!(malformed.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+async fn error_body(response: Response) -> serde_json::Value {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_malformed_requests_get_the_json_error_shape() {
+    let app = Router::new().route(
+        "/escrows/:id/lock",
+        post(
+            |Path(id): Path<i32>, Json(funds): Json<LockFunds>| async move {
+                format!("{} {}", id, funds.amount)
+            },
+        ),
+    );
+    let request = |uri: &str, body: &'static str| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let malformed = app
+        .clone()
+        .oneshot(request("/escrows/1/lock", r#"{"amount":"#))
+        .await
+        .unwrap();
+    assert_eq!(malformed.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = error_body(malformed).await;
+    assert_eq!(body["error"]["code"], "validation_failed");
+    assert_eq!(body["error"]["field"], "body");
+    assert!(body["error"]["message"].is_string());
+
+    let bad_id = app
+        .oneshot(request(
+            "/escrows/one/lock",
+            r#"{"amount":10,"asset_code":"XLM"}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(bad_id.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = error_body(bad_id).await;
+    assert_eq!(body["error"]["code"], "validation_failed");
+    assert_eq!(body["error"]["field"], "path");
+}
@@ -0,0 +1,190 @@
+use crate::errors::auth::AuthError;
+use crate::routes::auth::{authenticate, Authenticators};
+use crate::routes::body::MAX_BODY_BYTES;
+use crate::services::auth::{AuthUser, FirebaseAuth, KeySource, PublicKeys, Role};
+use crate::services::signing::{
+    sign, RequestVerifier, SignedRequest, DEFAULT_KEY_ID, KEY_ID_HEADER, MAX_CLOCK_SKEW_SECS,
+    NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    middleware,
+    routing::post,
+    Router,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const NOW: i64 = 1_700_000_000;
+const BODY: &[u8] = br#"{"reason":"Settled off-platform"}"#;
+
+fn verifier() -> RequestVerifier {
+    RequestVerifier::from_config(
+        "worker:old-secret, partner:new-secret",
+        Some("worker:admin"),
+    )
+    .unwrap()
+}
+
+/// Verifies a POST to `/escrows/1/release` signed with `secret` at `timestamp`.
+fn verify(
+    verifier: &RequestVerifier,
+    key_id: &str,
+    secret: &[u8],
+    nonce: &str,
+    timestamp: i64,
+) -> Result<AuthUser, AuthError> {
+    let signature = sign(secret, "POST", "/escrows/1/release", timestamp, nonce, BODY);
+    let timestamp = timestamp.to_string();
+    verifier.verify(
+        &SignedRequest {
+            key_id,
+            timestamp: &timestamp,
+            nonce,
+            signature: &signature,
+            method: "POST",
+            path: "/escrows/1/release",
+            body: BODY,
+        },
+        NOW,
+    )
+}
+
+#[test]
+fn test_signed_request_yields_service_user() {
+    let verifier = verifier();
+
+    let service = verify(&verifier, "worker", b"old-secret", "n-1", NOW).unwrap();
+    assert_eq!(service.uid, "service:worker");
+    assert_eq!(service.role, Role::Admin);
+
+    // Both keys stay valid while callers rotate, each with its own role
+    let partner = verify(&verifier, "partner", b"new-secret", "n-1", NOW).unwrap();
+    assert_eq!(partner.uid, "service:partner");
+    assert_eq!(partner.role, Role::default());
+}
+
+#[test]
+fn test_bad_signatures_are_rejected() {
+    let verifier = verifier();
+
+    for result in [
+        verify(&verifier, "worker", b"new-secret", "n-1", NOW),
+        verify(&verifier, "retired", b"old-secret", "n-2", NOW),
+        verify(
+            &verifier,
+            "worker",
+            b"old-secret",
+            "n-3",
+            NOW - MAX_CLOCK_SKEW_SECS - 1,
+        ),
+    ] {
+        assert!(matches!(result, Err(AuthError::InvalidSignature(_))));
+    }
+
+    // The signature covers the body
+    let signature = sign(
+        b"old-secret",
+        "POST",
+        "/escrows/1/release",
+        NOW,
+        "n-4",
+        BODY,
+    );
+    let now = NOW.to_string();
+    let tampered = SignedRequest {
+        key_id: "worker",
+        timestamp: &now,
+        nonce: "n-4",
+        signature: &signature,
+        method: "POST",
+        path: "/escrows/1/release",
+        body: b"{}",
+    };
+    assert!(matches!(
+        verifier.verify(&tampered, NOW),
+        Err(AuthError::InvalidSignature(_))
+    ));
+}
+
+#[test]
+fn test_nonces_cannot_be_replayed() {
+    let verifier = verifier();
+
+    assert!(verify(&verifier, "worker", b"old-secret", "n-1", NOW).is_ok());
+    assert!(matches!(
+        verify(&verifier, "worker", b"old-secret", "n-1", NOW),
+        Err(AuthError::InvalidSignature(_))
+    ));
+}
+
+#[test]
+fn test_secret_key_config() {
+    assert!(RequestVerifier::from_config("", None).is_err());
+    assert!(RequestVerifier::from_config("a:one,b", None).is_err());
+    assert!(RequestVerifier::from_config("a:one,a:two", None).is_err());
+    assert!(RequestVerifier::from_config("a:one", Some("b:admin")).is_err());
+    assert!(RequestVerifier::from_config("a:one", Some("a:root")).is_err());
+    assert!(RequestVerifier::from_config("a:one", Some("a:admin,a:lender")).is_err());
+
+    let single = RequestVerifier::from_config("only-secret", Some("default:arbiter")).unwrap();
+    let service = verify(&single, DEFAULT_KEY_ID, b"only-secret", "n-1", NOW).unwrap();
+    assert_eq!(service.role, Role::Arbiter);
+}
+
+struct NoKeys;
+
+impl KeySource for NoKeys {
+    fn fetch(&self) -> Result<PublicKeys, AuthError> {
+        Err(AuthError::KeysUnavailable("not in tests".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_layer_accepts_signed_requests() {
+    let app = Router::new()
+        .route(
+            "/escrows/:id/release",
+            post(|user: AuthUser, body: String| async move { format!("{} {}", user.uid, body) }),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            Authenticators {
+                firebase: Some(Arc::new(FirebaseAuth::new("unused", Arc::new(NoKeys)))),
+                services: Arc::new(verifier()),
+            },
+            authenticate,
+        ));
+
+    let now = chrono::Utc::now().timestamp();
+    let signed = |signature: String| {
+        Request::post("/escrows/1/release")
+            .header(KEY_ID_HEADER, "worker")
+            .header(TIMESTAMP_HEADER, now.to_string())
+            .header(NONCE_HEADER, "layer-nonce")
+            .header(SIGNATURE_HEADER, signature)
+            .body(Body::from(BODY))
+            .unwrap()
+    };
+
+    let forged = app.clone().oneshot(signed("00".repeat(32))).await.unwrap();
+    assert_eq!(forged.status(), StatusCode::UNAUTHORIZED);
+
+    let mut oversized = signed("00".repeat(32));
+    *oversized.body_mut() = Body::from(vec![b' '; MAX_BODY_BYTES + 1]);
+    let oversized = app.clone().oneshot(oversized).await.unwrap();
+    assert_eq!(oversized.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let signature = sign(
+        b"old-secret",
+        "POST",
+        "/escrows/1/release",
+        now,
+        "layer-nonce",
+        BODY,
+    );
+    let response = app.oneshot(signed(signature)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], [b"service:worker ", BODY].concat().as_slice());
+}
@@ -0,0 +1,170 @@
+use crate::errors::escrow::EscrowError;
+use crate::models::escrow::{Escrow, EscrowStatus};
+use crate::models::money::Money;
+use crate::models::stellar_address::StellarAddress;
+use crate::services::state_machine::EscrowTransition;
+
+fn address() -> StellarAddress {
+    StellarAddress::parse("GDHMW6QZOL73SHKG2JA3YHXFDHM46SS5ZRWEYF5BCYHX2C5TVO6KZBYL").unwrap()
+}
+
+fn escrow_in(status: EscrowStatus, locked_funds: i64) -> Escrow {
+    Escrow {
+        id: 1,
+        loan_amount: Money::from_units(1000),
+        loan_term: "12 months".to_string(),
+        purpose_of_loan: "Test loan".to_string(),
+        monthly_income: Money::from_units(5000),
+        status,
+        sender_address: address(),
+        recipient_address: address(),
+        locked_funds: Money::from_units(locked_funds),
+        escrow_account_id: Some("GESCROW".to_string()),
+        funding_tx_hash: None,
+        settlement_target: None,
+        settlement_tx_hash: None,
+        version: 1,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        funded_at: None,
+        released_at: None,
+        cancelled_at: None,
+        funding_deadline: None,
+        release_deadline: None,
+        expired_at: None,
+        settlement_recipient_amount: None,
+        asset_code: "XLM".to_string(),
+        asset_issuer: None,
+        deposit_tx_hash: None,
+        deposit_memo_id: 1,
+    }
+}
+
+#[test]
+fn test_pending_cannot_skip_funding() {
+    let result = EscrowTransition::between(EscrowStatus::Pending, EscrowStatus::Released);
+    assert!(matches!(
+        result,
+        Err(EscrowError::InvalidTransition {
+            from: EscrowStatus::Pending,
+            to: EscrowStatus::Released,
+        })
+    ));
+}
+
+#[test]
+fn test_legal_transitions_resolve() {
+    assert_eq!(
+        EscrowTransition::between(EscrowStatus::Pending, EscrowStatus::Funded).unwrap(),
+        EscrowTransition::Fund
+    );
+    assert_eq!(
+        EscrowTransition::between(EscrowStatus::Funded, EscrowStatus::Settling).unwrap(),
+        EscrowTransition::Settle
+    );
+    assert_eq!(
+        EscrowTransition::between(EscrowStatus::Settling, EscrowStatus::Cancelled).unwrap(),
+        EscrowTransition::Refund
+    );
+}
+
+#[test]
+fn test_terminal_statuses_have_no_exits() {
+    for from in [
+        EscrowStatus::Released,
+        EscrowStatus::Cancelled,
+        EscrowStatus::Expired,
+    ] {
+        for transition in [
+            EscrowTransition::Fund,
+            EscrowTransition::Expire,
+            EscrowTransition::Settle,
+            EscrowTransition::Release,
+            EscrowTransition::Cancel,
+            EscrowTransition::Refund,
+        ] {
+            assert!(transition.target(from).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_only_pending_escrows_expire() {
+    assert_eq!(
+        EscrowTransition::Expire
+            .target(EscrowStatus::Pending)
+            .unwrap(),
+        EscrowStatus::Expired
+    );
+    assert!(EscrowTransition::Expire
+        .target(EscrowStatus::Funded)
+        .is_err());
+}
+
+#[test]
+fn test_partially_released_escrow_pays_out_or_settles() {
+    let escrow = escrow_in(EscrowStatus::PartiallyReleased, 600);
+    assert_eq!(
+        EscrowTransition::ReleaseMilestone.apply(&escrow).unwrap(),
+        EscrowStatus::PartiallyReleased
+    );
+    assert_eq!(
+        EscrowTransition::Settle.apply(&escrow).unwrap(),
+        EscrowStatus::Settling
+    );
+    assert!(EscrowTransition::ReleaseMilestone
+        .target(EscrowStatus::Pending)
+        .is_err());
+}
+
+#[test]
+fn test_disputed_escrow_only_leaves_through_resolution() {
+    let escrow = escrow_in(EscrowStatus::Disputed, 1000);
+    assert!(EscrowTransition::Settle.apply(&escrow).is_err());
+    assert!(EscrowTransition::ReleaseMilestone.apply(&escrow).is_err());
+    assert_eq!(
+        EscrowTransition::Resolve.apply(&escrow).unwrap(),
+        EscrowStatus::Settling
+    );
+}
+
+#[test]
+fn test_funded_escrow_only_leaves_through_settlement() {
+    assert!(matches!(
+        EscrowTransition::between(EscrowStatus::Funded, EscrowStatus::Released),
+        Err(EscrowError::InvalidTransition { .. })
+    ));
+}
+
+#[test]
+fn test_settle_guard_requires_locked_funds() {
+    let escrow = escrow_in(EscrowStatus::Funded, 0);
+    assert!(matches!(
+        EscrowTransition::Settle.apply(&escrow),
+        Err(EscrowError::Validation {
+            field: "locked_funds",
+            ..
+        })
+    ));
+
+    let escrow = escrow_in(EscrowStatus::Funded, 1000);
+    assert_eq!(
+        EscrowTransition::Settle.apply(&escrow).unwrap(),
+        EscrowStatus::Settling
+    );
+}
+
+#[test]
+fn test_settlement_confirms_only_its_target() {
+    let mut escrow = escrow_in(EscrowStatus::Settling, 1000);
+    escrow.settlement_target = Some(EscrowStatus::Released);
+
+    assert_eq!(
+        EscrowTransition::Release.apply(&escrow).unwrap(),
+        EscrowStatus::Released
+    );
+    assert!(matches!(
+        EscrowTransition::Refund.apply(&escrow),
+        Err(EscrowError::Conflict(_))
+    ));
+}
@@ -0,0 +1,50 @@
+use crate::models::stellar_address::StellarAddress;
+use stellar_base::crypto::MuxedEd25519PublicKey;
+use stellar_base::KeyPair;
+
+const ACCOUNT: &str = "GDHMW6QZOL73SHKG2JA3YHXFDHM46SS5ZRWEYF5BCYHX2C5TVO6KZBYL";
+
+#[test]
+fn test_account_and_muxed_addresses_are_accepted() {
+    let account = StellarAddress::parse(ACCOUNT).unwrap();
+    assert!(!account.is_muxed());
+    assert_eq!(account.account_id().unwrap(), ACCOUNT);
+
+    let public_key = KeyPair::random().unwrap().public_key().clone();
+    let muxed_id = MuxedEd25519PublicKey::new(public_key.clone(), 42).account_id();
+    let muxed = StellarAddress::parse(&muxed_id).unwrap();
+    assert!(muxed.is_muxed());
+    assert_eq!(muxed.as_str(), muxed_id);
+    // Funds sit in the underlying account
+    assert_eq!(muxed.account_id().unwrap(), public_key.account_id());
+}
+
+#[test]
+fn test_invalid_addresses_are_rejected() {
+    let mut bad_checksum = ACCOUNT.to_string();
+    bad_checksum.replace_range(55.., "M");
+
+    for address in [
+        "",
+        "sender123",
+        &bad_checksum,
+        &ACCOUNT.to_lowercase(),
+        // Secret seeds are StrKeys too, but not addresses
+        "SBK2VIYYSVG76E7VC3QHYARNFLY2EAQXDHRC7BMXBBGIFG74ARPRMNQM",
+        &ACCOUNT[..55],
+    ] {
+        assert!(StellarAddress::parse(address).is_err(), "{}", address);
+    }
+}
+
+#[test]
+fn test_addresses_are_validated_when_deserialized() {
+    let address: StellarAddress = serde_json::from_str(&format!("\"{}\"", ACCOUNT)).unwrap();
+    assert_eq!(
+        serde_json::to_string(&address).unwrap(),
+        format!("\"{}\"", ACCOUNT)
+    );
+
+    let err = serde_json::from_str::<StellarAddress>("\"sender123\"").unwrap_err();
+    assert!(err.to_string().contains("not a Stellar account"));
+}
@@ -0,0 +1,160 @@
+use crate::models::money::Money;
+use crate::services::stellar::{
+    build_escrow_account_transaction, build_settlement_transaction, transaction_hash,
+    StellarConfig, SupportedAsset, BASE_RESERVE_STROOPS, ESCROW_ACCOUNT_RESERVE_STROOPS,
+};
+use stellar_base::{Asset, KeyPair, Network, Operation};
+
+const ISSUER: &str = "GDHMW6QZOL73SHKG2JA3YHXFDHM46SS5ZRWEYF5BCYHX2C5TVO6KZBYL";
+
+fn test_config() -> StellarConfig {
+    StellarConfig {
+        network: Network::new_test(),
+        horizon_url: "https://horizon-testnet.stellar.org".to_string(),
+        escrow_keypair: KeyPair::random().unwrap(),
+        supported_assets: vec![SupportedAsset::native()],
+    }
+}
+
+#[test]
+fn test_escrow_account_transaction_locks_funds() {
+    let config = test_config();
+    let escrow_account = KeyPair::random().unwrap();
+
+    let transaction = build_escrow_account_transaction(
+        &config,
+        42,
+        7,
+        &escrow_account,
+        &Asset::new_native(),
+        Money::from_units(10_000_000),
+    )
+    .unwrap();
+
+    assert_eq!(*transaction.sequence(), 42);
+    assert_eq!(
+        transaction.source_account().account_id(),
+        config.treasury().account_id()
+    );
+    // Signed by the treasury and, once, by the new escrow account
+    assert_eq!(transaction.signatures().len(), 2);
+
+    match &transaction.operations()[..] {
+        [Operation::CreateAccount(create), Operation::SetOptions(options)] => {
+            assert_eq!(create.destination(), escrow_account.public_key());
+            assert_eq!(
+                create.starting_balance().to_i64(),
+                10_000_000 + ESCROW_ACCOUNT_RESERVE_STROOPS
+            );
+            assert_eq!(*options.master_weight(), Some(0));
+            assert!(options.signer().is_some());
+        }
+        operations => panic!("unexpected operations: {:?}", operations),
+    }
+
+    assert_eq!(
+        transaction_hash(&config.network, &transaction)
+            .unwrap()
+            .len(),
+        64
+    );
+}
+
+#[test]
+fn test_settlement_transaction_pays_out_and_closes_account() {
+    let config = test_config();
+    let escrow_account = KeyPair::random().unwrap();
+    let recipient = KeyPair::random().unwrap();
+
+    let transaction = build_settlement_transaction(
+        &config,
+        43,
+        7,
+        escrow_account.public_key(),
+        &Asset::new_native(),
+        &[(
+            &recipient.public_key().clone().into(),
+            Money::from_units(10_000_000),
+        )],
+    )
+    .unwrap();
+
+    assert_eq!(*transaction.sequence(), 43);
+    // The escrow account's master key is disabled, only the treasury signs
+    assert_eq!(transaction.signatures().len(), 1);
+
+    match &transaction.operations()[..] {
+        [Operation::Payment(payment), Operation::AccountMerge(merge)] => {
+            assert_eq!(
+                payment.destination().account_id(),
+                recipient.public_key().account_id()
+            );
+            assert_eq!(payment.amount().to_i64(), 10_000_000);
+            assert_eq!(
+                merge.destination().account_id(),
+                config.treasury().account_id()
+            );
+        }
+        operations => panic!("unexpected operations: {:?}", operations),
+    }
+}
+
+#[test]
+fn test_issued_asset_escrow_account_trusts_asset() {
+    let config = test_config();
+    let escrow_account = KeyPair::random().unwrap();
+    let usdc = SupportedAsset::parse(&format!("USDC:{}", ISSUER))
+        .unwrap()
+        .to_stellar()
+        .unwrap();
+
+    let transaction = build_escrow_account_transaction(
+        &config,
+        42,
+        7,
+        &escrow_account,
+        &usdc,
+        Money::from_units(10_000_000),
+    )
+    .unwrap();
+
+    match &transaction.operations()[..] {
+        [Operation::CreateAccount(create), Operation::ChangeTrust(trust), Operation::Payment(deposit), Operation::SetOptions(_)] =>
+        {
+            assert_eq!(
+                create.starting_balance().to_i64(),
+                ESCROW_ACCOUNT_RESERVE_STROOPS + BASE_RESERVE_STROOPS
+            );
+            assert_eq!(*trust.asset(), usdc);
+            assert_eq!(*deposit.asset(), usdc);
+            assert_eq!(deposit.amount().to_i64(), 10_000_000);
+        }
+        operations => panic!("unexpected operations: {:?}", operations),
+    }
+}
+
+#[test]
+fn test_supported_assets_parse() {
+    let assets = SupportedAsset::parse_list(&format!("XLM, USDC:{}:2", ISSUER)).unwrap();
+    assert_eq!(assets[0], SupportedAsset::native());
+    assert_eq!(assets[1].code, "USDC");
+    assert_eq!(assets[1].issuer.as_deref(), Some(ISSUER));
+    assert_eq!(assets[1].unit(), 100_000);
+
+    assert!(assets[1]
+        .check_precision("amount", Money::from_units(1_500_000))
+        .is_ok());
+    assert!(assets[1]
+        .check_precision("amount", Money::from_units(1_500_001))
+        .is_err());
+
+    for invalid in [
+        "",
+        "USDC",
+        "USDC:GABC",
+        &format!("USDC:{}:8", ISSUER),
+        &format!("TOOLONGASSETCODE:{}", ISSUER),
+    ] {
+        assert!(SupportedAsset::parse_list(invalid).is_err(), "{}", invalid);
+    }
+}